@@ -0,0 +1,601 @@
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use clap::{Args, Parser, Subcommand};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+
+use crate::data::config::DataConfig;
+use crate::data::export::{self, ExportFormat, ExportOptions, TimestampFormat};
+use crate::data::layout;
+use crate::data::provider::{DataProvider, SymbolsProvider, DEFAULT_TIMEFRAME};
+use crate::data::store::DataStore;
+use crate::data::symbols::{default_initdate, SymbolFilter};
+use crate::data::timeframe::Timeframe;
+use crate::data::{AssetCategory, Column, Symbol};
+use crate::event::handler::EventHandler;
+use crate::event::sources::{EventSource, EventSourceOptions, StoreEventSource};
+use crate::extensions::datetime;
+use crate::signals::ema_signals::EmaCrossSignal;
+use crate::signals::{Signal, SignalProcessor};
+
+const DEFAULT_CHANNEL_SIZE: usize = 100;
+
+#[derive(Parser)]
+#[command(
+    name = "qrust",
+    version,
+    about = "Syncs, stores and backtests market data"
+)]
+pub struct Cli {
+    /// Config file to load instead of ./Config.toml
+    #[arg(long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Asset category: spot, um or cm
+    #[arg(long, short = 'c', global = true, default_value = "um")]
+    pub category: AssetCategory,
+    /// Logs debug messages
+    #[arg(long, short, global = true)]
+    pub verbose: bool,
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Fetches the archives of the symbols and updates their stores
+    Sync(SyncArgs),
+    #[command(subcommand)]
+    Symbols(SymbolsCommand),
+    #[command(subcommand)]
+    Store(StoreCommand),
+    /// Writes the bars of a symbol to a CSV, Arrow IPC, NDJSON or parquet file
+    Export(ExportArgs),
+    /// Runs a SQL query over the stores, or reads queries from stdin if none is given
+    Query(QueryArgs),
+    /// Runs the signals of a backtest config over the stored bars
+    Backtest {
+        #[arg(value_name = "CONFIG")]
+        path: PathBuf,
+    },
+}
+
+#[derive(Args)]
+pub struct SyncArgs {
+    /// Comma separated symbols, synced even if the symbol filter would skip them.
+    /// All symbols passing the symbol filter if none
+    #[arg(long, value_delimiter = ',')]
+    pub symbols: Vec<String>,
+    /// Skips the archives before the date, e.g. 2021-01 or 2021-01-15
+    #[arg(long, value_parser = datetime::parse_utc)]
+    pub since: Option<DateTime<Utc>>,
+}
+
+/// Lists symbols
+#[derive(Subcommand)]
+pub enum SymbolsCommand {
+    /// Lists the symbols of the exchange, delisted ones included
+    List {
+        /// Reads the symbols cache instead of fetching the symbols
+        #[arg(long)]
+        cached: bool,
+        /// Only lists the symbols that were tradable at the date
+        #[arg(long, value_parser = datetime::parse_utc)]
+        at: Option<DateTime<Utc>>,
+    },
+}
+
+/// Inspects the stores
+#[derive(Subcommand)]
+pub enum StoreCommand {
+    /// Lists the stored symbols with their timeframes
+    List,
+    /// Shows the metadata and last bars of a timeframe
+    Inspect {
+        symbol: String,
+        timeframe: Timeframe,
+        /// Number of bars to show
+        #[arg(long, default_value_t = 5)]
+        tail: usize,
+    },
+    /// Verifies the raw archives against their checksums and reports gaps
+    Verify {
+        /// Comma separated symbols, all stored symbols if none
+        #[arg(long, value_delimiter = ',')]
+        symbols: Vec<String>,
+    },
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    pub symbol: String,
+    pub timeframe: Timeframe,
+    /// First open time to export, e.g. 2021-01
+    #[arg(long, value_parser = datetime::parse_utc)]
+    pub from: Option<DateTime<Utc>>,
+    /// Open time to export up to, exclusive
+    #[arg(long, value_parser = datetime::parse_utc)]
+    pub to: Option<DateTime<Utc>>,
+    /// Comma separated columns, all columns if none
+    #[arg(long, value_delimiter = ',')]
+    pub columns: Vec<String>,
+    /// csv, ipc, feather, ndjson or parquet, guessed from the output file if not given
+    #[arg(long, short)]
+    pub format: Option<ExportFormat>,
+    /// How open and close times are written: native, ms or a strftime format
+    #[arg(long, default_value = "native")]
+    pub timestamps: TimestampFormat,
+    #[arg(long, short)]
+    pub output: PathBuf,
+}
+
+#[derive(Args)]
+pub struct QueryArgs {
+    /// A single statement, e.g. "SELECT * FROM BTCUSDT_1h LIMIT 5"
+    pub sql: Option<String>,
+    /// Writes the result to the file, in the format of its extension
+    #[arg(long, short)]
+    pub output: Option<PathBuf>,
+}
+
+/// A backtest read from TOML, e.g.
+/// `symbol = "BTCUSDT"`, `timeframe = "1h"` and
+/// `[[signals]]` tables like `type = "ema_cross"`, `fast = 10`, `slow = 20`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct BacktestConfig {
+    symbol: String,
+    timeframe: Timeframe,
+    /// Bars per event, five times the longest signal period if not given.
+    lookback: Option<usize>,
+    signals: Vec<SignalConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum SignalConfig {
+    EmaCross { fast: usize, slow: usize },
+}
+
+impl SignalConfig {
+    fn name(&self) -> String {
+        match self {
+            SignalConfig::EmaCross { fast, slow } => format!("ema_cross({}, {})", fast, slow),
+        }
+    }
+
+    fn processor(&self) -> Result<Box<dyn SignalProcessor>> {
+        match *self {
+            SignalConfig::EmaCross { fast, slow } => {
+                if fast == 0 || slow == 0 {
+                    return Err(anyhow!("EMA periods of {} must be positive", self.name()));
+                }
+                Ok(Box::new(EmaCrossSignal::new(fast, slow)))
+            }
+        }
+    }
+}
+
+pub async fn run(cli: Cli) -> Result<ExitCode> {
+    let asset_cat = cli.category;
+    let config = match &cli.config {
+        Some(path) => DataConfig::load_from(path, asset_cat.clone())?,
+        None => DataConfig::load(asset_cat.clone())?,
+    };
+    layout::migrate_legacy_layout(&config)?;
+
+    match cli.command {
+        Command::Sync(args) => sync(config, asset_cat, args).await,
+        Command::Symbols(SymbolsCommand::List { cached, at }) => {
+            list_symbols(config, asset_cat, cached, at).await
+        }
+        Command::Store(StoreCommand::List) => list_stores(config),
+        Command::Store(StoreCommand::Inspect {
+            symbol,
+            timeframe,
+            tail,
+        }) => inspect(config, &symbol.to_uppercase(), &timeframe, tail),
+        Command::Store(StoreCommand::Verify { symbols }) => verify(config, asset_cat, symbols),
+        Command::Export(args) => export(config, args),
+        Command::Query(args) => query(config, args),
+        Command::Backtest { path } => backtest(config, &path).await,
+    }
+}
+
+async fn sync(
+    mut config: DataConfig,
+    asset_cat: AssetCategory,
+    args: SyncArgs,
+) -> Result<ExitCode> {
+    let provider = SymbolsProvider::new(config.clone(), asset_cat);
+    let mut symbols = provider.get().await?;
+    if !args.symbols.is_empty() {
+        // Symbols asked for by name are synced whatever the symbol filter says
+        config.symbol_filter = SymbolFilter::new();
+        symbols = args
+            .symbols
+            .iter()
+            .map(|name| name.to_uppercase())
+            .map(|name| {
+                symbols
+                    .iter()
+                    .find(|symbol| symbol.name == name)
+                    .cloned()
+                    .unwrap_or_else(|| {
+                        log::warn!("Unknown symbol {}, syncing all its archives", name);
+                        Symbol::new(&name, default_initdate())
+                    })
+            })
+            .collect();
+    }
+    if let Some(since) = args.since {
+        for symbol in symbols.iter_mut() {
+            symbol.initdate = symbol.initdate.max(since);
+        }
+    }
+
+    let failed = DataStore::new_arc(config).sync(symbols).await;
+    if !failed.is_empty() {
+        log::error!("Failed to sync {}", failed.join(", "));
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn list_symbols(
+    config: DataConfig,
+    asset_cat: AssetCategory,
+    cached: bool,
+    at: Option<DateTime<Utc>>,
+) -> Result<ExitCode> {
+    let provider = SymbolsProvider::new(config, asset_cat);
+    let symbols = match (at, cached) {
+        (Some(at), _) => provider.universe_at(&at)?,
+        (None, true) => provider.cached()?,
+        (None, false) => provider.get().await?,
+    };
+
+    for symbol in symbols {
+        let status = symbol
+            .info
+            .as_ref()
+            .map(|info| info.status.as_str())
+            .unwrap_or("-");
+        let delistdate = symbol
+            .delistdate
+            .map(|delistdate| delistdate.date_naive().to_string())
+            .unwrap_or("-".to_string());
+        println!(
+            "{:<20} {:<16} {} {}",
+            symbol.name,
+            status,
+            symbol.initdate.date_naive(),
+            delistdate
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn list_stores(config: DataConfig) -> Result<ExitCode> {
+    let exchange = config.exchange;
+    let asset_cat = config.asset_cat.as_str();
+    let catalog = DataStore::new(config).catalog()?;
+    for metadata in catalog
+        .iter()
+        .filter(|metadata| metadata.exchange == exchange && metadata.asset_cat == asset_cat)
+    {
+        let timeframes = metadata.timeframes.keys().cloned().collect::<Vec<_>>();
+        let (first, last) = metadata
+            .timeframes
+            .get(DEFAULT_TIMEFRAME)
+            .map(|tf_metadata| (tf_metadata.first_open_time, tf_metadata.last_open_time))
+            .unwrap_or_default();
+        println!(
+            "{:<20} {} - {} gaps: {} [{}]",
+            metadata.symbol,
+            first
+                .map(|time| time.to_string())
+                .unwrap_or("-".to_string()),
+            last.map(|time| time.to_string()).unwrap_or("-".to_string()),
+            metadata.gap_count,
+            timeframes.join(", ")
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn inspect(
+    config: DataConfig,
+    symbol: &str,
+    timeframe: &Timeframe,
+    tail: usize,
+) -> Result<ExitCode> {
+    let store = DataStore::new(config);
+    let df = store.load(symbol, timeframe).ok_or(anyhow!(
+        "No {} bars stored for {}",
+        timeframe,
+        symbol
+    ))?;
+
+    if let Some(metadata) = store.metadata(symbol) {
+        println!("Last sync: {}", metadata.last_sync);
+        println!("Gaps: {}", metadata.gap_count);
+        println!("Sources: {}", metadata.sources.len());
+    }
+    println!("Bars: {}", df.height());
+    if df.height() > 0 {
+        let open_times = df.column(Column::OPEN_TIME)?;
+        let (first, last) = (open_times.get(0)?, open_times.get(df.height() - 1)?);
+        println!("Open times: {} - {}", first, last);
+    }
+    println!("{}", df.tail(Some(tail)));
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify(config: DataConfig, asset_cat: AssetCategory, symbols: Vec<String>) -> Result<ExitCode> {
+    let store = DataStore::new(config.clone());
+    let symbols = if symbols.is_empty() {
+        store
+            .catalog()?
+            .into_iter()
+            .filter(|metadata| {
+                metadata.exchange == config.exchange && metadata.asset_cat == asset_cat.as_str()
+            })
+            .map(|metadata| metadata.symbol)
+            .collect()
+    } else {
+        symbols
+            .iter()
+            .map(|name| name.to_uppercase())
+            .collect::<Vec<_>>()
+    };
+
+    let provider = DataProvider::new(config, asset_cat);
+    let mut is_valid = true;
+    for symbol in symbols {
+        let invalid = provider.verify(&symbol)?;
+        let gaps = store
+            .gap_report(&symbol)
+            .map(|report| report.gaps.len())
+            .unwrap_or_default();
+        println!("{:<20} invalid: {} gaps: {}", symbol, invalid.len(), gaps);
+        for path in invalid.iter() {
+            println!("  {}", path.display());
+        }
+        is_valid &= invalid.is_empty();
+    }
+
+    if !is_valid {
+        return Ok(ExitCode::FAILURE);
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn export(config: DataConfig, args: ExportArgs) -> Result<ExitCode> {
+    let symbol = args.symbol.to_uppercase();
+    let format = args
+        .format
+        .or_else(|| ExportFormat::from_path(&args.output))
+        .ok_or(anyhow!("No format given for {:?}", args.output))?;
+    let mut options = ExportOptions::new(format)
+        .with_range(args.from, args.to)
+        .with_timestamps(args.timestamps);
+    if !args.columns.is_empty() {
+        let columns = args.columns.iter().map(|c| c.as_str()).collect::<Vec<_>>();
+        options = options.with_columns(&columns);
+    }
+
+    let rows = DataStore::new(config).export(&symbol, &args.timeframe, &options, &args.output)?;
+    log::info!("Exported {} bars to {:?}", rows, args.output);
+    Ok(ExitCode::SUCCESS)
+}
+
+fn query(config: DataConfig, args: QueryArgs) -> Result<ExitCode> {
+    let store = DataStore::new(config);
+    let Some(sql) = args.sql else {
+        return repl(&store);
+    };
+
+    let df = store.query(&sql)?;
+    match args.output {
+        Some(path) => {
+            let format =
+                ExportFormat::from_path(&path).ok_or(anyhow!("Unknown format of {:?}", path))?;
+            export::write_frame(df, &ExportOptions::new(format), &path)?;
+        }
+        None => println!("{}", df),
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Runs the statements read from stdin, each ending with `;`. `.tables`
+/// lists the tables and `.exit` quits, as does the end of the input.
+fn repl(store: &DataStore) -> Result<ExitCode> {
+    const PROMPT: &str = "qrust> ";
+    let mut context = store.sql_context()?;
+    let stdin = io::stdin();
+    let is_terminal = stdin.is_terminal();
+    let prompt = || {
+        if is_terminal {
+            eprint!("{}", PROMPT);
+        }
+    };
+
+    prompt();
+    let mut statement = String::new();
+    for line in stdin.lines() {
+        let line = line?;
+        match line.trim() {
+            ".exit" if statement.is_empty() => break,
+            ".tables" if statement.is_empty() => println!("{}", context.get_tables().join("\n")),
+            _ => statement.push_str(&format!("{}\n", line)),
+        }
+        if !statement.trim_end().ends_with(';') {
+            if statement.trim().is_empty() {
+                prompt();
+            }
+            continue;
+        }
+
+        let sql = std::mem::take(&mut statement);
+        match context
+            .execute(sql.trim().trim_end_matches(';'))
+            .and_then(|lf| lf.collect())
+        {
+            Ok(df) => println!("{}", df),
+            Err(e) => log::error!("{}", e),
+        }
+        prompt();
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+async fn backtest(config: DataConfig, path: &Path) -> Result<ExitCode> {
+    let content = std::fs::read_to_string(path)?;
+    let backtest: BacktestConfig = toml::from_str(&content)?;
+    let signal_procs = backtest
+        .signals
+        .iter()
+        .map(|signal| signal.processor())
+        .collect::<Result<Vec<_>>>()?;
+    let lookback = backtest.lookback.unwrap_or_else(|| {
+        let threshold = signal_procs.iter().map(|proc| proc.get_threshold()).max();
+        threshold.unwrap_or_default() * 5
+    });
+
+    let (sender, receiver) = mpsc::channel(DEFAULT_CHANNEL_SIZE);
+    let options = EventSourceOptions {
+        symbol: backtest.symbol.to_uppercase(),
+        timeframe: backtest.timeframe,
+    };
+    let source = StoreEventSource::new(config, options, sender);
+    let mut handler = EventHandler::new(signal_procs, receiver);
+
+    let source_task = tokio::spawn(async move { source.start(lookback).await });
+    let signals = handler.listen().await;
+    source_task.await??;
+
+    for (signal, signals) in backtest.signals.iter().zip(signals) {
+        let count_of = |kind: Signal| signals.iter().filter(|s| **s == kind).count();
+        // A change between buy and sell, holds in between not counting
+        let changes = signals
+            .iter()
+            .filter(|s| **s != Signal::Hold)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .filter(|pair| pair[0] != pair[1])
+            .count();
+        println!(
+            "{:<20} events: {} buy: {} sell: {} hold: {} changes: {}",
+            signal.name(),
+            signals.len(),
+            count_of(Signal::Buy),
+            count_of(Signal::Sell),
+            count_of(Signal::Hold),
+            changes
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli() {
+        Cli::command().debug_assert();
+
+        let cli = Cli::try_parse_from([
+            "qrust",
+            "sync",
+            "--category",
+            "um",
+            "--symbols",
+            "BTCUSDT,ETHUSDT",
+            "--since",
+            "2021-01",
+        ])
+        .unwrap();
+        assert!(matches!(cli.category, AssetCategory::Usdm));
+        match cli.command {
+            Command::Sync(args) => {
+                assert_eq!(vec!["BTCUSDT", "ETHUSDT"], args.symbols);
+                assert_eq!(Some(datetime::create_utc(2021, 1, 1)), args.since);
+            }
+            _ => panic!("Expected sync"),
+        }
+
+        let cli = Cli::try_parse_from(["qrust", "store", "inspect", "BTCUSDT", "1h", "-c", "spot"])
+            .unwrap();
+        assert!(matches!(cli.category, AssetCategory::Spot));
+        assert!(matches!(
+            cli.command,
+            Command::Store(StoreCommand::Inspect { timeframe, tail: 5, .. })
+                if timeframe == Timeframe::parse("1h").unwrap()
+        ));
+
+        let cli = Cli::try_parse_from([
+            "qrust",
+            "export",
+            "BTCUSDT",
+            "1d",
+            "--columns",
+            "open_time,close",
+            "--timestamps",
+            "%Y-%m-%d",
+            "-o",
+            "btc.feather",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Export(args) => {
+                assert_eq!(vec!["open_time", "close"], args.columns);
+                assert_eq!(None, args.format);
+                assert_eq!(
+                    TimestampFormat::Strftime("%Y-%m-%d".to_string()),
+                    args.timestamps
+                );
+            }
+            _ => panic!("Expected export"),
+        }
+
+        let cli = Cli::try_parse_from(["qrust", "query", "SELECT * FROM BTCUSDT_1h"]).unwrap();
+        assert!(matches!(
+            cli.command,
+            Command::Query(QueryArgs {
+                sql: Some(_),
+                output: None
+            })
+        ));
+
+        assert!(Cli::try_parse_from(["qrust", "sync", "--category", "futures"]).is_err());
+        assert!(Cli::try_parse_from(["qrust", "store", "inspect", "BTCUSDT", "1x"]).is_err());
+    }
+
+    #[test]
+    fn test_backtest_config() {
+        let backtest: BacktestConfig = toml::from_str(
+            r#"
+            symbol = "BTCUSDT"
+            timeframe = "4h"
+
+            [[signals]]
+            type = "ema_cross"
+            fast = 10
+            slow = 20
+            "#,
+        )
+        .unwrap();
+        assert_eq!(None, backtest.lookback);
+        assert_eq!("ema_cross(10, 20)", backtest.signals[0].name());
+        assert_eq!(20, backtest.signals[0].processor().unwrap().get_threshold());
+
+        let signal = SignalConfig::EmaCross { fast: 0, slow: 20 };
+        assert!(signal.processor().is_err());
+    }
+}
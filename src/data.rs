@@ -1,9 +1,27 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error};
 use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 
+pub mod bars;
+pub mod checksum;
 pub mod config;
+pub mod dataset;
+pub mod derived;
+pub mod exchange;
+pub mod export;
+pub mod gaps;
+pub mod layout;
+pub mod metadata;
 pub mod provider;
+pub mod retry;
 pub mod sanitizer;
 pub mod store;
+pub mod symbols;
+pub mod timeframe;
+
+use symbols::SymbolInfo;
 
 #[derive(Clone)]
 pub enum AssetCategory {
@@ -30,10 +48,54 @@ impl AssetCategory {
     }
 }
 
-#[derive(Debug, Clone)]
+impl FromStr for AssetCategory {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<AssetCategory, Error> {
+        match value {
+            "spot" | "um" | "cm" => Ok(AssetCategory::as_value(value)),
+            _ => Err(anyhow!(
+                "Invalid asset category '{}', expected spot, um or cm",
+                value
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Symbol {
     pub name: String,
     pub initdate: DateTime<Utc>,
+    /// The end of the last stored bar of a delisted symbol, once synced.
+    #[serde(default)]
+    pub delistdate: Option<DateTime<Utc>>,
+    pub info: Option<SymbolInfo>,
+}
+
+impl Symbol {
+    pub fn new(name: &str, initdate: DateTime<Utc>) -> Symbol {
+        Symbol {
+            name: name.to_string(),
+            initdate,
+            delistdate: None,
+            info: None,
+        }
+    }
+
+    pub fn is_delisted(&self) -> bool {
+        self.delistdate.is_some() || self.info.as_ref().is_some_and(|info| info.is_delisted())
+    }
+
+    /// Whether the symbol was tradable at the date. Delisted symbols whose
+    /// delisting date is not known yet never count as listed, as their
+    /// listing dates are not known either.
+    pub fn is_listed_at(&self, date: &DateTime<Utc>) -> bool {
+        let is_listed = match self.delistdate {
+            Some(delistdate) => *date < delistdate,
+            None => !self.is_delisted(),
+        };
+        self.initdate <= *date && is_listed
+    }
 }
 
 pub struct Column;
@@ -44,10 +106,30 @@ impl Column {
     pub const LOW: &'static str = "low";
     pub const CLOSE: &'static str = "close";
     pub const VOLUME: &'static str = "volume";
+    pub const CLOSE_TIME: &'static str = "close_time";
     pub const QUOTE_VOLUME: &'static str = "quote_volume";
     pub const COUNT: &'static str = "count";
     pub const TAKER_BUY_VOLUME: &'static str = "taker_buy_volume";
     pub const TAKER_BUY_QUOTE_VOLUME: &'static str = "taker_buy_quote_volume";
+    pub const IGNORE: &'static str = "ignore";
     pub const LOG_RETURNS: &'static str = "log_returns";
     pub const CUM_RETURNS: &'static str = "cum_returns";
+    pub const SIMPLE_RETURNS: &'static str = "simple_returns";
+    pub const LOG_VOLUME: &'static str = "log_volume";
+    pub const TYPICAL_PRICE: &'static str = "typical_price";
+    pub const VWAP: &'static str = "vwap";
+    pub const FUNDING_RATE: &'static str = "funding_rate";
+}
+
+/// Columns of the aggTrades archives.
+pub struct TradeColumn;
+impl TradeColumn {
+    pub const AGG_TRADE_ID: &'static str = "agg_trade_id";
+    pub const PRICE: &'static str = "price";
+    pub const QUANTITY: &'static str = "quantity";
+    pub const FIRST_TRADE_ID: &'static str = "first_trade_id";
+    pub const LAST_TRADE_ID: &'static str = "last_trade_id";
+    pub const TRANSACT_TIME: &'static str = "transact_time";
+    pub const IS_BUYER_MAKER: &'static str = "is_buyer_maker";
+    pub const IS_BEST_MATCH: &'static str = "is_best_match";
 }
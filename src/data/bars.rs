@@ -0,0 +1,425 @@
+use anyhow::Result;
+use polars::lazy::dsl::*;
+use polars::prelude::*;
+
+use crate::data::{Column, TradeColumn};
+
+const QUOTE_QUANTITY: &str = "quote_quantity";
+const BAR_ID: &str = "bar_id";
+
+/// Information-driven bars, which close once enough activity accumulated
+/// instead of after a fixed amount of time.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BarType {
+    /// Closes after the given base volume was traded.
+    Volume(f64),
+    /// Closes after the given quote volume was traded.
+    Dollar(f64),
+    /// Closes after the given number of trades.
+    Tick(u64),
+    /// Closes once the signed trade count exceeds its expected imbalance.
+    TickImbalance { expected_rows: usize, alpha: f64 },
+    /// Closes once the signed volume exceeds its expected imbalance.
+    VolumeImbalance { expected_rows: usize, alpha: f64 },
+}
+
+impl BarType {
+    /// Name of the store the bars are written to, e.g. `volume-1000` for bars
+    /// built from the 1m store and `volume-1000-trades` for bars built from trades.
+    pub fn store_name(&self, source: BarSource) -> String {
+        let name = match self {
+            BarType::Volume(volume) => format!("volume-{}", volume),
+            BarType::Dollar(quote_volume) => format!("dollar-{}", quote_volume),
+            BarType::Tick(count) => format!("tick-{}", count),
+            BarType::TickImbalance {
+                expected_rows,
+                alpha,
+            } => format!("tick_imbalance-{}-{}", expected_rows, alpha),
+            BarType::VolumeImbalance {
+                expected_rows,
+                alpha,
+            } => format!("volume_imbalance-{}-{}", expected_rows, alpha),
+        };
+        match source {
+            BarSource::Klines => name,
+            BarSource::Trades => format!("{}-trades", name),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BarSource {
+    Klines,
+    Trades,
+}
+
+/// Builds bars from aggregated trades instead of the exchange's klines.
+pub struct BarBuilder;
+
+impl BarBuilder {
+    pub fn new() -> BarBuilder {
+        BarBuilder {}
+    }
+
+    /// Aggregates the trades into time bars of the given duration, e.g. `1s`,
+    /// with the kline columns. Taker buys are the trades where the buyer was
+    /// not the maker. Intervals without any trade produce no bar.
+    pub fn time_bars(&self, trades: LazyFrame, every: &str) -> LazyFrame {
+        let duration = Duration::parse(every);
+        let is_taker_buy = col(TradeColumn::IS_BUYER_MAKER).not();
+
+        trades
+            .with_column(
+                (col(TradeColumn::PRICE) * col(TradeColumn::QUANTITY)).alias(QUOTE_QUANTITY),
+            )
+            .sort(TradeColumn::TRANSACT_TIME, SortOptions::default())
+            .groupby_dynamic(
+                col(TradeColumn::TRANSACT_TIME),
+                [],
+                DynamicGroupOptions {
+                    index_column: TradeColumn::TRANSACT_TIME.into(),
+                    every: duration,
+                    period: duration,
+                    offset: Duration::parse("0s"),
+                    truncate: true,
+                    include_boundaries: false,
+                    closed_window: ClosedWindow::Left,
+                    start_by: Default::default(),
+                    check_sorted: false,
+                },
+            )
+            .agg([
+                col(TradeColumn::PRICE).first().alias(Column::OPEN),
+                col(TradeColumn::PRICE).max().alias(Column::HIGH),
+                col(TradeColumn::PRICE).min().alias(Column::LOW),
+                col(TradeColumn::PRICE).last().alias(Column::CLOSE),
+                col(TradeColumn::QUANTITY).sum().alias(Column::VOLUME),
+                col(QUOTE_QUANTITY).sum().alias(Column::QUOTE_VOLUME),
+                (col(TradeColumn::LAST_TRADE_ID) - col(TradeColumn::FIRST_TRADE_ID) + lit(1))
+                    .sum()
+                    .alias(Column::COUNT),
+                col(TradeColumn::QUANTITY)
+                    .filter(is_taker_buy.clone())
+                    .sum()
+                    .alias(Column::TAKER_BUY_VOLUME),
+                col(QUOTE_QUANTITY)
+                    .filter(is_taker_buy)
+                    .sum()
+                    .alias(Column::TAKER_BUY_QUOTE_VOLUME),
+            ])
+            .rename([TradeColumn::TRANSACT_TIME], [Column::OPEN_TIME])
+            .with_columns([
+                (col(Column::OPEN_TIME).cast(DataType::Int64) + lit(duration.duration_ms() - 1))
+                    .alias(Column::CLOSE_TIME),
+                lit(0i64).alias(Column::IGNORE),
+            ])
+            .select([
+                col(Column::OPEN_TIME),
+                col(Column::OPEN),
+                col(Column::HIGH),
+                col(Column::LOW),
+                col(Column::CLOSE),
+                col(Column::VOLUME),
+                col(Column::CLOSE_TIME),
+                col(Column::QUOTE_VOLUME),
+                col(Column::COUNT),
+                col(Column::TAKER_BUY_VOLUME),
+                col(Column::TAKER_BUY_QUOTE_VOLUME),
+                col(Column::IGNORE),
+            ])
+    }
+}
+
+impl BarBuilder {
+    /// Keeps the expected imbalance bar length within a tenth and ten times of
+    /// its initial value, as the estimate otherwise tends to collapse or explode.
+    const MAX_EXPECTED_ROWS_FACTOR: f64 = 10.0;
+
+    /// Turns every trade into a one-trade bar with the kline columns,
+    /// so trades and 1m bars can be sampled the same way.
+    pub fn trade_rows(&self, trades: LazyFrame) -> LazyFrame {
+        let quote_quantity = col(TradeColumn::PRICE) * col(TradeColumn::QUANTITY);
+        let is_taker_buy = col(TradeColumn::IS_BUYER_MAKER).not();
+
+        trades
+            .sort(TradeColumn::TRANSACT_TIME, SortOptions::default())
+            .select([
+                col(TradeColumn::TRANSACT_TIME).alias(Column::OPEN_TIME),
+                col(TradeColumn::PRICE).alias(Column::OPEN),
+                col(TradeColumn::PRICE).alias(Column::HIGH),
+                col(TradeColumn::PRICE).alias(Column::LOW),
+                col(TradeColumn::PRICE).alias(Column::CLOSE),
+                col(TradeColumn::QUANTITY).alias(Column::VOLUME),
+                col(TradeColumn::TRANSACT_TIME)
+                    .cast(DataType::Int64)
+                    .alias(Column::CLOSE_TIME),
+                quote_quantity.clone().alias(Column::QUOTE_VOLUME),
+                (col(TradeColumn::LAST_TRADE_ID) - col(TradeColumn::FIRST_TRADE_ID) + lit(1))
+                    .alias(Column::COUNT),
+                when(is_taker_buy.clone())
+                    .then(col(TradeColumn::QUANTITY))
+                    .otherwise(lit(0.0))
+                    .alias(Column::TAKER_BUY_VOLUME),
+                when(is_taker_buy)
+                    .then(quote_quantity)
+                    .otherwise(lit(0.0))
+                    .alias(Column::TAKER_BUY_QUOTE_VOLUME),
+                lit(0i64).alias(Column::IGNORE),
+            ])
+    }
+
+    /// Samples rows with the kline columns, sorted by open time, into bars of the
+    /// given type. The last bar is dropped while its threshold is not reached yet.
+    pub fn information_bars(&self, rows: &DataFrame, bar_type: &BarType) -> Result<DataFrame> {
+        let f64s_of = |name: &str| self.f64s_of(rows, name);
+
+        let bar_ids = match bar_type {
+            BarType::Volume(volume) => self.threshold_ids(&f64s_of(Column::VOLUME)?, *volume),
+            BarType::Dollar(quote_volume) => {
+                self.threshold_ids(&f64s_of(Column::QUOTE_VOLUME)?, *quote_volume)
+            }
+            BarType::Tick(count) => self.threshold_ids(&f64s_of(Column::COUNT)?, *count as f64),
+            BarType::TickImbalance {
+                expected_rows,
+                alpha,
+            } => {
+                let signs = self
+                    .signed_flows(rows)?
+                    .into_iter()
+                    .map(|flow| flow.signum())
+                    .collect::<Vec<_>>();
+                self.imbalance_ids(&signs, *expected_rows, *alpha)
+            }
+            BarType::VolumeImbalance {
+                expected_rows,
+                alpha,
+            } => self.imbalance_ids(&self.signed_flows(rows)?, *expected_rows, *alpha),
+        };
+
+        let complete = bar_ids.iter().filter(|id| id.is_some()).count();
+        let bar_ids = Series::new(BAR_ID, bar_ids);
+        let rows = rows
+            .slice(0, complete)
+            .hstack(&[bar_ids.slice(0, complete)])?;
+
+        let bars = rows
+            .lazy()
+            .groupby_stable([col(BAR_ID)])
+            .agg([
+                col(Column::OPEN_TIME).first(),
+                col(Column::OPEN).first(),
+                col(Column::HIGH).max(),
+                col(Column::LOW).min(),
+                col(Column::CLOSE).last(),
+                col(Column::VOLUME).sum(),
+                col(Column::CLOSE_TIME).last(),
+                col(Column::QUOTE_VOLUME).sum(),
+                col(Column::COUNT).sum(),
+                col(Column::TAKER_BUY_VOLUME).sum(),
+                col(Column::TAKER_BUY_QUOTE_VOLUME).sum(),
+            ])
+            .drop_columns([BAR_ID])
+            .collect()?;
+        Ok(bars)
+    }
+
+    /// Net taker flow of every row: bought minus sold volume. Rows without
+    /// any flow take the sign of their price change (tick rule).
+    fn signed_flows(&self, rows: &DataFrame) -> Result<Vec<f64>> {
+        let f64s_of = |name: &str| self.f64s_of(rows, name);
+        let volume = f64s_of(Column::VOLUME)?;
+        let taker_buy_volume = f64s_of(Column::TAKER_BUY_VOLUME)?;
+        let close = f64s_of(Column::CLOSE)?;
+
+        let mut prev_sign = 1.0;
+        let flows = (0..rows.height())
+            .map(|i| {
+                let flow = 2.0 * taker_buy_volume[i] - volume[i];
+                if flow != 0.0 {
+                    return flow;
+                }
+                if i > 0 && close[i] != close[i - 1] {
+                    prev_sign = (close[i] - close[i - 1]).signum();
+                }
+                prev_sign * volume[i].max(f64::EPSILON)
+            })
+            .collect();
+        Ok(flows)
+    }
+
+    /// Closes a bar whenever the running sum reaches the threshold.
+    /// Rows after the last closed bar get no id.
+    fn threshold_ids(&self, values: &[f64], threshold: f64) -> Vec<Option<u32>> {
+        let mut ids = Vec::with_capacity(values.len());
+        let mut id = 0;
+        let mut sum = 0.0;
+        for value in values {
+            ids.push(id);
+            sum += value;
+            if sum >= threshold {
+                id += 1;
+                sum = 0.0;
+            }
+        }
+        self.complete_ids(ids, id)
+    }
+
+    /// Closes a bar once the absolute sum of the signed values exceeds
+    /// `E[T] * |E[x]|`, both estimated as EWMA over the previous bars.
+    fn imbalance_ids(&self, values: &[f64], expected_rows: usize, alpha: f64) -> Vec<Option<u32>> {
+        let initial_rows = expected_rows.max(1) as f64;
+        let warmup = &values[..values.len().min(expected_rows.max(1))];
+        let mut expected_rows = initial_rows;
+        let mut expected_value = warmup.iter().sum::<f64>() / warmup.len().max(1) as f64;
+
+        let mut ids = Vec::with_capacity(values.len());
+        let mut id = 0;
+        let (mut imbalance, mut rows) = (0.0, 0);
+        for value in values {
+            ids.push(id);
+            imbalance += value;
+            rows += 1;
+            if imbalance.abs() >= expected_rows * expected_value.abs() {
+                expected_rows = (alpha * rows as f64 + (1.0 - alpha) * expected_rows).clamp(
+                    initial_rows / Self::MAX_EXPECTED_ROWS_FACTOR,
+                    initial_rows * Self::MAX_EXPECTED_ROWS_FACTOR,
+                );
+                expected_value = alpha * imbalance / rows as f64 + (1.0 - alpha) * expected_value;
+                id += 1;
+                (imbalance, rows) = (0.0, 0);
+            }
+        }
+        self.complete_ids(ids, id)
+    }
+
+    fn f64s_of(&self, rows: &DataFrame, name: &str) -> Result<Vec<f64>> {
+        let series = rows.column(name)?.cast(&DataType::Float64)?;
+        Ok(series
+            .f64()?
+            .into_iter()
+            .map(|v| v.unwrap_or_default())
+            .collect())
+    }
+
+    fn complete_ids(&self, ids: Vec<u32>, completed: u32) -> Vec<Option<u32>> {
+        ids.into_iter()
+            .map(|id| (id < completed).then_some(id))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::datetime;
+
+    #[test]
+    fn test_time_bars() {
+        let second = |ms: i64| {
+            datetime::create_utc(2023, 1, 1).naive_utc() + chrono::Duration::milliseconds(ms)
+        };
+        let trades = df!(
+            TradeColumn::AGG_TRADE_ID => &[1i64, 2, 3, 4],
+            TradeColumn::PRICE => &[10.0, 12.0, 9.0, 11.0],
+            TradeColumn::QUANTITY => &[1.0, 2.0, 3.0, 4.0],
+            TradeColumn::FIRST_TRADE_ID => &[1i64, 2, 5, 6],
+            TradeColumn::LAST_TRADE_ID => &[1i64, 4, 5, 6],
+            TradeColumn::TRANSACT_TIME => &[second(100), second(900), second(1500), second(10_200)],
+            TradeColumn::IS_BUYER_MAKER => &[false, true, false, false]
+        )
+        .unwrap();
+
+        let bars = BarBuilder::new()
+            .time_bars(trades.lazy(), "1s")
+            .collect()
+            .unwrap();
+
+        assert_eq!((3, 12), bars.shape());
+        let f64s_of = |name: &str| {
+            bars.column(name)
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![Some(10.0), Some(9.0), Some(11.0)],
+            f64s_of(Column::OPEN)
+        );
+        assert_eq!(
+            vec![Some(12.0), Some(9.0), Some(11.0)],
+            f64s_of(Column::HIGH)
+        );
+        assert_eq!(
+            vec![Some(3.0), Some(3.0), Some(4.0)],
+            f64s_of(Column::VOLUME)
+        );
+        assert_eq!(
+            vec![Some(34.0), Some(27.0), Some(44.0)],
+            f64s_of(Column::QUOTE_VOLUME)
+        );
+        assert_eq!(
+            vec![Some(1.0), Some(3.0), Some(4.0)],
+            f64s_of(Column::TAKER_BUY_VOLUME)
+        );
+
+        let count = bars.column(Column::COUNT).unwrap().i64().unwrap();
+        assert_eq!(Some(4), count.get(0));
+        let close_time = bars.column(Column::CLOSE_TIME).unwrap().i64().unwrap();
+        assert_eq!(Some(1672531200999), close_time.get(0));
+    }
+
+    #[test]
+    fn test_volume_bars() {
+        let rows = create_rows(&[2.0, 3.0, 6.0, 1.0, 4.0, 2.0]);
+
+        let bars = BarBuilder::new()
+            .information_bars(&rows, &BarType::Volume(5.0))
+            .unwrap();
+
+        assert_eq!(3, bars.height());
+        let volume = bars.column(Column::VOLUME).unwrap().f64().unwrap();
+        assert_eq!(
+            vec![Some(5.0), Some(6.0), Some(5.0)],
+            volume.into_iter().collect::<Vec<_>>()
+        );
+        let open = bars.column(Column::OPEN).unwrap().f64().unwrap();
+        assert_eq!(Some(3.0), open.get(1));
+    }
+
+    #[test]
+    fn test_imbalance_ids() {
+        let builder = BarBuilder::new();
+        let signs = [1.0, 1.0, 1.0, 1.0, -1.0, 1.0, -1.0, -1.0, -1.0, -1.0];
+
+        let ids = builder.imbalance_ids(&signs, 2, 0.5);
+
+        assert_eq!(Some(0), ids[0]);
+        assert_eq!(Some(0), ids[1]);
+        assert_eq!(Some(1), ids[2]);
+        assert!(ids.last().unwrap().is_some());
+    }
+
+    fn create_rows(volume: &[f64]) -> DataFrame {
+        let n = volume.len();
+        let minute = |m: usize| {
+            datetime::create_utc(2023, 1, 1).naive_utc() + chrono::Duration::minutes(m as i64)
+        };
+        let prices = (0..n).map(|i| i as f64 + 1.0).collect::<Vec<_>>();
+        df!(
+            Column::OPEN_TIME => (0..n).map(minute).collect::<Vec<_>>(),
+            Column::OPEN => &prices,
+            Column::HIGH => &prices,
+            Column::LOW => &prices,
+            Column::CLOSE => &prices,
+            Column::VOLUME => volume,
+            Column::CLOSE_TIME => (0..n).map(|i| i as i64).collect::<Vec<_>>(),
+            Column::QUOTE_VOLUME => volume,
+            Column::COUNT => (0..n).map(|_| 1i64).collect::<Vec<_>>(),
+            Column::TAKER_BUY_VOLUME => volume,
+            Column::TAKER_BUY_QUOTE_VOLUME => volume
+        )
+        .unwrap()
+    }
+}
@@ -0,0 +1,247 @@
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{anyhow, Result};
+use sha2::{Digest, Sha256};
+
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    pub path: PathBuf,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Checksum mismatch for '{}': expected {}, got {}",
+            self.path.display(),
+            self.expected,
+            self.actual
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+pub struct ChecksumVerifier;
+
+impl ChecksumVerifier {
+    const DIGEST_EXT: &str = "sha256";
+    const DIGEST_LEN: usize = 64;
+
+    pub fn new() -> ChecksumVerifier {
+        ChecksumVerifier {}
+    }
+
+    pub fn digest(&self, path: &Path) -> Result<String> {
+        let mut file = File::open(path)?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut file, &mut hasher)?;
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /// Parses the content of a binance.vision `.CHECKSUM` file,
+    /// which has the `sha256sum` format: `<hex digest>  <file name>`.
+    pub fn parse(&self, content: &str) -> Result<String> {
+        let digest = content
+            .split_whitespace()
+            .next()
+            .ok_or(anyhow!("Checksum content is empty."))?
+            .to_lowercase();
+
+        let is_hex = digest.chars().all(|c| c.is_ascii_hexdigit());
+        if digest.len() != Self::DIGEST_LEN || !is_hex {
+            return Err(anyhow!("Invalid checksum: {}", digest));
+        }
+
+        Ok(digest)
+    }
+
+    pub fn verify(&self, path: &Path, expected: &str) -> Result<()> {
+        let actual = self.digest(path)?;
+        self.check(path, expected, &actual)
+    }
+
+    /// Compares an already computed digest of the file with the expected one.
+    pub fn check(&self, path: &Path, expected: &str, actual: &str) -> Result<()> {
+        if actual != expected {
+            return Err(ChecksumMismatch {
+                path: path.to_path_buf(),
+                expected: expected.to_string(),
+                actual: actual.to_string(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Writes the digest of the file into a `<file>.sha256` sidecar, followed by
+    /// its size and modification time, so it can be re-verified later with
+    /// `verify_recorded` or cheaply checked with `check_recorded`.
+    pub fn record(&self, path: &Path) -> Result<()> {
+        let digest = self.digest(path)?;
+        let stat = self.stat_of(path)?;
+        fs::write(self.digest_path_for(path), format!("{}  {}", digest, stat))?;
+        Ok(())
+    }
+
+    /// Re-hashes the file and compares it with its recorded digest. On a mismatch
+    /// the recorded size and modification time are dropped, so `check_recorded`
+    /// re-hashes the file as well.
+    pub fn verify_recorded(&self, path: &Path) -> Result<()> {
+        let digest_path = self.digest_path_for(path);
+        if !digest_path.exists() {
+            return Err(anyhow!("No recorded digest for '{}'", path.display()));
+        }
+
+        let expected = self.parse(&fs::read_to_string(&digest_path)?)?;
+        let result = self.verify(path, &expected);
+        if result.is_err() {
+            fs::write(&digest_path, &expected)?;
+        }
+        result
+    }
+
+    /// Like `verify_recorded`, but only re-hashes the file if its size or
+    /// modification time differ from the recorded ones. A file without a
+    /// sidecar gets its digest recorded instead of failing.
+    pub fn check_recorded(&self, path: &Path) -> Result<()> {
+        let digest_path = self.digest_path_for(path);
+        if !digest_path.exists() {
+            return self.record(path);
+        }
+
+        let content = fs::read_to_string(&digest_path)?;
+        let recorded_stat = content.split_whitespace().nth(1);
+        if recorded_stat.is_some_and(|stat| stat == self.stat_of(path).unwrap_or_default()) {
+            return Ok(());
+        }
+
+        self.verify(path, &self.parse(&content)?)?;
+        self.record(path)
+    }
+
+    /// Size and modification time of the file as `<size>:<nanos since the epoch>`.
+    pub fn stat_of(&self, path: &Path) -> Result<String> {
+        let metadata = fs::metadata(path)?;
+        let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+        Ok(format!("{}:{}", metadata.len(), modified.as_nanos()))
+    }
+
+    pub fn digest_path_for(&self, path: &Path) -> PathBuf {
+        let mut digest_path = path.as_os_str().to_owned();
+        digest_path.push(".");
+        digest_path.push(Self::DIGEST_EXT);
+        PathBuf::from(digest_path)
+    }
+}
+
+/// Hashes content chunk by chunk while it is being streamed to disk.
+pub struct StreamingDigest {
+    hasher: Sha256,
+}
+
+impl StreamingDigest {
+    pub fn new() -> StreamingDigest {
+        StreamingDigest {
+            hasher: Sha256::new(),
+        }
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.hasher.update(chunk);
+    }
+
+    pub fn finish(self) -> String {
+        hex::encode(self.hasher.finalize())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ABC_DIGEST: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    #[test]
+    fn test_parse() {
+        let verifier = ChecksumVerifier::new();
+        let content = format!("{}  BTCUSDT-1m-2023-04.zip\n", ABC_DIGEST.to_uppercase());
+        assert_eq!(ABC_DIGEST, verifier.parse(&content).unwrap());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let verifier = ChecksumVerifier::new();
+        assert!(verifier.parse("").is_err());
+        assert!(verifier
+            .parse("not-a-digest  BTCUSDT-1m-2023-04.zip")
+            .is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        let verifier = ChecksumVerifier::new();
+        let path = std::env::temp_dir().join("qrust-checksum-test.csv");
+        fs::write(&path, "abc").unwrap();
+
+        assert!(verifier.verify(&path, ABC_DIGEST).is_ok());
+
+        let err = verifier.verify(&path, &"0".repeat(64)).unwrap_err();
+        assert!(err.downcast_ref::<ChecksumMismatch>().is_some());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_check_recorded() {
+        let verifier = ChecksumVerifier::new();
+        let path = std::env::temp_dir().join("qrust-check-recorded-test.csv");
+        let digest_path = verifier.digest_path_for(&path);
+        fs::write(&path, "abc").unwrap();
+        let _ = fs::remove_file(&digest_path);
+
+        verifier.check_recorded(&path).unwrap();
+        let recorded = fs::read_to_string(&digest_path).unwrap();
+        assert_eq!(ABC_DIGEST, verifier.parse(&recorded).unwrap());
+
+        fs::write(&path, "abcd").unwrap();
+        assert!(verifier.check_recorded(&path).is_err());
+
+        // Corrupted without a change of size or modification time, only a full verify notices
+        verifier.record(&path).unwrap();
+        let recorded = fs::read_to_string(&digest_path).unwrap();
+        let abcd_digest = verifier.parse(&recorded).unwrap();
+        fs::write(&digest_path, recorded.replace(&abcd_digest, ABC_DIGEST)).unwrap();
+        assert!(verifier.check_recorded(&path).is_ok());
+        assert!(verifier.verify_recorded(&path).is_err());
+        assert!(verifier.check_recorded(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&digest_path).unwrap();
+    }
+
+    #[test]
+    fn test_streaming_digest() {
+        let mut digest = StreamingDigest::new();
+        digest.update(b"a");
+        digest.update(b"bc");
+        assert_eq!(ABC_DIGEST, digest.finish());
+    }
+
+    #[test]
+    fn test_digest_path_for() {
+        let verifier = ChecksumVerifier::new();
+        let path = Path::new("BTCUSDT-1m-2023-04.csv");
+        assert_eq!(
+            PathBuf::from("BTCUSDT-1m-2023-04.csv.sha256"),
+            verifier.digest_path_for(path)
+        );
+    }
+}
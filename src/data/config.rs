@@ -1,13 +1,99 @@
+use std::env;
+use std::fmt;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
-use serde::Deserialize;
+use chrono::format::{Item, StrftimeItems};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
 
+use crate::data::dataset::Dataset;
+use crate::data::exchange::ExchangeId;
+use crate::data::symbols::SymbolFilter;
+use crate::data::timeframe::{ResampleOptions, Timeframe};
 use crate::data::AssetCategory;
 
+/// How the parquet files of a symbol are laid out below `base_store_dir/<exchange>/<cat>/<symbol>/`.
+/// `File` keeps one `{symbol}-{tf}.parquet` per timeframe, `Partitioned` splits every
+/// timeframe into `<tf>/year=YYYY/month=MM/` directories.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreLayout {
+    File,
+    Partitioned,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        origin: String,
+        source: toml::de::Error,
+    },
+    Invalid {
+        field: String,
+        reason: String,
+    },
+}
+
+impl ConfigError {
+    fn invalid(field: &str, reason: impl fmt::Display) -> ConfigError {
+        ConfigError::Invalid {
+            field: field.to_string(),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Read { path, source } => {
+                write!(f, "Could not read config '{}': {}", path.display(), source)
+            }
+            ConfigError::Parse { origin, source } => {
+                write!(f, "Invalid config in {}: {}", origin, source)
+            }
+            ConfigError::Invalid { field, reason } => {
+                write!(f, "Invalid config value for {}: {}", field, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Read { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            ConfigError::Invalid { .. } => None,
+        }
+    }
+}
+
+/// Endpoints of an exchange other than Binance, whose endpoints are the top-level ones.
 #[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawExchangeConfig {
+    pub info_uri: String,
+    pub spot_hist_trades_daily_uri: String,
+    pub usdm_hist_trades_daily_uri: String,
+    pub coinm_hist_trades_daily_uri: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
 struct RawDataConfig {
+    #[serde(default)]
+    pub exchange: ExchangeId,
     pub base_raw_dir: String,
     pub base_store_dir: String,
+    pub store_layout: StoreLayout,
 
     pub spot_info_uri: String,
     pub usdm_info_uri: String,
@@ -17,6 +103,9 @@ struct RawDataConfig {
     pub usdm_klines_uri: String,
     pub coinm_klines_uri: String,
 
+    pub usdm_funding_rate_uri: String,
+    pub coinm_funding_rate_uri: String,
+
     pub spot_hist_klines_monthly_uri: String,
     pub usdm_hist_klines_monthly_uri: String,
     pub coinm_hist_klines_monthly_uri: String,
@@ -25,42 +114,258 @@ struct RawDataConfig {
     pub usdm_hist_klines_daily_uri: String,
     pub coinm_hist_klines_daily_uri: String,
 
+    pub archive_list_uri: String,
+
     pub download_file_format: String,
     pub date_format_monthly: String,
     pub date_format_daily: String,
 
-    pub default_timeframes: Vec<String>,
+    pub default_timeframes: Vec<Timeframe>,
+    #[serde(default)]
+    pub resample: ResampleOptions,
+    pub datasets: Vec<Dataset>,
+    #[serde(default)]
+    pub symbol_filter: SymbolFilter,
+
+    pub retry_max_attempts: u32,
+    pub retry_initial_backoff_ms: u64,
+    pub retry_max_backoff_ms: u64,
+    pub retry_jitter: f64,
+    pub max_requests_per_minute: u32,
+    pub max_concurrent_downloads: usize,
+    pub max_concurrent_symbols: usize,
+
+    pub bybit: Option<RawExchangeConfig>,
+    pub okx: Option<RawExchangeConfig>,
 }
 
 impl RawDataConfig {
-    pub fn new() -> RawDataConfig {
-        let content = fs::read_to_string("Config.toml").unwrap();
-        toml::from_str(&content).unwrap()
+    fn validate(&self) -> Result<(), ConfigError> {
+        let uris = [
+            ("spot_info_uri", &self.spot_info_uri),
+            ("usdm_info_uri", &self.usdm_info_uri),
+            ("coinm_info_uri", &self.coinm_info_uri),
+            ("spot_klines_uri", &self.spot_klines_uri),
+            ("usdm_klines_uri", &self.usdm_klines_uri),
+            ("coinm_klines_uri", &self.coinm_klines_uri),
+            ("usdm_funding_rate_uri", &self.usdm_funding_rate_uri),
+            ("coinm_funding_rate_uri", &self.coinm_funding_rate_uri),
+            ("archive_list_uri", &self.archive_list_uri),
+        ];
+        for (field, uri) in uris {
+            validate_uri(field, uri)?;
+        }
+        // Archive uris are joined with the file names, so they have to end with a slash
+        let archive_uris = [
+            (
+                "spot_hist_klines_monthly_uri",
+                &self.spot_hist_klines_monthly_uri,
+            ),
+            (
+                "usdm_hist_klines_monthly_uri",
+                &self.usdm_hist_klines_monthly_uri,
+            ),
+            (
+                "coinm_hist_klines_monthly_uri",
+                &self.coinm_hist_klines_monthly_uri,
+            ),
+            (
+                "spot_hist_klines_daily_uri",
+                &self.spot_hist_klines_daily_uri,
+            ),
+            (
+                "usdm_hist_klines_daily_uri",
+                &self.usdm_hist_klines_daily_uri,
+            ),
+            (
+                "coinm_hist_klines_daily_uri",
+                &self.coinm_hist_klines_daily_uri,
+            ),
+        ];
+        for (field, uri) in archive_uris {
+            validate_archive_uri(field, uri)?;
+        }
+        for (name, exchangec) in [("bybit", &self.bybit), ("okx", &self.okx)] {
+            if let Some(exchangec) = exchangec {
+                exchangec.validate(name)?;
+            }
+        }
+
+        validate_date_format("date_format_monthly", &self.date_format_monthly)?;
+        validate_date_format("date_format_daily", &self.date_format_daily)?;
+        for placeholder in ["[[Symbol]]", "[[Timeframe]]", "[[Date]]"] {
+            if !self.download_file_format.contains(placeholder) {
+                return Err(ConfigError::invalid(
+                    "download_file_format",
+                    format!("missing {}", placeholder),
+                ));
+            }
+        }
+
+        for (i, timeframe) in self.default_timeframes.iter().enumerate() {
+            if self.default_timeframes[..i].contains(timeframe) {
+                return Err(ConfigError::invalid(
+                    "default_timeframes",
+                    format!("{} is listed twice", timeframe),
+                ));
+            }
+        }
+
+        if !(0.0..=1.0).contains(&self.retry_jitter) {
+            return Err(ConfigError::invalid(
+                "retry_jitter",
+                "must be between 0 and 1",
+            ));
+        }
+        let counts = [
+            ("retry_max_attempts", self.retry_max_attempts as usize),
+            (
+                "max_requests_per_minute",
+                self.max_requests_per_minute as usize,
+            ),
+            ("max_concurrent_downloads", self.max_concurrent_downloads),
+            ("max_concurrent_symbols", self.max_concurrent_symbols),
+        ];
+        for (field, count) in counts {
+            if count == 0 {
+                return Err(ConfigError::invalid(field, "must be at least 1"));
+            }
+        }
+
+        let section = match self.exchange {
+            ExchangeId::Binance => return Ok(()),
+            ExchangeId::Bybit => &self.bybit,
+            ExchangeId::Okx => &self.okx,
+        };
+        if section.is_none() {
+            return Err(ConfigError::invalid(
+                "exchange",
+                format!("no [{}] section", self.exchange.as_str()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+impl RawExchangeConfig {
+    fn validate(&self, name: &str) -> Result<(), ConfigError> {
+        validate_uri(&format!("{}.info_uri", name), &self.info_uri)?;
+        let archive_uris = [
+            (
+                "spot_hist_trades_daily_uri",
+                &self.spot_hist_trades_daily_uri,
+            ),
+            (
+                "usdm_hist_trades_daily_uri",
+                &self.usdm_hist_trades_daily_uri,
+            ),
+            (
+                "coinm_hist_trades_daily_uri",
+                &self.coinm_hist_trades_daily_uri,
+            ),
+        ];
+        for (field, uri) in archive_uris {
+            validate_archive_uri(&format!("{}.{}", name, field), uri)?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_uri(field: &str, uri: &str) -> Result<(), ConfigError> {
+    Url::parse(uri).map_err(|e| ConfigError::invalid(field, format!("'{}': {}", uri, e)))?;
+    Ok(())
+}
+
+fn validate_archive_uri(field: &str, uri: &str) -> Result<(), ConfigError> {
+    validate_uri(field, uri)?;
+    if !uri.ends_with('/') {
+        return Err(ConfigError::invalid(
+            field,
+            format!("'{}' does not end with '/'", uri),
+        ));
     }
+    Ok(())
+}
+
+fn validate_date_format(field: &str, format: &str) -> Result<(), ConfigError> {
+    let has_error = StrftimeItems::new(format).any(|item| item == Item::Error);
+    if format.is_empty() || has_error {
+        return Err(ConfigError::invalid(
+            field,
+            format!("'{}' is not a date format", format),
+        ));
+    }
+    Ok(())
 }
 
 #[derive(Clone)]
 pub struct DataConfig {
+    pub exchange: ExchangeId,
     pub asset_cat: AssetCategory,
 
     pub base_raw_dir: String,
     pub base_store_dir: String,
+    pub store_layout: StoreLayout,
 
     pub info_uri: String,
     pub klines_uri: String,
+    pub funding_rate_uri: Option<String>,
     pub hist_klines_monthly_uri: String,
     pub hist_klines_daily_uri: String,
+    pub hist_trades_daily_uri: Option<String>,
+    pub archive_list_uri: Option<String>,
 
     pub download_file_format: String,
     pub date_format_monthly: String,
     pub date_format_daily: String,
 
-    pub default_timeframes: Vec<String>,
+    pub default_timeframes: Vec<Timeframe>,
+    pub resample: ResampleOptions,
+    pub datasets: Vec<Dataset>,
+    pub symbol_filter: SymbolFilter,
+
+    pub retry_max_attempts: u32,
+    pub retry_initial_backoff_ms: u64,
+    pub retry_max_backoff_ms: u64,
+    pub retry_jitter: f64,
+    pub max_requests_per_minute: u32,
+    pub max_concurrent_downloads: usize,
+    pub max_concurrent_symbols: usize,
 }
 
 impl DataConfig {
-    pub fn new(asset_cat: AssetCategory) -> DataConfig {
-        let rawc = RawDataConfig::new();
+    /// Names the config file to load instead of `Config.toml` in the working directory.
+    pub const CONFIG_PATH_VAR: &'static str = "QRUST_CONFIG";
+
+    /// Loads the config of the asset category from, in increasing precedence,
+    /// the built-in defaults, the user-level file, `Config.toml` in the working
+    /// directory or the file named by `QRUST_CONFIG`, and `QRUST_*` variables.
+    pub fn load(asset_cat: AssetCategory) -> Result<DataConfig, ConfigError> {
+        let mut builder = DataConfig::builder().asset_cat(asset_cat).user_file()?;
+        builder = match env::var_os(Self::CONFIG_PATH_VAR) {
+            Some(path) => builder.file(Path::new(&path))?,
+            None => builder.optional_file(Path::new(DataConfigBuilder::CONFIG_FILE))?,
+        };
+        builder.env().build()
+    }
+
+    /// Same as `load` with an explicit config file, which has to exist.
+    pub fn load_from(path: &Path, asset_cat: AssetCategory) -> Result<DataConfig, ConfigError> {
+        DataConfig::builder()
+            .asset_cat(asset_cat)
+            .user_file()?
+            .file(path)?
+            .env()
+            .build()
+    }
+
+    /// A builder starting from the built-in defaults only, not reading any file
+    /// or environment variable unless told to.
+    pub fn builder() -> DataConfigBuilder {
+        DataConfigBuilder::new()
+    }
+
+    fn from_raw(asset_cat: AssetCategory, rawc: RawDataConfig) -> DataConfig {
         let (info_uri, klines_uri, hist_klines_monthly_uri, hist_klines_daily_uri) = match asset_cat
         {
             AssetCategory::Spot => (
@@ -82,19 +387,363 @@ impl DataConfig {
                 rawc.coinm_hist_klines_daily_uri,
             ),
         };
+        // Only perpetuals have funding
+        let funding_rate_uri = match asset_cat {
+            AssetCategory::Spot => None,
+            AssetCategory::Usdm => Some(rawc.usdm_funding_rate_uri),
+            AssetCategory::Coinm => Some(rawc.coinm_funding_rate_uri),
+        };
 
-        DataConfig {
+        let exchange = rawc.exchange;
+        let mut config = DataConfig {
+            exchange,
             asset_cat,
             base_raw_dir: rawc.base_raw_dir.clone(),
             base_store_dir: rawc.base_store_dir.clone(),
+            store_layout: rawc.store_layout,
             info_uri,
             klines_uri,
+            funding_rate_uri,
             hist_klines_monthly_uri,
             hist_klines_daily_uri,
+            hist_trades_daily_uri: None,
+            archive_list_uri: Some(rawc.archive_list_uri),
             download_file_format: rawc.download_file_format,
             date_format_monthly: rawc.date_format_monthly,
             date_format_daily: rawc.date_format_daily,
             default_timeframes: rawc.default_timeframes,
+            resample: rawc.resample,
+            datasets: rawc.datasets,
+            symbol_filter: rawc.symbol_filter,
+            retry_max_attempts: rawc.retry_max_attempts,
+            retry_initial_backoff_ms: rawc.retry_initial_backoff_ms,
+            retry_max_backoff_ms: rawc.retry_max_backoff_ms,
+            retry_jitter: rawc.retry_jitter,
+            max_requests_per_minute: rawc.max_requests_per_minute,
+            max_concurrent_downloads: rawc.max_concurrent_downloads,
+            max_concurrent_symbols: rawc.max_concurrent_symbols,
+        };
+
+        // Validated to be there
+        let exchangec = match exchange {
+            ExchangeId::Binance => return config,
+            ExchangeId::Bybit => rawc.bybit.unwrap(),
+            ExchangeId::Okx => rawc.okx.unwrap(),
+        };
+        // Only the Binance archives are listed and come with funding rates
+        config.info_uri = exchangec.info_uri;
+        config.hist_trades_daily_uri = Some(match config.asset_cat {
+            AssetCategory::Spot => exchangec.spot_hist_trades_daily_uri,
+            AssetCategory::Usdm => exchangec.usdm_hist_trades_daily_uri,
+            AssetCategory::Coinm => exchangec.coinm_hist_trades_daily_uri,
+        });
+        config.funding_rate_uri = None;
+        config.archive_list_uri = None;
+        config
+    }
+}
+
+/// Layers config sources on top of each other, later ones overriding single
+/// values of earlier ones, and validates the result in `build`.
+pub struct DataConfigBuilder {
+    table: Table,
+    asset_cat: Option<AssetCategory>,
+    error: Option<ConfigError>,
+}
+
+impl DataConfigBuilder {
+    const CONFIG_FILE: &'static str = "Config.toml";
+    const DEFAULTS: &'static str = include_str!("../../Config.toml");
+    const ENV_PREFIX: &'static str = "QRUST_";
+    /// Separates the keys of nested tables in variable names, e.g. `QRUST_RESAMPLE__TIMEZONE`.
+    const ENV_SEPARATOR: &'static str = "__";
+
+    fn new() -> DataConfigBuilder {
+        let (table, error) = match toml::from_str::<Table>(Self::DEFAULTS) {
+            Ok(table) => (table, None),
+            Err(source) => (
+                Table::new(),
+                Some(ConfigError::Parse {
+                    origin: "the built-in defaults".to_string(),
+                    source,
+                }),
+            ),
+        };
+        DataConfigBuilder {
+            table,
+            asset_cat: None,
+            error,
+        }
+    }
+
+    /// `qrust/Config.toml` in the user's config directory.
+    pub fn user_config_path() -> Option<PathBuf> {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(config_dir.join("qrust").join(Self::CONFIG_FILE))
+    }
+
+    pub fn file(self, path: &Path) -> Result<DataConfigBuilder, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|source| ConfigError::Read {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        self.content(&content, &format!("'{}'", path.display()))
+    }
+
+    /// Same as `file`, skipping files that do not exist.
+    pub fn optional_file(self, path: &Path) -> Result<DataConfigBuilder, ConfigError> {
+        if !path.exists() {
+            return Ok(self);
         }
+        self.file(path)
+    }
+
+    pub fn user_file(self) -> Result<DataConfigBuilder, ConfigError> {
+        match Self::user_config_path() {
+            Some(path) => self.optional_file(&path),
+            None => Ok(self),
+        }
+    }
+
+    /// Adds the TOML content, `origin` naming it in errors.
+    pub fn content(
+        mut self,
+        content: &str,
+        origin: &str,
+    ) -> Result<DataConfigBuilder, ConfigError> {
+        let layer = toml::from_str::<Table>(content).map_err(|source| ConfigError::Parse {
+            origin: origin.to_string(),
+            source,
+        })?;
+        merge_into(&mut self.table, layer);
+        Ok(self)
+    }
+
+    /// Applies the `QRUST_*` variables of the process, see `env_vars`.
+    pub fn env(self) -> DataConfigBuilder {
+        self.env_vars(env::vars())
+    }
+
+    /// Applies every `QRUST_<KEY>` variable to the lowercase key, e.g.
+    /// `QRUST_BASE_STORE_DIR`. Values are read as TOML values if they are
+    /// one, like `5` or `["1h", "4h"]`, and as plain strings otherwise.
+    /// Variables of keys the defaults don't have, like `QRUST_LOG`, are skipped.
+    pub fn env_vars<I>(mut self, vars: I) -> DataConfigBuilder
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        for (name, value) in vars {
+            let key = match name.strip_prefix(Self::ENV_PREFIX) {
+                Some(key) if name != DataConfig::CONFIG_PATH_VAR => key.to_lowercase(),
+                _ => continue,
+            };
+            let value = toml::from_str::<Table>(&format!("value = {}", value))
+                .ok()
+                .and_then(|mut table| table.remove("value"))
+                .unwrap_or(Value::String(value));
+            let path = key.split(Self::ENV_SEPARATOR).collect::<Vec<_>>();
+            if !self.table.contains_key(path[0]) {
+                log::warn!("Skipping {}, {} is not a config key", name, path[0]);
+                continue;
+            }
+            insert_into(&mut self.table, &path, value);
+        }
+        self
+    }
+
+    /// Sets a single value, nested keys separated by dots, e.g. `resample.timezone`.
+    pub fn set<T: Serialize>(mut self, key: &str, value: T) -> DataConfigBuilder {
+        match Value::try_from(value) {
+            Ok(value) => {
+                let path = key.split('.').collect::<Vec<_>>();
+                insert_into(&mut self.table, &path, value);
+            }
+            Err(e) => {
+                self.error.get_or_insert(ConfigError::invalid(key, e));
+            }
+        }
+        self
+    }
+
+    pub fn asset_cat(mut self, asset_cat: AssetCategory) -> DataConfigBuilder {
+        self.asset_cat = Some(asset_cat);
+        self
+    }
+
+    pub fn exchange(self, exchange: ExchangeId) -> DataConfigBuilder {
+        self.set("exchange", exchange)
+    }
+
+    pub fn base_raw_dir(self, base_raw_dir: &str) -> DataConfigBuilder {
+        self.set("base_raw_dir", base_raw_dir)
+    }
+
+    pub fn base_store_dir(self, base_store_dir: &str) -> DataConfigBuilder {
+        self.set("base_store_dir", base_store_dir)
+    }
+
+    pub fn store_layout(self, store_layout: StoreLayout) -> DataConfigBuilder {
+        self.set("store_layout", store_layout)
+    }
+
+    pub fn default_timeframes(self, default_timeframes: &[Timeframe]) -> DataConfigBuilder {
+        self.set("default_timeframes", default_timeframes)
+    }
+
+    pub fn build(self) -> Result<DataConfig, ConfigError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let asset_cat = self
+            .asset_cat
+            .ok_or(ConfigError::invalid("asset_cat", "not set"))?;
+        let rawc: RawDataConfig =
+            Value::Table(self.table)
+                .try_into()
+                .map_err(|source| ConfigError::Parse {
+                    origin: "the merged config".to_string(),
+                    source,
+                })?;
+        rawc.validate()?;
+        Ok(DataConfig::from_raw(asset_cat, rawc))
+    }
+}
+
+/// Merges tables key by key, any other value of the layer replaces the one in `table`.
+fn merge_into(table: &mut Table, layer: Table) {
+    for (key, value) in layer {
+        match (table.get_mut(&key), value) {
+            (Some(Value::Table(existing)), Value::Table(value)) => merge_into(existing, value),
+            (_, value) => {
+                table.insert(key, value);
+            }
+        }
+    }
+}
+
+fn insert_into(table: &mut Table, path: &[&str], value: Value) {
+    match path {
+        [] => {}
+        [key] => {
+            table.insert(key.to_string(), value);
+        }
+        [key, rest @ ..] => {
+            let entry = table
+                .entry(key.to_string())
+                .or_insert_with(|| Value::Table(Table::new()));
+            if !entry.is_table() {
+                *entry = Value::Table(Table::new());
+            }
+            if let Value::Table(nested) = entry {
+                insert_into(nested, rest, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder() {
+        let config = DataConfig::builder()
+            .asset_cat(AssetCategory::Usdm)
+            .base_store_dir("/tmp/store")
+            .store_layout(StoreLayout::Partitioned)
+            .default_timeframes(&[Timeframe::parse("1h").unwrap()])
+            .build()
+            .unwrap();
+        assert_eq!(ExchangeId::Binance, config.exchange);
+        assert_eq!("/tmp/store", config.base_store_dir);
+        assert_eq!(StoreLayout::Partitioned, config.store_layout);
+        assert_eq!(
+            vec![Timeframe::parse("1h").unwrap()],
+            config.default_timeframes
+        );
+        assert!(config.funding_rate_uri.is_some());
+
+        let config = DataConfig::builder()
+            .asset_cat(AssetCategory::Usdm)
+            .exchange(ExchangeId::Okx)
+            .build()
+            .unwrap();
+        assert_eq!(None, config.funding_rate_uri);
+        assert!(config.info_uri.contains("okx.com"));
+
+        assert!(DataConfig::builder().build().is_err());
+    }
+
+    #[test]
+    fn test_layers() {
+        let config = DataConfig::builder()
+            .asset_cat(AssetCategory::Spot)
+            .content(
+                "base_raw_dir = \"/data/raw\"\n[resample]\ntimezone = \"+08:00\"",
+                "test",
+            )
+            .unwrap()
+            .env_vars([
+                ("QRUST_BASE_RAW_DIR".to_string(), "/env/raw".to_string()),
+                ("QRUST_RETRY_MAX_ATTEMPTS".to_string(), "7".to_string()),
+                ("QRUST_RESAMPLE__WEEK_START".to_string(), "Sun".to_string()),
+                (
+                    "QRUST_SYMBOL_FILTER__STATUS".to_string(),
+                    "TRADING".to_string(),
+                ),
+                ("QRUST_CONFIG".to_string(), "ignored.toml".to_string()),
+                ("QRUST_LOG".to_string(), "debug".to_string()),
+                ("HOME".to_string(), "/home/test".to_string()),
+            ])
+            .build()
+            .unwrap();
+        assert_eq!("/env/raw", config.base_raw_dir);
+        assert_eq!(7, config.retry_max_attempts);
+        assert_eq!(8 * 3600, config.resample.timezone.local_minus_utc());
+        assert_eq!(chrono::Weekday::Sun, config.resample.week_start);
+        assert_eq!(Some("TRADING".to_string()), config.symbol_filter.status);
+        // Values of other layers are kept
+        assert_eq!("%Y-%m", config.date_format_monthly);
+    }
+
+    #[test]
+    fn test_invalid() {
+        let build = |content: &str| {
+            DataConfig::builder()
+                .asset_cat(AssetCategory::Spot)
+                .content(content, "test")
+                .and_then(|builder| builder.build())
+        };
+
+        let e = build("spot_info_uri = \"not a uri\"").err().unwrap();
+        assert!(matches!(e, ConfigError::Invalid { ref field, .. } if field == "spot_info_uri"));
+        let e = build("spot_hist_klines_daily_uri = \"https://data.binance.vision/daily\"")
+            .err()
+            .unwrap();
+        assert!(matches!(e, ConfigError::Invalid { .. }));
+        let e = build("date_format_daily = \"%Y-%Q\"").err().unwrap();
+        assert!(
+            matches!(e, ConfigError::Invalid { ref field, .. } if field == "date_format_daily")
+        );
+        let e = build("default_timeframes = [\"1x\"]").err().unwrap();
+        assert!(matches!(e, ConfigError::Parse { .. }));
+        let e = build("base_store_dri = \"/tmp\"").err().unwrap();
+        assert!(matches!(e, ConfigError::Parse { .. }));
+        let e = build("retry_jitter = 1.5").err().unwrap();
+        assert!(e.to_string().contains("retry_jitter"));
+        let e = build("base_raw_dir = ").err().unwrap();
+        assert!(matches!(e, ConfigError::Parse { ref origin, .. } if origin == "test"));
+    }
+
+    #[test]
+    fn test_load_from_missing() {
+        let path = Path::new("/nonexistent/qrust/Config.toml");
+        let e = DataConfig::load_from(path, AssetCategory::Spot)
+            .err()
+            .unwrap();
+        assert!(matches!(e, ConfigError::Read { .. }));
     }
 }
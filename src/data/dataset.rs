@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+
+use crate::data::provider::Timeperiod;
+use crate::data::{AssetCategory, Column};
+
+/// The archive datasets published on binance.vision. Their names match
+/// the directory names used there, e.g. `futures/um/daily/aggTrades/`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Dataset {
+    Klines,
+    AggTrades,
+    Trades,
+    MarkPriceKlines,
+    IndexPriceKlines,
+    PremiumIndexKlines,
+    FundingRate,
+    BookTicker,
+    BookDepth,
+    Metrics,
+}
+
+impl Dataset {
+    pub const ALL: [Dataset; 10] = [
+        Dataset::Klines,
+        Dataset::AggTrades,
+        Dataset::Trades,
+        Dataset::MarkPriceKlines,
+        Dataset::IndexPriceKlines,
+        Dataset::PremiumIndexKlines,
+        Dataset::FundingRate,
+        Dataset::BookTicker,
+        Dataset::BookDepth,
+        Dataset::Metrics,
+    ];
+
+    const KLINES_HEADER: &str = "open_time,open,high,low,close,volume,close_time,quote_volume,count,taker_buy_volume,taker_buy_quote_volume,ignore";
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Dataset::Klines => "klines",
+            Dataset::AggTrades => "aggTrades",
+            Dataset::Trades => "trades",
+            Dataset::MarkPriceKlines => "markPriceKlines",
+            Dataset::IndexPriceKlines => "indexPriceKlines",
+            Dataset::PremiumIndexKlines => "premiumIndexKlines",
+            Dataset::FundingRate => "fundingRate",
+            Dataset::BookTicker => "bookTicker",
+            Dataset::BookDepth => "bookDepth",
+            Dataset::Metrics => "metrics",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Dataset> {
+        Dataset::ALL
+            .into_iter()
+            .find(|dataset| dataset.as_str() == name)
+    }
+
+    /// Kline datasets are published per interval and share the klines columns.
+    pub fn is_kline(&self) -> bool {
+        matches!(
+            self,
+            Dataset::Klines
+                | Dataset::MarkPriceKlines
+                | Dataset::IndexPriceKlines
+                | Dataset::PremiumIndexKlines
+        )
+    }
+
+    pub fn supports(&self, asset_cat: &AssetCategory) -> bool {
+        match asset_cat {
+            AssetCategory::Spot => {
+                matches!(self, Dataset::Klines | Dataset::AggTrades | Dataset::Trades)
+            }
+            AssetCategory::Usdm | AssetCategory::Coinm => true,
+        }
+    }
+
+    pub fn timeperiods(&self) -> &'static [Timeperiod] {
+        match self {
+            Dataset::FundingRate => &[Timeperiod::Monthly],
+            Dataset::BookDepth | Dataset::Metrics => &[Timeperiod::Daily],
+            _ => &[Timeperiod::Monthly, Timeperiod::Daily],
+        }
+    }
+
+    /// The column every row of the dataset is ordered and partitioned by.
+    pub fn time_column(&self) -> &'static str {
+        match self {
+            Dataset::AggTrades => "transact_time",
+            Dataset::Trades => "time",
+            Dataset::FundingRate => "calc_time",
+            Dataset::BookTicker => "transaction_time",
+            Dataset::BookDepth => "timestamp",
+            Dataset::Metrics => "create_time",
+            _ => Column::OPEN_TIME,
+        }
+    }
+
+    /// The columns identifying a row, used to drop rows repeated across files.
+    /// Book depth has a row per percentage level at every timestamp.
+    pub fn key_columns(&self) -> &'static [&'static str] {
+        match self {
+            Dataset::AggTrades => &["agg_trade_id"],
+            Dataset::Trades => &["id"],
+            Dataset::BookTicker => &["update_id"],
+            Dataset::BookDepth => &["timestamp", "percentage"],
+            Dataset::FundingRate => &["calc_time"],
+            Dataset::Metrics => &["create_time"],
+            _ => &[Column::OPEN_TIME],
+        }
+    }
+
+    /// The CSV header of the dataset. Spot trades carry an extra `is_best_match` column.
+    pub fn header(&self, asset_cat: &AssetCategory) -> &'static str {
+        let is_spot = matches!(asset_cat, AssetCategory::Spot);
+        match self {
+            Dataset::AggTrades if is_spot => "agg_trade_id,price,quantity,first_trade_id,last_trade_id,transact_time,is_buyer_maker,is_best_match",
+            Dataset::AggTrades => "agg_trade_id,price,quantity,first_trade_id,last_trade_id,transact_time,is_buyer_maker",
+            Dataset::Trades if is_spot => "id,price,qty,quote_qty,time,is_buyer_maker,is_best_match",
+            Dataset::Trades => "id,price,qty,quote_qty,time,is_buyer_maker",
+            Dataset::FundingRate => "calc_time,funding_interval_hours,last_funding_rate",
+            Dataset::BookTicker => "update_id,best_bid_price,best_bid_qty,best_ask_price,best_ask_qty,transaction_time,event_time",
+            Dataset::BookDepth => "timestamp,percentage,depth,notional",
+            Dataset::Metrics => "create_time,symbol,sum_open_interest,sum_open_interest_value,count_toptrader_long_short_ratio,sum_toptrader_long_short_ratio,count_long_short_ratio,sum_taker_long_short_vol_ratio",
+            _ => Self::KLINES_HEADER,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_name() {
+        for dataset in Dataset::ALL {
+            assert_eq!(Some(dataset), Dataset::from_name(dataset.as_str()));
+        }
+        assert_eq!(None, Dataset::from_name("1h"));
+    }
+
+    #[test]
+    fn test_header() {
+        let spot = Dataset::Trades.header(&AssetCategory::Spot);
+        let usdm = Dataset::Trades.header(&AssetCategory::Usdm);
+        assert_eq!(7, spot.split(',').count());
+        assert_eq!(6, usdm.split(',').count());
+
+        for dataset in Dataset::ALL {
+            let header = dataset.header(&AssetCategory::Usdm);
+            assert!(header.split(',').any(|c| c == dataset.time_column()));
+            for key in dataset.key_columns() {
+                assert!(header.split(',').any(|c| c == *key));
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
+use polars::prelude::*;
+
+use crate::data::Column;
+use crate::extensions::datetime;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BrickSize {
+    Fixed(f64),
+    /// The average true range of the last `period` bars of the source.
+    Atr(usize),
+}
+
+/// Series computed from a stored timeframe and cached next to it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DerivedSeries {
+    HeikinAshi,
+    Renko(BrickSize),
+}
+
+impl DerivedSeries {
+    /// Suffix of the cache store, appended to the source timeframe.
+    pub fn name(&self) -> String {
+        match self {
+            DerivedSeries::HeikinAshi => "ha".to_string(),
+            DerivedSeries::Renko(BrickSize::Fixed(size)) => format!("renko-{}", size),
+            DerivedSeries::Renko(BrickSize::Atr(period)) => format!("renko-atr{}", period),
+        }
+    }
+}
+
+pub struct DerivedBuilder;
+
+impl DerivedBuilder {
+    pub fn new() -> DerivedBuilder {
+        DerivedBuilder {}
+    }
+
+    /// Builds the series from bars sorted by open time.
+    pub fn build(&self, bars: &DataFrame, series: &DerivedSeries) -> Result<DataFrame> {
+        match series {
+            DerivedSeries::HeikinAshi => self.heikin_ashi(bars),
+            DerivedSeries::Renko(brick_size) => {
+                let size = match brick_size {
+                    BrickSize::Fixed(size) => *size,
+                    BrickSize::Atr(period) => self.atr(bars, *period)?,
+                };
+                self.renko(bars, size)
+            }
+        }
+    }
+
+    pub fn heikin_ashi(&self, bars: &DataFrame) -> Result<DataFrame> {
+        let (open, high, low, close) = self.ohlc_of(bars)?;
+
+        let mut ha_open = Vec::with_capacity(bars.height());
+        let mut ha_high = Vec::with_capacity(bars.height());
+        let mut ha_low = Vec::with_capacity(bars.height());
+        let mut ha_close = Vec::with_capacity(bars.height());
+        for i in 0..bars.height() {
+            let c = (open[i] + high[i] + low[i] + close[i]) / 4.0;
+            let o = match i {
+                0 => (open[0] + close[0]) / 2.0,
+                _ => (ha_open[i - 1] + ha_close[i - 1]) / 2.0,
+            };
+            ha_open.push(o);
+            ha_high.push(high[i].max(o).max(c));
+            ha_low.push(low[i].min(o).min(c));
+            ha_close.push(c);
+        }
+
+        let df = DataFrame::new(vec![
+            bars.column(Column::OPEN_TIME)?.clone(),
+            Series::new(Column::OPEN, ha_open),
+            Series::new(Column::HIGH, ha_high),
+            Series::new(Column::LOW, ha_low),
+            Series::new(Column::CLOSE, ha_close),
+            bars.column(Column::VOLUME)?.clone(),
+        ])?;
+        Ok(df)
+    }
+
+    /// Close based Renko bricks. A brick continues the trend once the close moved
+    /// one brick beyond the last brick and reverses after two bricks against it.
+    /// Each brick carries the open time of the bar that completed it.
+    pub fn renko(&self, bars: &DataFrame, size: f64) -> Result<DataFrame> {
+        if size.is_nan() || size <= 0.0 {
+            return Err(anyhow!("Invalid brick size: {}", size));
+        }
+
+        let (_, _, _, close) = self.ohlc_of(bars)?;
+        let open_time = bars.column(Column::OPEN_TIME)?.datetime()?;
+        let unit = open_time.time_unit();
+
+        let mut bricks: Vec<(NaiveDateTime, f64, f64)> = Vec::new();
+        let reference = close.first().copied().unwrap_or_default();
+        for (ts, close) in open_time.into_iter().zip(close) {
+            let Some(ts) = ts else {
+                continue;
+            };
+            let time = datetime::from_time_unit(ts, unit);
+            loop {
+                let (up, down) = match bricks.last() {
+                    Some((_, o, c)) if c > o => (*c, *o),
+                    Some((_, o, c)) => (*o, *c),
+                    None => (reference, reference),
+                };
+                if close >= up + size {
+                    bricks.push((time, up, up + size));
+                } else if close <= down - size {
+                    bricks.push((time, down, down - size));
+                } else {
+                    break;
+                }
+            }
+        }
+
+        let df = DataFrame::new(vec![
+            Series::new(
+                Column::OPEN_TIME,
+                bricks.iter().map(|b| b.0).collect::<Vec<_>>(),
+            ),
+            Series::new(Column::OPEN, bricks.iter().map(|b| b.1).collect::<Vec<_>>()),
+            Series::new(
+                Column::HIGH,
+                bricks.iter().map(|b| b.1.max(b.2)).collect::<Vec<_>>(),
+            ),
+            Series::new(
+                Column::LOW,
+                bricks.iter().map(|b| b.1.min(b.2)).collect::<Vec<_>>(),
+            ),
+            Series::new(
+                Column::CLOSE,
+                bricks.iter().map(|b| b.2).collect::<Vec<_>>(),
+            ),
+        ])?;
+        Ok(df)
+    }
+
+    /// Simple average of the true range over the last `period` bars.
+    pub fn atr(&self, bars: &DataFrame, period: usize) -> Result<f64> {
+        let (_, high, low, close) = self.ohlc_of(bars)?;
+        let true_ranges = (1..bars.height())
+            .map(|i| {
+                (high[i] - low[i])
+                    .max((high[i] - close[i - 1]).abs())
+                    .max((low[i] - close[i - 1]).abs())
+            })
+            .collect::<Vec<_>>();
+
+        let last = &true_ranges[true_ranges.len().saturating_sub(period.max(1))..];
+        if last.is_empty() {
+            return Err(anyhow!("Not enough bars for an ATR of {}", period));
+        }
+        Ok(last.iter().sum::<f64>() / last.len() as f64)
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn ohlc_of(&self, bars: &DataFrame) -> Result<(Vec<f64>, Vec<f64>, Vec<f64>, Vec<f64>)> {
+        let f64s_of = |name: &str| -> Result<Vec<f64>> {
+            let series = bars.column(name)?.cast(&DataType::Float64)?;
+            Ok(series
+                .f64()?
+                .into_iter()
+                .map(|v| v.unwrap_or(f64::NAN))
+                .collect())
+        };
+        Ok((
+            f64s_of(Column::OPEN)?,
+            f64s_of(Column::HIGH)?,
+            f64s_of(Column::LOW)?,
+            f64s_of(Column::CLOSE)?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_heikin_ashi() {
+        let bars = create_bars(&[(10.0, 12.0, 9.0, 11.0), (11.0, 14.0, 10.0, 13.0)]);
+
+        let ha = DerivedBuilder::new().heikin_ashi(&bars).unwrap();
+
+        let values_of = |name: &str| {
+            ha.column(name)
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![Some(10.5), Some(10.5)], values_of(Column::OPEN));
+        assert_eq!(vec![Some(10.5), Some(12.0)], values_of(Column::CLOSE));
+        assert_eq!(vec![Some(12.0), Some(14.0)], values_of(Column::HIGH));
+        assert_eq!(vec![Some(9.0), Some(10.0)], values_of(Column::LOW));
+    }
+
+    #[test]
+    fn test_renko() {
+        let closes = [100.0, 101.5, 103.2, 102.5, 100.9, 99.0, 100.5];
+        let bars = create_bars(&closes.map(|c| (c, c, c, c)));
+
+        let renko = DerivedBuilder::new().renko(&bars, 1.0).unwrap();
+
+        let close = renko.column(Column::CLOSE).unwrap().f64().unwrap();
+        assert_eq!(
+            vec![
+                Some(101.0),
+                Some(102.0),
+                Some(103.0),
+                Some(101.0),
+                Some(100.0),
+                Some(99.0)
+            ],
+            close.into_iter().collect::<Vec<_>>()
+        );
+        let open = renko.column(Column::OPEN).unwrap().f64().unwrap();
+        assert_eq!(Some(102.0), open.get(3));
+    }
+
+    #[test]
+    fn test_atr() {
+        let bars = create_bars(&[
+            (10.0, 11.0, 9.0, 10.0),
+            (10.0, 12.0, 10.0, 11.0),
+            (11.0, 11.0, 7.0, 8.0),
+        ]);
+        assert_eq!(3.0, DerivedBuilder::new().atr(&bars, 14).unwrap());
+        assert_eq!(4.0, DerivedBuilder::new().atr(&bars, 1).unwrap());
+    }
+
+    fn create_bars(ohlc: &[(f64, f64, f64, f64)]) -> DataFrame {
+        let minute = |m: usize| {
+            datetime::create_utc(2023, 1, 1).naive_utc() + chrono::Duration::minutes(m as i64)
+        };
+        df!(
+            Column::OPEN_TIME => (0..ohlc.len()).map(minute).collect::<Vec<_>>(),
+            Column::OPEN => ohlc.iter().map(|b| b.0).collect::<Vec<_>>(),
+            Column::HIGH => ohlc.iter().map(|b| b.1).collect::<Vec<_>>(),
+            Column::LOW => ohlc.iter().map(|b| b.2).collect::<Vec<_>>(),
+            Column::CLOSE => ohlc.iter().map(|b| b.3).collect::<Vec<_>>(),
+            Column::VOLUME => ohlc.iter().map(|_| 1.0).collect::<Vec<_>>()
+        )
+        .unwrap()
+    }
+}
@@ -0,0 +1,174 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use polars::prelude::*;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+
+use crate::data::config::DataConfig;
+use crate::data::dataset::Dataset;
+use crate::data::provider::Timeperiod;
+use crate::data::{AssetCategory, Symbol, TradeColumn};
+
+pub mod binance;
+pub mod bybit;
+pub mod okx;
+
+use binance::Binance;
+use bybit::Bybit;
+use okx::Okx;
+
+/// The exchanges whose archives can be synced. The name is the first
+/// directory below `base_raw_dir` and `base_store_dir`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExchangeId {
+    #[default]
+    Binance,
+    Bybit,
+    Okx,
+}
+
+impl ExchangeId {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExchangeId::Binance => "binance",
+            ExchangeId::Bybit => "bybit",
+            ExchangeId::Okx => "okx",
+        }
+    }
+
+    pub fn exchange(&self) -> Arc<dyn Exchange> {
+        match self {
+            ExchangeId::Binance => Arc::new(Binance),
+            ExchangeId::Bybit => Arc::new(Bybit),
+            ExchangeId::Okx => Arc::new(Okx),
+        }
+    }
+}
+
+/// What differs between exchanges up to the raw CSVs: how symbols are listed,
+/// where the archives are and what their columns are. Raw CSVs are rewritten
+/// into the binance.vision layout, so everything downstream is shared.
+pub trait Exchange: Send + Sync {
+    fn id(&self) -> ExchangeId;
+
+    /// The endpoint listing the symbols of the configured asset category.
+    fn info_uri_for(&self, config: &DataConfig) -> Result<Url>;
+
+    /// Parses the response of the info endpoint.
+    fn symbols_from(&self, json: &JsonValue, asset_cat: &AssetCategory) -> Result<Vec<Symbol>>;
+
+    fn supports(&self, dataset: Dataset, asset_cat: &AssetCategory) -> bool;
+
+    /// The archives a dataset is built from. Exchanges without kline
+    /// archives have their 1m klines built from the trades.
+    fn source_of(&self, dataset: Dataset) -> Dataset {
+        dataset
+    }
+
+    fn timeperiods(&self, dataset: Dataset) -> &'static [Timeperiod];
+
+    fn archive_name_for(
+        &self,
+        config: &DataConfig,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> String;
+
+    fn archive_uri_for(
+        &self,
+        config: &DataConfig,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> Result<Url>;
+
+    /// Whether every archive has a `.CHECKSUM` file next to it.
+    fn has_checksums(&self) -> bool {
+        false
+    }
+
+    /// Rewrites an unpacked archive CSV into the binance.vision columns of the dataset.
+    fn normalize(&self, dataset: Dataset, asset_cat: &AssetCategory, csvpath: &Path) -> Result<()>;
+}
+
+/// Reads a CSV with a header of its own, maps it and writes it back with the new columns.
+fn rewrite_csv<F>(path: &Path, map: F) -> Result<()>
+where
+    F: FnOnce(DataFrame) -> Result<DataFrame>,
+{
+    let raw = CsvReader::from_path(path)?.has_header(true).finish()?;
+    let mut df = map(raw)?;
+    let mut file = File::create(path)?;
+    CsvWriter::new(&mut file).has_header(true).finish(&mut df)?;
+    Ok(())
+}
+
+/// Lays out trades in the aggTrades columns, every trade being an aggregate of
+/// its own. Expects `agg_trade_id`, `price`, `quantity`, `transact_time` in
+/// epoch millis and `is_buyer_maker`.
+fn agg_trades_from(trades: LazyFrame, asset_cat: &AssetCategory) -> Result<DataFrame> {
+    let trade_id = col(TradeColumn::AGG_TRADE_ID).cast(DataType::Int64);
+    let mut columns = vec![
+        trade_id.clone(),
+        col(TradeColumn::PRICE).cast(DataType::Float64),
+        col(TradeColumn::QUANTITY).cast(DataType::Float64),
+        trade_id.clone().alias(TradeColumn::FIRST_TRADE_ID),
+        trade_id.alias(TradeColumn::LAST_TRADE_ID),
+        col(TradeColumn::TRANSACT_TIME).cast(DataType::Int64),
+        col(TradeColumn::IS_BUYER_MAKER),
+    ];
+    // Spot aggTrades carry a flag futures ones do not
+    if matches!(asset_cat, AssetCategory::Spot) {
+        columns.push(lit(true).alias(TradeColumn::IS_BEST_MATCH));
+    }
+    Ok(trades.select(columns).collect()?)
+}
+
+/// The taker side of a trade, `buy` or `sell` in any case, as the maker flag of the buyer.
+fn is_buyer_maker(side: &str) -> Expr {
+    col(side).str().to_lowercase().eq(lit("sell"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agg_trades_from() {
+        let trades = df!(
+            TradeColumn::AGG_TRADE_ID => &[1i64, 2],
+            TradeColumn::PRICE => &["100.5", "101"],
+            TradeColumn::QUANTITY => &[0.5, 1.0],
+            TradeColumn::TRANSACT_TIME => &[1680566400000i64, 1680566400500],
+            "side" => &["Buy", "sell"]
+        )
+        .unwrap()
+        .lazy()
+        .with_column(is_buyer_maker("side").alias(TradeColumn::IS_BUYER_MAKER));
+
+        let df = agg_trades_from(trades.clone(), &AssetCategory::Usdm).unwrap();
+        let header = Dataset::AggTrades.header(&AssetCategory::Usdm);
+        assert_eq!(header.split(',').collect::<Vec<_>>(), df.get_column_names());
+        let makers = df
+            .column(TradeColumn::IS_BUYER_MAKER)
+            .unwrap()
+            .bool()
+            .unwrap();
+        assert_eq!(
+            vec![Some(false), Some(true)],
+            makers.into_iter().collect::<Vec<_>>()
+        );
+
+        let df = agg_trades_from(trades, &AssetCategory::Spot).unwrap();
+        assert_eq!(8, df.width());
+    }
+}
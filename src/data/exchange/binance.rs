@@ -0,0 +1,298 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use reqwest::Url;
+use serde_json::Value as JsonValue;
+
+use crate::data::config::DataConfig;
+use crate::data::dataset::Dataset;
+use crate::data::exchange::{Exchange, ExchangeId};
+use crate::data::provider::{Timeperiod, DEFAULT_TIMEFRAME};
+use crate::data::sanitizer::CsvSanitizer;
+use crate::data::symbols::{default_initdate, SymbolInfo};
+use crate::data::{AssetCategory, Symbol};
+
+/// The monthly and daily archives of data.binance.vision.
+pub struct Binance;
+
+impl Binance {
+    const KEY_INITDATE: &'static str = "onboardDate";
+
+    fn symbol_from(&self, json: &JsonValue) -> Result<Symbol> {
+        let name = json["symbol"]
+            .as_str()
+            .ok_or(anyhow!("Symbol without name: {}", json))?;
+        let str_of = |key: &str| json.get(key)?.as_str().map(|value| value.to_string());
+        let filter_of = |filter_type: &str, key: &str| -> Option<f64> {
+            json["filters"]
+                .as_array()?
+                .iter()
+                .find(|filter| filter["filterType"] == filter_type)?
+                .get(key)?
+                .as_str()?
+                .parse()
+                .ok()
+        };
+
+        let info = SymbolInfo {
+            // Coin-M contracts report their status as contractStatus
+            status: str_of("status")
+                .or_else(|| str_of("contractStatus"))
+                .unwrap_or_default(),
+            base_asset: str_of("baseAsset").unwrap_or_default(),
+            quote_asset: str_of("quoteAsset").unwrap_or_default(),
+            contract_type: str_of("contractType").filter(|value| !value.is_empty()),
+            margin_asset: str_of("marginAsset"),
+            tick_size: filter_of("PRICE_FILTER", "tickSize"),
+            step_size: filter_of("LOT_SIZE", "stepSize"),
+            // Spot lists it as minNotional, USD-M as notional
+            min_notional: filter_of("MIN_NOTIONAL", "notional")
+                .or_else(|| filter_of("MIN_NOTIONAL", "minNotional"))
+                .or_else(|| filter_of("NOTIONAL", "minNotional")),
+            delivery_date: json
+                .get("deliveryDate")
+                .and_then(|value| value.as_i64())
+                .and_then(DateTime::from_timestamp_millis),
+        };
+
+        let mut symbol = Symbol::new(name, self.initdate_for(json));
+        symbol.info = Some(info);
+        Ok(symbol)
+    }
+
+    fn initdate_for(&self, json: &JsonValue) -> DateTime<Utc> {
+        if let Some(initdate) = json.get(Self::KEY_INITDATE) {
+            let mut timestamp = initdate.as_i64().unwrap();
+            if timestamp.to_string().len() <= 10 {
+                timestamp *= 1000;
+            }
+            DateTime::from_utc(
+                NaiveDateTime::from_timestamp_millis(timestamp).unwrap(),
+                Utc,
+            )
+        } else {
+            default_initdate()
+        }
+    }
+
+    /// The configured klines uris point to `.../<timeperiod>/klines/`,
+    /// the other datasets are siblings of it.
+    fn base_uri_for(
+        &self,
+        config: &DataConfig,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+    ) -> Result<Url> {
+        let klinesuri = match timeperiod {
+            Timeperiod::Monthly => &config.hist_klines_monthly_uri,
+            Timeperiod::Daily => &config.hist_klines_daily_uri,
+        };
+        let uri = Url::parse(klinesuri)?;
+        if dataset == Dataset::Klines {
+            return Ok(uri);
+        }
+        Ok(uri.join(&format!("../{}/", dataset.as_str()))?)
+    }
+
+    fn date_format_for<'a>(&self, config: &'a DataConfig, timeperiod: &Timeperiod) -> &'a str {
+        match timeperiod {
+            Timeperiod::Monthly => &config.date_format_monthly,
+            Timeperiod::Daily => &config.date_format_daily,
+        }
+    }
+}
+
+impl Exchange for Binance {
+    fn id(&self) -> ExchangeId {
+        ExchangeId::Binance
+    }
+
+    fn info_uri_for(&self, config: &DataConfig) -> Result<Url> {
+        Ok(Url::parse(&config.info_uri)?)
+    }
+
+    fn symbols_from(&self, json: &JsonValue, _asset_cat: &AssetCategory) -> Result<Vec<Symbol>> {
+        json["symbols"]
+            .as_array()
+            .ok_or(anyhow!("No symbols in exchange info"))?
+            .iter()
+            .map(|item| self.symbol_from(item))
+            .collect()
+    }
+
+    fn supports(&self, dataset: Dataset, asset_cat: &AssetCategory) -> bool {
+        dataset.supports(asset_cat)
+    }
+
+    fn timeperiods(&self, dataset: Dataset) -> &'static [Timeperiod] {
+        dataset.timeperiods()
+    }
+
+    fn archive_name_for(
+        &self,
+        config: &DataConfig,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> String {
+        let timeframe = if dataset.is_kline() {
+            DEFAULT_TIMEFRAME
+        } else {
+            dataset.as_str()
+        };
+        let dateformat = self.date_format_for(config, timeperiod);
+        let mut filename = config.download_file_format.to_string();
+        filename = filename.replace("[[Symbol]]", &symbol.to_uppercase());
+        filename = filename.replace("[[Timeframe]]", timeframe);
+        filename = filename.replace("[[Date]]", &date.format(dateformat).to_string());
+        filename
+    }
+
+    fn archive_uri_for(
+        &self,
+        config: &DataConfig,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> Result<Url> {
+        const SLASH: &str = "/";
+        let filename = self.archive_name_for(config, symbol, dataset, timeperiod, date);
+        let baseuri = self.base_uri_for(config, dataset, timeperiod)?;
+        let mut uri = baseuri.join(&format!("{}{}", symbol.to_uppercase(), SLASH))?;
+        if dataset.is_kline() {
+            uri = uri.join(&format!("{}{}", DEFAULT_TIMEFRAME, SLASH))?;
+        }
+        Ok(uri.join(&filename)?)
+    }
+
+    fn has_checksums(&self) -> bool {
+        true
+    }
+
+    /// The archives have the binance.vision columns already, but only some have a header.
+    fn normalize(&self, dataset: Dataset, asset_cat: &AssetCategory, csvpath: &Path) -> Result<()> {
+        CsvSanitizer::with_header(dataset.header(asset_cat)).run(csvpath)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+
+    use crate::extensions::datetime;
+
+    #[test]
+    fn test_monthly_archive_name_for() {
+        let config = DataConfig::builder()
+            .asset_cat(AssetCategory::Spot)
+            .build()
+            .unwrap();
+        let date = datetime::create_utc(2023, 4, 1);
+        let file_name = Binance.archive_name_for(
+            &config,
+            "btcusdt",
+            Dataset::Klines,
+            &Timeperiod::Monthly,
+            &date,
+        );
+        assert_eq!("BTCUSDT-1m-2023-04.zip", file_name);
+    }
+
+    #[test]
+    fn test_daily_archive_name_for() {
+        let config = DataConfig::builder()
+            .asset_cat(AssetCategory::Spot)
+            .build()
+            .unwrap();
+        let date = datetime::create_utc(2023, 4, 4);
+        let file_name = Binance.archive_name_for(
+            &config,
+            "btcusdt",
+            Dataset::Klines,
+            &Timeperiod::Daily,
+            &date,
+        );
+        assert_eq!("BTCUSDT-1m-2023-04-04.zip", file_name);
+    }
+
+    #[test]
+    fn test_archive_uri_for() {
+        let config = DataConfig::builder()
+            .asset_cat(AssetCategory::Spot)
+            .build()
+            .unwrap();
+        let date = datetime::create_utc(2023, 4, 4);
+        let file_name = Binance.archive_name_for(
+            &config,
+            "btcusdt",
+            Dataset::AggTrades,
+            &Timeperiod::Daily,
+            &date,
+        );
+        assert_eq!("BTCUSDT-aggTrades-2023-04-04.zip", file_name);
+
+        let uri = Binance
+            .archive_uri_for(
+                &config,
+                "btcusdt",
+                Dataset::AggTrades,
+                &Timeperiod::Daily,
+                &date,
+            )
+            .unwrap();
+        assert_eq!(
+            "https://data.binance.vision/data/spot/daily/aggTrades/BTCUSDT/BTCUSDT-aggTrades-2023-04-04.zip",
+            uri.as_str()
+        );
+        let uri = Binance
+            .archive_uri_for(
+                &config,
+                "BTCUSDT",
+                Dataset::Klines,
+                &Timeperiod::Monthly,
+                &date,
+            )
+            .unwrap();
+        assert_eq!(
+            "https://data.binance.vision/data/spot/monthly/klines/BTCUSDT/1m/BTCUSDT-1m-2023-04.zip",
+            uri.as_str()
+        );
+    }
+
+    #[test]
+    fn test_symbols_from() {
+        let json: JsonValue = serde_json::from_str(
+            r#"{"symbols": [
+                {"symbol": "BTCUSDT", "status": "TRADING", "contractType": "PERPETUAL",
+                 "baseAsset": "BTC", "quoteAsset": "USDT", "marginAsset": "USDT",
+                 "onboardDate": 1569369600000, "deliveryDate": 4133404800000,
+                 "filters": [
+                    {"filterType": "PRICE_FILTER", "tickSize": "0.10"},
+                    {"filterType": "LOT_SIZE", "stepSize": "0.001"},
+                    {"filterType": "MIN_NOTIONAL", "notional": "100"}]},
+                {"symbol": "BTCUSD_PERP", "contractStatus": "TRADING", "contractType": "PERPETUAL",
+                 "baseAsset": "BTC", "quoteAsset": "USD", "marginAsset": "BTC", "filters": []}
+            ]}"#,
+        )
+        .unwrap();
+
+        let symbols = Binance.symbols_from(&json, &AssetCategory::Usdm).unwrap();
+        assert_eq!(2, symbols.len());
+        let info = symbols[0].info.as_ref().unwrap();
+        assert_eq!(datetime::create_utc(2019, 9, 25), symbols[0].initdate);
+        assert_eq!("USDT", info.quote_asset);
+        assert_eq!(Some("PERPETUAL".to_string()), info.contract_type);
+        assert_eq!(Some(0.1), info.tick_size);
+        assert_eq!(Some(0.001), info.step_size);
+        assert_eq!(Some(100.0), info.min_notional);
+        assert_eq!(Some(2100), info.delivery_date.map(|d| d.year()));
+
+        let info = symbols[1].info.as_ref().unwrap();
+        assert_eq!("TRADING", info.status);
+        assert_eq!(None, info.tick_size);
+    }
+}
@@ -0,0 +1,316 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use polars::prelude::*;
+use reqwest::Url;
+use serde_json::Value as JsonValue;
+
+use crate::data::config::DataConfig;
+use crate::data::dataset::Dataset;
+use crate::data::exchange::{agg_trades_from, is_buyer_maker, rewrite_csv, Exchange, ExchangeId};
+use crate::data::provider::Timeperiod;
+use crate::data::symbols::{default_initdate, SymbolInfo};
+use crate::data::{AssetCategory, Symbol, TradeColumn};
+
+/// The daily trade archives of public.bybit.com, `spot/BTCUSDT/BTCUSDT_2023-04-04.csv.gz`
+/// for spot and `trading/BTCUSDT/BTCUSDT2023-04-04.csv.gz` for contracts.
+pub struct Bybit;
+
+impl Bybit {
+    const DATE_FORMAT: &'static str = "%Y-%m-%d";
+    const INFO_LIMIT: &'static str = "1000";
+    /// Leaves room for the trades within a milli in the ids of contract trades.
+    const TRADES_PER_MILLI: i64 = 1_000_000;
+
+    fn category_for(asset_cat: &AssetCategory) -> &'static str {
+        match asset_cat {
+            AssetCategory::Spot => "spot",
+            AssetCategory::Usdm => "linear",
+            AssetCategory::Coinm => "inverse",
+        }
+    }
+
+    fn symbol_from(&self, json: &JsonValue) -> Result<Symbol> {
+        let name = json["symbol"]
+            .as_str()
+            .ok_or(anyhow!("Symbol without name: {}", json))?;
+        let str_of = |key: &str| json.get(key)?.as_str().filter(|value| !value.is_empty());
+        let float_of = |filter: &str, key: &str| json[filter][key].as_str()?.parse::<f64>().ok();
+        // Times are millis as strings, 0 if there are none
+        let time_of = |key: &str| {
+            str_of(key)?
+                .parse::<i64>()
+                .ok()
+                .filter(|millis| *millis > 0)
+                .and_then(DateTime::from_timestamp_millis)
+        };
+
+        let info = SymbolInfo {
+            status: Self::status_of(str_of("status").unwrap_or_default()).to_string(),
+            base_asset: str_of("baseCoin").unwrap_or_default().to_string(),
+            quote_asset: str_of("quoteCoin").unwrap_or_default().to_string(),
+            contract_type: str_of("contractType").map(Self::contract_type_of),
+            margin_asset: str_of("settleCoin").map(|value| value.to_string()),
+            tick_size: float_of("priceFilter", "tickSize"),
+            // Spot lists the step as basePrecision, contracts as qtyStep
+            step_size: float_of("lotSizeFilter", "qtyStep")
+                .or_else(|| float_of("lotSizeFilter", "basePrecision")),
+            min_notional: float_of("lotSizeFilter", "minNotionalValue")
+                .or_else(|| float_of("lotSizeFilter", "minOrderAmt")),
+            delivery_date: time_of("deliveryTime"),
+        };
+
+        let initdate = time_of("launchTime").unwrap_or_else(default_initdate);
+        let mut symbol = Symbol::new(name, initdate);
+        symbol.info = Some(info);
+        Ok(symbol)
+    }
+
+    /// Maps the statuses to the ones of Binance, which symbol filters are written against.
+    fn status_of(status: &str) -> &str {
+        match status {
+            "Trading" => "TRADING",
+            "PreLaunch" => "PENDING_TRADING",
+            "Delivering" => "SETTLING",
+            "Closed" => "CLOSE",
+            _ => status,
+        }
+    }
+
+    fn contract_type_of(contract_type: &str) -> String {
+        if contract_type.ends_with("Perpetual") {
+            "PERPETUAL".to_string()
+        } else {
+            contract_type.to_uppercase()
+        }
+    }
+
+    /// Contract archives have the time in seconds with a fraction and a trade id
+    /// that is not a number, spot ones the time in millis and a numeric id.
+    /// Contract trades get the time in millis times a million plus their index
+    /// within the milli as id, which keeps increasing across daily archives.
+    fn trades_from(raw: DataFrame, asset_cat: &AssetCategory) -> Result<DataFrame> {
+        let trades = match asset_cat {
+            AssetCategory::Spot => raw.lazy().select([
+                col("id").alias(TradeColumn::AGG_TRADE_ID),
+                col("price").alias(TradeColumn::PRICE),
+                col("volume").alias(TradeColumn::QUANTITY),
+                col("timestamp").alias(TradeColumn::TRANSACT_TIME),
+                is_buyer_maker("side").alias(TradeColumn::IS_BUYER_MAKER),
+            ]),
+            _ => {
+                let time = col(TradeColumn::TRANSACT_TIME);
+                let row = col("row").cast(DataType::Int64);
+                let index_in_milli = row.clone() - row.min().over([time.clone()]);
+                raw.lazy()
+                    .with_column(
+                        (col("timestamp").cast(DataType::Float64) * lit(1000.0) + lit(0.5))
+                            .cast(DataType::Int64)
+                            .alias(TradeColumn::TRANSACT_TIME),
+                    )
+                    .with_row_count("row", None)
+                    .select([
+                        (time.clone() * lit(Self::TRADES_PER_MILLI) + index_in_milli)
+                            .alias(TradeColumn::AGG_TRADE_ID),
+                        col("price").alias(TradeColumn::PRICE),
+                        col("size").alias(TradeColumn::QUANTITY),
+                        time,
+                        is_buyer_maker("side").alias(TradeColumn::IS_BUYER_MAKER),
+                    ])
+            }
+        };
+        agg_trades_from(trades, asset_cat)
+    }
+}
+
+impl Exchange for Bybit {
+    fn id(&self) -> ExchangeId {
+        ExchangeId::Bybit
+    }
+
+    fn info_uri_for(&self, config: &DataConfig) -> Result<Url> {
+        let category = Self::category_for(&config.asset_cat);
+        let params = [("category", category), ("limit", Self::INFO_LIMIT)];
+        Ok(Url::parse_with_params(&config.info_uri, &params)?)
+    }
+
+    fn symbols_from(&self, json: &JsonValue, _asset_cat: &AssetCategory) -> Result<Vec<Symbol>> {
+        json["result"]["list"]
+            .as_array()
+            .ok_or(anyhow!(
+                "No symbols in instruments info: {}",
+                json["retMsg"]
+            ))?
+            .iter()
+            .map(|item| self.symbol_from(item))
+            .collect()
+    }
+
+    fn supports(&self, dataset: Dataset, _asset_cat: &AssetCategory) -> bool {
+        matches!(dataset, Dataset::Klines | Dataset::AggTrades)
+    }
+
+    fn source_of(&self, dataset: Dataset) -> Dataset {
+        match dataset {
+            Dataset::Klines => Dataset::AggTrades,
+            _ => dataset,
+        }
+    }
+
+    fn timeperiods(&self, _dataset: Dataset) -> &'static [Timeperiod] {
+        &[Timeperiod::Daily]
+    }
+
+    fn archive_name_for(
+        &self,
+        config: &DataConfig,
+        symbol: &str,
+        _dataset: Dataset,
+        _timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> String {
+        let separator = match config.asset_cat {
+            AssetCategory::Spot => "_",
+            _ => "",
+        };
+        let date = date.format(Self::DATE_FORMAT);
+        format!("{}{}{}.csv.gz", symbol.to_uppercase(), separator, date)
+    }
+
+    fn archive_uri_for(
+        &self,
+        config: &DataConfig,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> Result<Url> {
+        let baseuri = config
+            .hist_trades_daily_uri
+            .as_deref()
+            .ok_or(anyhow!("No trade archives configured for bybit"))?;
+        let filename = self.archive_name_for(config, symbol, dataset, timeperiod, date);
+        let uri = Url::parse(baseuri)?.join(&format!("{}/", symbol.to_uppercase()))?;
+        Ok(uri.join(&filename)?)
+    }
+
+    fn normalize(
+        &self,
+        _dataset: Dataset,
+        asset_cat: &AssetCategory,
+        csvpath: &Path,
+    ) -> Result<()> {
+        rewrite_csv(csvpath, |raw| Self::trades_from(raw, asset_cat))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::data::exchange::ExchangeId;
+    use crate::extensions::datetime;
+
+    #[test]
+    fn test_archive_uri_for() {
+        let date = datetime::create_utc(2023, 4, 4);
+        let config = DataConfig::builder()
+            .exchange(ExchangeId::Bybit)
+            .asset_cat(AssetCategory::Usdm)
+            .build()
+            .unwrap();
+        let uri = Bybit
+            .archive_uri_for(
+                &config,
+                "btcusdt",
+                Dataset::AggTrades,
+                &Timeperiod::Daily,
+                &date,
+            )
+            .unwrap();
+        assert_eq!(
+            "https://public.bybit.com/trading/BTCUSDT/BTCUSDT2023-04-04.csv.gz",
+            uri.as_str()
+        );
+
+        let config = DataConfig::builder()
+            .exchange(ExchangeId::Bybit)
+            .asset_cat(AssetCategory::Spot)
+            .build()
+            .unwrap();
+        let uri = Bybit
+            .archive_uri_for(
+                &config,
+                "BTCUSDT",
+                Dataset::AggTrades,
+                &Timeperiod::Daily,
+                &date,
+            )
+            .unwrap();
+        assert_eq!(
+            "https://public.bybit.com/spot/BTCUSDT/BTCUSDT_2023-04-04.csv.gz",
+            uri.as_str()
+        );
+    }
+
+    #[test]
+    fn test_symbols_from() {
+        let json: JsonValue = serde_json::from_str(
+            r#"{"retCode": 0, "result": {"category": "linear", "list": [
+                {"symbol": "BTCUSDT", "contractType": "LinearPerpetual", "status": "Trading",
+                 "baseCoin": "BTC", "quoteCoin": "USDT", "settleCoin": "USDT",
+                 "launchTime": "1585526400000", "deliveryTime": "0",
+                 "priceFilter": {"tickSize": "0.10"},
+                 "lotSizeFilter": {"qtyStep": "0.001", "minNotionalValue": "5"}}
+            ]}}"#,
+        )
+        .unwrap();
+
+        let symbols = Bybit.symbols_from(&json, &AssetCategory::Usdm).unwrap();
+        let info = symbols[0].info.as_ref().unwrap();
+        assert_eq!(datetime::create_utc(2020, 3, 30), symbols[0].initdate);
+        assert_eq!("TRADING", info.status);
+        assert_eq!(Some("PERPETUAL".to_string()), info.contract_type);
+        assert_eq!(Some(0.001), info.step_size);
+        assert_eq!(Some(5.0), info.min_notional);
+        assert_eq!(None, info.delivery_date);
+    }
+
+    #[test]
+    fn test_trades_from() {
+        let raw = df!(
+            "timestamp" => &[1680566400.1234, 1680566401.5, 1680566401.5],
+            "symbol" => &["BTCUSDT", "BTCUSDT", "BTCUSDT"],
+            "side" => &["Buy", "Sell", "Sell"],
+            "size" => &[0.5, 1.0, 0.2],
+            "price" => &[28000.5, 28001.0, 28001.0],
+            "trdMatchID" => &["a1", "b2", "c3"]
+        )
+        .unwrap();
+
+        let trades = Bybit::trades_from(raw, &AssetCategory::Usdm).unwrap();
+        let times = trades
+            .column(TradeColumn::TRANSACT_TIME)
+            .unwrap()
+            .i64()
+            .unwrap();
+        assert_eq!(Some(1680566400123), times.get(0));
+        let ids = trades
+            .column(TradeColumn::AGG_TRADE_ID)
+            .unwrap()
+            .i64()
+            .unwrap();
+        assert_eq!(Some(1680566401500 * Bybit::TRADES_PER_MILLI), ids.get(1));
+        assert_eq!(
+            Some(1680566401500 * Bybit::TRADES_PER_MILLI + 1),
+            ids.get(2)
+        );
+        let makers = trades
+            .column(TradeColumn::IS_BUYER_MAKER)
+            .unwrap()
+            .bool()
+            .unwrap();
+        assert_eq!(Some(true), makers.get(1));
+    }
+}
@@ -0,0 +1,261 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use polars::prelude::*;
+use reqwest::Url;
+use serde_json::Value as JsonValue;
+
+use crate::data::config::DataConfig;
+use crate::data::dataset::Dataset;
+use crate::data::exchange::{agg_trades_from, is_buyer_maker, rewrite_csv, Exchange, ExchangeId};
+use crate::data::provider::Timeperiod;
+use crate::data::symbols::{default_initdate, SymbolInfo};
+use crate::data::{AssetCategory, Symbol, TradeColumn};
+
+/// The daily trade archives of okx.com, one directory per day holding
+/// e.g. `BTC-USDT-SWAP-trades-2023-04-04.zip`. Symbols are OKX instrument ids.
+pub struct Okx;
+
+impl Okx {
+    const DATE_FORMAT: &'static str = "%Y-%m-%d";
+    const DIR_DATE_FORMAT: &'static str = "%Y%m%d";
+
+    /// USD-M and Coin-M symbols are the linear and inverse swaps.
+    fn inst_type_for(asset_cat: &AssetCategory) -> (&'static str, Option<&'static str>) {
+        match asset_cat {
+            AssetCategory::Spot => ("SPOT", None),
+            AssetCategory::Usdm => ("SWAP", Some("linear")),
+            AssetCategory::Coinm => ("SWAP", Some("inverse")),
+        }
+    }
+
+    fn symbol_from(&self, json: &JsonValue) -> Result<Symbol> {
+        let name = json["instId"]
+            .as_str()
+            .ok_or(anyhow!("Instrument without id: {}", json))?;
+        let str_of = |key: &str| json.get(key)?.as_str().filter(|value| !value.is_empty());
+        let float_of = |key: &str| str_of(key)?.parse::<f64>().ok();
+        let time_of = |key: &str| {
+            str_of(key)?
+                .parse::<i64>()
+                .ok()
+                .and_then(DateTime::from_timestamp_millis)
+        };
+        // Swaps only name their assets in the underlying, e.g. BTC-USDT
+        let (base_asset, quote_asset) = match (str_of("baseCcy"), str_of("quoteCcy")) {
+            (Some(base), Some(quote)) => (base, quote),
+            _ => str_of("uly")
+                .and_then(|uly| uly.split_once('-'))
+                .unwrap_or_default(),
+        };
+
+        let info = SymbolInfo {
+            status: Self::status_of(str_of("state").unwrap_or_default()).to_string(),
+            base_asset: base_asset.to_string(),
+            quote_asset: quote_asset.to_string(),
+            contract_type: match str_of("instType") {
+                Some("SWAP") => Some("PERPETUAL".to_string()),
+                Some("SPOT") | None => None,
+                Some(inst_type) => Some(inst_type.to_string()),
+            },
+            margin_asset: str_of("settleCcy").map(|value| value.to_string()),
+            tick_size: float_of("tickSz"),
+            step_size: float_of("lotSz"),
+            min_notional: None,
+            delivery_date: time_of("expTime"),
+        };
+
+        let initdate = time_of("listTime").unwrap_or_else(default_initdate);
+        let mut symbol = Symbol::new(name, initdate);
+        symbol.info = Some(info);
+        Ok(symbol)
+    }
+
+    /// Maps the states to the statuses of Binance, which symbol filters are written against.
+    fn status_of(state: &str) -> &str {
+        match state {
+            "live" => "TRADING",
+            "preopen" => "PENDING_TRADING",
+            "suspend" => "BREAK",
+            _ => state,
+        }
+    }
+
+    fn trades_from(raw: DataFrame, asset_cat: &AssetCategory) -> Result<DataFrame> {
+        let trades = raw.lazy().select([
+            col("trade_id").alias(TradeColumn::AGG_TRADE_ID),
+            col("price").alias(TradeColumn::PRICE),
+            col("size").alias(TradeColumn::QUANTITY),
+            col("created_time").alias(TradeColumn::TRANSACT_TIME),
+            is_buyer_maker("side").alias(TradeColumn::IS_BUYER_MAKER),
+        ]);
+        agg_trades_from(trades, asset_cat)
+    }
+}
+
+impl Exchange for Okx {
+    fn id(&self) -> ExchangeId {
+        ExchangeId::Okx
+    }
+
+    fn info_uri_for(&self, config: &DataConfig) -> Result<Url> {
+        let (inst_type, _) = Self::inst_type_for(&config.asset_cat);
+        Ok(Url::parse_with_params(
+            &config.info_uri,
+            &[("instType", inst_type)],
+        )?)
+    }
+
+    // `Option::is_none_or` needs Rust 1.82
+    #[allow(clippy::unnecessary_map_or)]
+    fn symbols_from(&self, json: &JsonValue, asset_cat: &AssetCategory) -> Result<Vec<Symbol>> {
+        let (_, ct_type) = Self::inst_type_for(asset_cat);
+        json["data"]
+            .as_array()
+            .ok_or(anyhow!("No instruments in response: {}", json["msg"]))?
+            .iter()
+            .filter(|item| ct_type.map_or(true, |ct_type| item["ctType"] == ct_type))
+            .map(|item| self.symbol_from(item))
+            .collect()
+    }
+
+    fn supports(&self, dataset: Dataset, _asset_cat: &AssetCategory) -> bool {
+        matches!(dataset, Dataset::Klines | Dataset::AggTrades)
+    }
+
+    fn source_of(&self, dataset: Dataset) -> Dataset {
+        match dataset {
+            Dataset::Klines => Dataset::AggTrades,
+            _ => dataset,
+        }
+    }
+
+    fn timeperiods(&self, _dataset: Dataset) -> &'static [Timeperiod] {
+        &[Timeperiod::Daily]
+    }
+
+    fn archive_name_for(
+        &self,
+        _config: &DataConfig,
+        symbol: &str,
+        _dataset: Dataset,
+        _timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> String {
+        let date = date.format(Self::DATE_FORMAT);
+        format!("{}-trades-{}.zip", symbol.to_uppercase(), date)
+    }
+
+    fn archive_uri_for(
+        &self,
+        config: &DataConfig,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> Result<Url> {
+        let baseuri = config
+            .hist_trades_daily_uri
+            .as_deref()
+            .ok_or(anyhow!("No trade archives configured for okx"))?;
+        let filename = self.archive_name_for(config, symbol, dataset, timeperiod, date);
+        let dir = date.format(Self::DIR_DATE_FORMAT);
+        let uri = Url::parse(baseuri)?.join(&format!("{}/", dir))?;
+        Ok(uri.join(&filename)?)
+    }
+
+    fn normalize(
+        &self,
+        _dataset: Dataset,
+        asset_cat: &AssetCategory,
+        csvpath: &Path,
+    ) -> Result<()> {
+        rewrite_csv(csvpath, |raw| Self::trades_from(raw, asset_cat))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::extensions::datetime;
+
+    #[test]
+    fn test_archive_uri_for() {
+        let config = DataConfig::builder()
+            .exchange(ExchangeId::Okx)
+            .asset_cat(AssetCategory::Usdm)
+            .build()
+            .unwrap();
+        let date = datetime::create_utc(2023, 4, 4);
+        let uri = Okx
+            .archive_uri_for(
+                &config,
+                "BTC-USDT-SWAP",
+                Dataset::AggTrades,
+                &Timeperiod::Daily,
+                &date,
+            )
+            .unwrap();
+        assert_eq!(
+            "https://www.okx.com/cdn/okex/traderecords/trades/daily/20230404/BTC-USDT-SWAP-trades-2023-04-04.zip",
+            uri.as_str()
+        );
+    }
+
+    #[test]
+    fn test_symbols_from() {
+        let json: JsonValue = serde_json::from_str(
+            r#"{"code": "0", "data": [
+                {"instId": "BTC-USDT-SWAP", "instType": "SWAP", "ctType": "linear", "state": "live",
+                 "uly": "BTC-USDT", "settleCcy": "USDT", "baseCcy": "", "quoteCcy": "",
+                 "tickSz": "0.1", "lotSz": "1", "listTime": "1573557408000", "expTime": ""},
+                {"instId": "BTC-USD-SWAP", "instType": "SWAP", "ctType": "inverse", "state": "live",
+                 "uly": "BTC-USD", "settleCcy": "BTC", "tickSz": "0.1", "lotSz": "1"}
+            ]}"#,
+        )
+        .unwrap();
+
+        let symbols = Okx.symbols_from(&json, &AssetCategory::Usdm).unwrap();
+        assert_eq!(1, symbols.len());
+        let info = symbols[0].info.as_ref().unwrap();
+        assert_eq!("TRADING", info.status);
+        assert_eq!("BTC", info.base_asset);
+        assert_eq!("USDT", info.quote_asset);
+        assert_eq!(Some("PERPETUAL".to_string()), info.contract_type);
+        assert_eq!(None, info.delivery_date);
+
+        let symbols = Okx.symbols_from(&json, &AssetCategory::Coinm).unwrap();
+        assert_eq!("BTC-USD-SWAP", symbols[0].name);
+        assert_eq!(default_initdate(), symbols[0].initdate);
+    }
+
+    #[test]
+    fn test_trades_from() {
+        let raw = df!(
+            "instrument_name" => &["BTC-USDT-SWAP", "BTC-USDT-SWAP"],
+            "trade_id" => &[100i64, 101],
+            "side" => &["buy", "sell"],
+            "price" => &[28000.5, 28001.0],
+            "size" => &[2.0, 1.0],
+            "created_time" => &[1680566400123i64, 1680566401000]
+        )
+        .unwrap();
+
+        let trades = Okx::trades_from(raw, &AssetCategory::Usdm).unwrap();
+        assert_eq!((2, 7), trades.shape());
+        let first = trades
+            .column(TradeColumn::FIRST_TRADE_ID)
+            .unwrap()
+            .i64()
+            .unwrap();
+        assert_eq!(Some(100), first.get(0));
+        let makers = trades
+            .column(TradeColumn::IS_BUYER_MAKER)
+            .unwrap()
+            .bool()
+            .unwrap();
+        assert_eq!(Some(false), makers.get(0));
+    }
+}
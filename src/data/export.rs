@@ -0,0 +1,244 @@
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use chrono::{DateTime, Utc};
+use polars::prelude::*;
+
+/// The file formats bars can be exported to. Feather is Arrow IPC.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Ipc,
+    Ndjson,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ipc => "ipc",
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+
+    /// Guesses the format from the extension of the file, e.g. `.feather` or `.jsonl`.
+    pub fn from_path(path: &Path) -> Option<ExportFormat> {
+        let extension = path.extension()?.to_str()?.to_lowercase();
+        extension.parse().ok()
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<ExportFormat> {
+        match value {
+            "csv" => Ok(ExportFormat::Csv),
+            "ipc" | "arrow" | "feather" => Ok(ExportFormat::Ipc),
+            "ndjson" | "jsonl" => Ok(ExportFormat::Ndjson),
+            "parquet" => Ok(ExportFormat::Parquet),
+            _ => Err(anyhow!(
+                "Invalid export format '{}', expected csv, ipc, feather, ndjson or parquet",
+                value
+            )),
+        }
+    }
+}
+
+/// How datetime columns are written.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// As the datetime type of the format, text for CSV and NDJSON.
+    #[default]
+    Native,
+    /// As milliseconds since the epoch.
+    EpochMillis,
+    /// As text in a strftime format, e.g. `%Y-%m-%d %H:%M`.
+    Strftime(String),
+}
+
+impl FromStr for TimestampFormat {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<TimestampFormat> {
+        match value {
+            "native" => Ok(TimestampFormat::Native),
+            "ms" | "epoch_ms" => Ok(TimestampFormat::EpochMillis),
+            _ if value.contains('%') => Ok(TimestampFormat::Strftime(value.to_string())),
+            _ => Err(anyhow!(
+                "Invalid timestamp format '{}', expected native, ms or a strftime format",
+                value
+            )),
+        }
+    }
+}
+
+/// What part of a store to export and how. Without a range all bars are exported.
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub columns: Option<Vec<String>>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    pub timestamps: TimestampFormat,
+}
+
+impl ExportOptions {
+    pub fn new(format: ExportFormat) -> ExportOptions {
+        ExportOptions {
+            format,
+            columns: None,
+            from: None,
+            to: None,
+            timestamps: TimestampFormat::Native,
+        }
+    }
+
+    pub fn with_columns(mut self, columns: &[&str]) -> ExportOptions {
+        self.columns = Some(columns.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    /// Only bars with `from <= open_time < to`.
+    pub fn with_range(
+        mut self,
+        from: Option<DateTime<Utc>>,
+        to: Option<DateTime<Utc>>,
+    ) -> ExportOptions {
+        self.from = from;
+        self.to = to;
+        self
+    }
+
+    pub fn with_timestamps(mut self, timestamps: TimestampFormat) -> ExportOptions {
+        self.timestamps = timestamps;
+        self
+    }
+}
+
+/// Writes the frame to a single file in the format of the options.
+pub fn write_frame(df: DataFrame, options: &ExportOptions, path: &Path) -> Result<()> {
+    let mut df = format_timestamps(df, &options.timestamps)?;
+    let mut file = File::create(path)?;
+    match options.format {
+        ExportFormat::Csv => CsvWriter::new(&mut file).has_header(true).finish(&mut df)?,
+        ExportFormat::Ipc => IpcWriter::new(&mut file).finish(&mut df)?,
+        ExportFormat::Ndjson => JsonWriter::new(&mut file)
+            .with_json_format(JsonFormat::JsonLines)
+            .finish(&mut df)?,
+        ExportFormat::Parquet => {
+            ParquetWriter::new(&mut file).finish(&mut df)?;
+        }
+    }
+    Ok(())
+}
+
+fn format_timestamps(df: DataFrame, timestamps: &TimestampFormat) -> Result<DataFrame> {
+    let time_columns = df
+        .get_columns()
+        .iter()
+        .filter(|series| matches!(series.dtype(), DataType::Datetime(_, _)))
+        .map(|series| series.name().to_string())
+        .collect::<Vec<_>>();
+    if time_columns.is_empty() || *timestamps == TimestampFormat::Native {
+        return Ok(df);
+    }
+
+    let formatted = time_columns
+        .iter()
+        .map(|name| match timestamps {
+            TimestampFormat::EpochMillis => col(name).dt().timestamp(TimeUnit::Milliseconds),
+            TimestampFormat::Strftime(format) => col(name).dt().strftime(format),
+            TimestampFormat::Native => col(name),
+        })
+        .collect::<Vec<_>>();
+    Ok(df.lazy().with_columns(formatted).collect()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::Value as JsonValue;
+
+    use crate::data::Column;
+    use crate::extensions::datetime;
+
+    fn create_bars() -> DataFrame {
+        let open_times = [
+            datetime::create_utc(2023, 4, 1).naive_utc(),
+            datetime::create_utc(2023, 4, 2).naive_utc(),
+        ];
+        df!(
+            Column::OPEN_TIME => &open_times,
+            Column::CLOSE => &[28000.5, 28100.0]
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_format_from() {
+        assert_eq!(
+            Some(ExportFormat::Ipc),
+            ExportFormat::from_path(Path::new("a.feather"))
+        );
+        assert_eq!(
+            Some(ExportFormat::Ndjson),
+            ExportFormat::from_path(Path::new("a.JSONL"))
+        );
+        assert_eq!(None, ExportFormat::from_path(Path::new("a.xlsx")));
+        assert_eq!(
+            TimestampFormat::Strftime("%Y-%m-%d".to_string()),
+            "%Y-%m-%d".parse().unwrap()
+        );
+        assert!("iso".parse::<TimestampFormat>().is_err());
+    }
+
+    #[test]
+    fn test_format_timestamps() {
+        let df = format_timestamps(create_bars(), &TimestampFormat::EpochMillis).unwrap();
+        let open_times = df.column(Column::OPEN_TIME).unwrap().i64().unwrap();
+        assert_eq!(Some(1680307200000), open_times.get(0));
+
+        let timestamps = TimestampFormat::Strftime("%Y-%m-%d".to_string());
+        let df = format_timestamps(create_bars(), &timestamps).unwrap();
+        let open_times = df.column(Column::OPEN_TIME).unwrap().utf8().unwrap();
+        assert_eq!(Some("2023-04-02"), open_times.get(1));
+    }
+
+    #[test]
+    fn test_write_frame() {
+        let dir = std::env::temp_dir().join("qrust_test_write_frame");
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("bars.jsonl");
+        let options =
+            ExportOptions::new(ExportFormat::Ndjson).with_timestamps(TimestampFormat::EpochMillis);
+        write_frame(create_bars(), &options, &path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(2, content.lines().count());
+        let first: JsonValue = serde_json::from_str(content.lines().next().unwrap()).unwrap();
+        assert_eq!(1680307200000i64, first[Column::OPEN_TIME]);
+        assert_eq!(28000.5, first[Column::CLOSE]);
+
+        write_frame(
+            create_bars(),
+            &ExportOptions::new(ExportFormat::Ndjson),
+            &path,
+        )
+        .unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.contains("\"2023-04-02 00:00:00\""));
+
+        let path = dir.join("bars.arrow");
+        write_frame(create_bars(), &ExportOptions::new(ExportFormat::Ipc), &path).unwrap();
+        let df = IpcReader::new(File::open(&path).unwrap()).finish().unwrap();
+        assert!(df.frame_equal(&create_bars()));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
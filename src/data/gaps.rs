@@ -0,0 +1,172 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::Result;
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::data::Column;
+use crate::extensions::datetime;
+
+/// A run of missing bars, from the first to the last missing open time.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Gap {
+    pub from: NaiveDateTime,
+    pub to: NaiveDateTime,
+}
+
+impl Gap {
+    pub fn bars(&self, cadence: Duration) -> i64 {
+        (self.to - self.from).num_seconds() / cadence.num_seconds() + 1
+    }
+
+    pub fn dates(&self) -> Vec<NaiveDate> {
+        self.from
+            .date()
+            .iter_days()
+            .take_while(|date| *date <= self.to.date())
+            .collect()
+    }
+}
+
+pub struct GapDetector {
+    cadence: Duration,
+}
+
+impl GapDetector {
+    pub fn new(cadence: Duration) -> GapDetector {
+        GapDetector { cadence }
+    }
+
+    /// Finds all missing bars in the sorted frame. If `after` is given,
+    /// the bars between it and the first row are checked as well.
+    pub fn detect(&self, df: &DataFrame, after: Option<NaiveDateTime>) -> Result<Vec<Gap>> {
+        let open_time = df.column(Column::OPEN_TIME)?.datetime()?;
+        let unit = open_time.time_unit();
+
+        let mut gaps = Vec::new();
+        let mut prev = after;
+        for ts in open_time.into_iter().flatten() {
+            let current = datetime::from_time_unit(ts, unit);
+            if let Some(prev) = prev {
+                if current - prev > self.cadence {
+                    gaps.push(Gap {
+                        from: prev + self.cadence,
+                        to: current - self.cadence,
+                    });
+                }
+            }
+            prev = Some(current);
+        }
+        Ok(gaps)
+    }
+
+    pub fn dates_for(&self, gaps: &[Gap]) -> Vec<NaiveDate> {
+        let dates = gaps
+            .iter()
+            .flat_map(|gap| gap.dates())
+            .collect::<BTreeSet<_>>();
+        dates.into_iter().collect()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GapReport {
+    pub symbol: String,
+    pub cadence: String,
+    pub updated: DateTime<Utc>,
+    pub gaps: Vec<Gap>,
+}
+
+impl GapReport {
+    pub fn new(symbol: &str, cadence: &str, gaps: Vec<Gap>) -> GapReport {
+        GapReport {
+            symbol: symbol.to_string(),
+            cadence: cadence.to_string(),
+            updated: Utc::now(),
+            gaps,
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<GapReport> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// Adds gaps found by a later sync, keeping the report sorted and free of duplicates.
+    pub fn merge(&mut self, gaps: Vec<Gap>) {
+        let merged = self.gaps.drain(..).chain(gaps).collect::<BTreeSet<_>>();
+        self.gaps = merged.into_iter().collect();
+        self.updated = Utc::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let detector = GapDetector::new(Duration::minutes(1));
+        let df = df!(
+            Column::OPEN_TIME => &[minute(0), minute(1), minute(4), minute(5), minute(7)]
+        )
+        .unwrap();
+
+        let gaps = detector.detect(&df, None).unwrap();
+
+        assert_eq!(2, gaps.len());
+        assert_eq!(minute(2), gaps[0].from);
+        assert_eq!(minute(3), gaps[0].to);
+        assert_eq!(2, gaps[0].bars(Duration::minutes(1)));
+        assert_eq!(minute(6), gaps[1].from);
+        assert_eq!(minute(6), gaps[1].to);
+    }
+
+    #[test]
+    fn test_detect_after() {
+        let detector = GapDetector::new(Duration::minutes(1));
+        let df = df!(Column::OPEN_TIME => &[minute(3), minute(4)]).unwrap();
+
+        assert!(detector.detect(&df, Some(minute(2))).unwrap().is_empty());
+
+        let gaps = detector.detect(&df, Some(minute(0))).unwrap();
+        assert_eq!(1, gaps.len());
+        assert_eq!(minute(1), gaps[0].from);
+        assert_eq!(minute(2), gaps[0].to);
+    }
+
+    #[test]
+    fn test_dates_for() {
+        let detector = GapDetector::new(Duration::minutes(1));
+        let gaps = vec![
+            Gap {
+                from: minute(0),
+                to: minute(60 * 24 + 5),
+            },
+            Gap {
+                from: minute(60 * 24 + 10),
+                to: minute(60 * 24 + 20),
+            },
+        ];
+
+        let dates = detector.dates_for(&gaps);
+
+        assert_eq!(2, dates.len());
+        assert_eq!(NaiveDate::from_ymd_opt(2023, 1, 1).unwrap(), dates[0]);
+        assert_eq!(NaiveDate::from_ymd_opt(2023, 1, 2).unwrap(), dates[1]);
+    }
+
+    fn minute(minute: i64) -> NaiveDateTime {
+        datetime::create_utc(2023, 1, 1).naive_utc() + Duration::minutes(minute)
+    }
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+
+use crate::data::config::DataConfig;
+use crate::data::exchange::ExchangeId;
+use crate::data::AssetCategory;
+
+const LEGACY_RAW_DIR: &str = "binance/klines";
+
+/// Moves the archives and stores of the layout from before exchanges were
+/// supported below `<exchange>/<asset category>/`. Binance was the only
+/// exchange then, `base_raw_dir` defaulted to `.../binance/klines` and
+/// `base_store_dir` to `.../store/binance`, both holding the asset categories
+/// directly. Returns the number of directories moved.
+pub fn migrate_legacy_layout(config: &DataConfig) -> Result<usize> {
+    if config.exchange != ExchangeId::Binance {
+        return Ok(0);
+    }
+
+    let base_raw_dir = Path::new(&config.base_raw_dir);
+    let base_store_dir = Path::new(&config.base_store_dir);
+    let exchange = ExchangeId::Binance.as_str();
+    let mut moved = 0;
+    for asset_cat in [
+        AssetCategory::Spot,
+        AssetCategory::Usdm,
+        AssetCategory::Coinm,
+    ] {
+        let cat = asset_cat.as_str();
+        let raw_dir = base_raw_dir.join(exchange).join(cat);
+        // The old base_raw_dir kept in the config, or the old archives below the new one
+        for legacy_dir in [
+            base_raw_dir.join(cat),
+            base_raw_dir.join(LEGACY_RAW_DIR).join(cat),
+        ] {
+            moved += move_dir(&legacy_dir, &raw_dir)? as usize;
+        }
+
+        let store_dir = base_store_dir.join(exchange).join(cat);
+        moved += move_dir(&base_store_dir.join(cat), &store_dir)? as usize;
+    }
+    Ok(moved)
+}
+
+fn move_dir(from: &Path, to: &Path) -> Result<bool> {
+    if !from.is_dir() {
+        return Ok(false);
+    }
+    if to.exists() {
+        log::warn!(
+            "Both {:?} and {:?} exist, move the files of the old layout manually",
+            from,
+            to
+        );
+        return Ok(false);
+    }
+
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(from, to)?;
+    log::info!("Moved {:?} to {:?}", from, to);
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_legacy_layout() {
+        let base_dir = std::env::temp_dir().join("qrust-layout-test");
+        let _ = fs::remove_dir_all(&base_dir);
+        let mut config = DataConfig::builder()
+            .asset_cat(AssetCategory::Usdm)
+            .build()
+            .unwrap();
+        config.base_raw_dir = base_dir.join("raw").to_string_lossy().to_string();
+        config.base_store_dir = base_dir.join("store").to_string_lossy().to_string();
+
+        let legacy_raw = base_dir.join("raw/binance/klines/um/monthly/BTCUSDT");
+        let legacy_store = base_dir.join("store/spot/BTCUSDT");
+        fs::create_dir_all(&legacy_raw).unwrap();
+        fs::create_dir_all(&legacy_store).unwrap();
+        fs::write(legacy_raw.join("BTCUSDT-1m-2023-04.csv"), "").unwrap();
+        fs::write(legacy_store.join("BTCUSDT.parquet"), "").unwrap();
+
+        assert_eq!(2, migrate_legacy_layout(&config).unwrap());
+        assert!(base_dir
+            .join("raw/binance/um/monthly/BTCUSDT/BTCUSDT-1m-2023-04.csv")
+            .exists());
+        assert!(base_dir
+            .join("store/binance/spot/BTCUSDT/BTCUSDT.parquet")
+            .exists());
+        assert!(!legacy_raw.exists());
+        assert_eq!(0, migrate_legacy_layout(&config).unwrap());
+
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+}
@@ -0,0 +1,224 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::data::checksum::{ChecksumVerifier, StreamingDigest};
+use crate::data::exchange::ExchangeId;
+use crate::data::timeframe::ResampleOptions;
+
+/// Bumped whenever the columns or layout of the store files change,
+/// so that stores written by an older version are rebuilt on sync.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Summary of one timeframe of a store, as found in its parquet file(s).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimeframeMetadata {
+    pub first_open_time: Option<NaiveDateTime>,
+    pub last_open_time: Option<NaiveDateTime>,
+    pub rows: usize,
+    pub content_hash: String,
+    /// The digest of each store file followed by its size and modification time,
+    /// so that unchanged files are not re-hashed by the next sync.
+    #[serde(default)]
+    pub file_digests: BTreeMap<String, String>,
+    /// How the timeframe was resampled, `None` for the 1m bars.
+    #[serde(default)]
+    pub resample: Option<ResampleOptions>,
+}
+
+/// Sidecar record kept next to the parquet files of a symbol.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreMetadata {
+    pub schema_version: u32,
+    #[serde(default)]
+    pub exchange: ExchangeId,
+    pub asset_cat: String,
+    pub symbol: String,
+    pub last_sync: DateTime<Utc>,
+    pub gap_count: usize,
+    pub sources: BTreeSet<String>,
+    pub timeframes: BTreeMap<String, TimeframeMetadata>,
+}
+
+impl StoreMetadata {
+    pub fn new(exchange: ExchangeId, asset_cat: &str, symbol: &str) -> StoreMetadata {
+        StoreMetadata {
+            schema_version: SCHEMA_VERSION,
+            exchange,
+            asset_cat: asset_cat.to_string(),
+            symbol: symbol.to_string(),
+            last_sync: Utc::now(),
+            gap_count: 0,
+            sources: BTreeSet::new(),
+            timeframes: BTreeMap::new(),
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<StoreMetadata> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    /// A store is stale if it was written with another schema, misses one of the
+    /// expected timeframes or was not synced since the start of the current UTC day.
+    pub fn is_stale(&self, timeframes: &[String], now: &DateTime<Utc>) -> bool {
+        let today = now.date_naive().and_hms_opt(0, 0, 0).unwrap();
+        self.schema_version != SCHEMA_VERSION
+            || timeframes
+                .iter()
+                .any(|tf| !self.timeframes.contains_key(tf))
+            || self.last_sync.naive_utc() < today
+    }
+
+    /// Hashes the given store files in order. A single file gets its plain
+    /// sha256, multiple partitions get the sha256 of their digests. Digests in
+    /// `previous` are reused for files whose size and modification time are
+    /// unchanged. Returns the hash with the file digests to keep for next time.
+    pub fn content_hash_of(
+        paths: &[PathBuf],
+        previous: &BTreeMap<String, String>,
+    ) -> Result<(String, BTreeMap<String, String>)> {
+        let verifier = ChecksumVerifier::new();
+        let mut file_digests = BTreeMap::new();
+        let mut digests = Vec::new();
+        for path in paths {
+            let name = path.to_string_lossy().to_string();
+            let stat = verifier.stat_of(path)?;
+            let digest = match previous
+                .get(&name)
+                .and_then(|recorded| recorded.split_once("  "))
+            {
+                Some((digest, recorded_stat)) if recorded_stat == stat => digest.to_string(),
+                _ => verifier.digest(path)?,
+            };
+            file_digests.insert(name, format!("{}  {}", digest, stat));
+            digests.push(digest);
+        }
+
+        let content_hash = match digests.as_slice() {
+            [digest] => digest.clone(),
+            _ => {
+                let mut digest = StreamingDigest::new();
+                for file_digest in digests.iter() {
+                    digest.update(file_digest.as_bytes());
+                }
+                digest.finish()
+            }
+        };
+        Ok((content_hash, file_digests))
+    }
+
+    /// A cheap stand-in for `content_hash_of`, hashing the paths with the size
+    /// and modification time of the files instead of their content.
+    pub fn stamp_of(paths: &[PathBuf]) -> Result<String> {
+        let verifier = ChecksumVerifier::new();
+        let mut digest = StreamingDigest::new();
+        for path in paths {
+            digest.update(path.to_string_lossy().as_bytes());
+            digest.update(verifier.stat_of(path)?.as_bytes());
+        }
+        Ok(digest.finish())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::datetime;
+
+    #[test]
+    fn test_is_stale() {
+        let timeframes = vec!["1m".to_string(), "1h".to_string()];
+        let mut metadata = StoreMetadata::new(ExchangeId::Binance, "spot", "BTCUSDT");
+        metadata.last_sync = datetime::create_utc(2023, 5, 2);
+        for tf in timeframes.iter() {
+            metadata.timeframes.insert(
+                tf.clone(),
+                TimeframeMetadata {
+                    first_open_time: None,
+                    last_open_time: None,
+                    rows: 0,
+                    content_hash: String::new(),
+                    file_digests: BTreeMap::new(),
+                    resample: None,
+                },
+            );
+        }
+
+        assert!(!metadata.is_stale(&timeframes, &datetime::create_utc(2023, 5, 2)));
+        assert!(metadata.is_stale(&timeframes, &datetime::create_utc(2023, 5, 3)));
+        assert!(metadata.is_stale(&["4h".to_string()], &datetime::create_utc(2023, 5, 2)));
+
+        metadata.schema_version = 0;
+        assert!(metadata.is_stale(&timeframes, &datetime::create_utc(2023, 5, 2)));
+    }
+
+    #[test]
+    fn test_read_write() {
+        let path = std::env::temp_dir().join("qrust-metadata-test.json");
+        let mut metadata = StoreMetadata::new(ExchangeId::Binance, "spot", "BTCUSDT");
+        metadata
+            .sources
+            .insert("BTCUSDT-1m-2023-04.zip".to_string());
+        metadata.write(&path).unwrap();
+
+        let read = StoreMetadata::read(&path).unwrap();
+        assert_eq!(metadata.symbol, read.symbol);
+        assert_eq!(metadata.sources, read.sources);
+        assert_eq!(SCHEMA_VERSION, read.schema_version);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_stamp_of() {
+        let path = std::env::temp_dir().join("qrust-stamp-test.parquet");
+        fs::write(&path, "abc").unwrap();
+        let paths = [path.clone()];
+        let stamp = StoreMetadata::stamp_of(&paths).unwrap();
+        assert_eq!(stamp, StoreMetadata::stamp_of(&paths).unwrap());
+
+        fs::write(&path, "abcd").unwrap();
+        assert_ne!(stamp, StoreMetadata::stamp_of(&paths).unwrap());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_content_hash_of() {
+        let path = std::env::temp_dir().join("qrust-content-hash-test.parquet");
+        fs::write(&path, "abc").unwrap();
+        let paths = [path.clone()];
+        let (hash, file_digests) =
+            StoreMetadata::content_hash_of(&paths, &BTreeMap::new()).unwrap();
+        assert_eq!(ChecksumVerifier::new().digest(&path).unwrap(), hash);
+
+        // An unchanged stat reuses the recorded digest
+        let mut recorded = file_digests.clone();
+        let stat = ChecksumVerifier::new().stat_of(&path).unwrap();
+        recorded.insert(
+            path.to_string_lossy().to_string(),
+            format!("{}  {}", "0".repeat(64), stat),
+        );
+        let (reused, _) = StoreMetadata::content_hash_of(&paths, &recorded).unwrap();
+        assert_eq!("0".repeat(64), reused);
+
+        fs::write(&path, "abcd").unwrap();
+        let (changed, _) = StoreMetadata::content_hash_of(&paths, &recorded).unwrap();
+        assert_ne!(hash, changed);
+        assert_ne!("0".repeat(64), changed);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
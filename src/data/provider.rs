@@ -1,25 +1,37 @@
 use log::{debug, error, info, warn};
 use std::fs;
 use std::fs::{read_dir, File};
-use std::io::Write;
-use std::path::PathBuf;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use ::zip::ZipArchive;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use chrono::{DateTime, Datelike, Days, Duration, Months, NaiveDateTime, Utc};
+use flate2::read::GzDecoder;
+use futures::stream::{self, StreamExt};
 use polars::prelude::*;
-use reqwest::Url;
+use reqwest::{Client, Response, Url};
 use serde_json::Value as JsonValue;
-use url;
+use tokio::fs::File as AsyncFile;
+use tokio::io::AsyncWriteExt;
+use tokio::task;
 
+use crate::data::bars::BarBuilder;
+use crate::data::checksum::{ChecksumMismatch, ChecksumVerifier, StreamingDigest};
 use crate::data::config::DataConfig;
-use crate::data::sanitizer::{CsvSanitizer, DataFrameSanitizer};
-use crate::data::{AssetCategory, Symbol};
+use crate::data::dataset::Dataset;
+use crate::data::exchange::Exchange;
+use crate::data::retry::{RateLimiter, RetryPolicy};
+use crate::data::sanitizer::{DataFrameSanitizer, QualityReport};
+use crate::data::symbols::{archived_from, archived_months_from, listing_from, SymbolsCache};
+use crate::data::{AssetCategory, Column, Symbol};
 use crate::extensions::datetime;
 
-const DEFAULT_TIMEFRAME: &str = "1m";
+pub const DEFAULT_TIMEFRAME: &str = "1m";
+const CHECKSUM_EXT: &str = ".CHECKSUM";
 
-#[derive(PartialEq)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Timeperiod {
     Daily,
     Monthly,
@@ -37,148 +49,733 @@ impl Timeperiod {
 pub struct SymbolsProvider {
     config: DataConfig,
     asset_cat: AssetCategory,
+    exchange: Arc<dyn Exchange>,
 }
 
 impl SymbolsProvider {
-    const KEY_INITDATE: &'static str = "onboardDate";
-
     pub fn new(config: DataConfig, asset_cat: AssetCategory) -> SymbolsProvider {
-        SymbolsProvider { config, asset_cat }
+        let exchange = config.exchange.exchange();
+        SymbolsProvider {
+            config,
+            asset_cat,
+            exchange,
+        }
     }
 
+    /// Fetches the listed symbols with their metadata and merges them into the
+    /// local cache, which keeps delisted symbols as well as symbols only found
+    /// in the archives. Falls back to the cache if exchangeInfo can not be fetched.
     pub async fn get(&self) -> Result<Vec<Symbol>> {
-        let uri = &self.config.info_uri;
-        let response = reqwest::get(uri).await?;
-        let json: JsonValue = response.json().await?;
+        let cache_path = self.cache_path();
+        let live = match self.fetch().await {
+            Ok(live) => live,
+            Err(e) => {
+                warn!("Failed to fetch symbols, reading {:?}: {}", cache_path, e);
+                return SymbolsCache::read(&cache_path);
+            }
+        };
+        let archived = self.archived().await.unwrap_or_else(|e| {
+            warn!("Failed to list archived symbols: {}", e);
+            Vec::new()
+        });
+        let cached = self.cached().unwrap_or_default();
 
-        let symbols: Vec<Symbol> = json["symbols"]
-            .as_array()
-            .unwrap()
+        let mut symbols = SymbolsCache::merge(cached, live, archived);
+        self.date_delisted(&mut symbols).await;
+        SymbolsCache::write(&cache_path, &symbols)?;
+        Ok(symbols)
+    }
+
+    /// The symbols of the last successful `get`.
+    pub fn cached(&self) -> Result<Vec<Symbol>> {
+        SymbolsCache::read(&self.cache_path())
+    }
+
+    /// The cached symbols that were tradable at the date, delisted ones included.
+    pub fn universe_at(&self, date: &DateTime<Utc>) -> Result<Vec<Symbol>> {
+        Ok(SymbolsCache::universe_at(&self.cached()?, date))
+    }
+
+    fn cache_path(&self) -> PathBuf {
+        SymbolsCache::path_for(&self.config)
+    }
+
+    /// Names of all symbols with monthly kline archives, listed or not.
+    /// Only the Binance archives can be listed.
+    async fn archived(&self) -> Result<Vec<String>> {
+        let list_uri = match &self.config.archive_list_uri {
+            Some(list_uri) => list_uri,
+            None => return Ok(Vec::new()),
+        };
+        let prefix = Url::parse(&self.config.hist_klines_monthly_uri)?
+            .path()
+            .trim_start_matches('/')
+            .to_string();
+
+        let mut names = Vec::new();
+        let mut marker = String::new();
+        loop {
+            let uri = Url::parse_with_params(
+                list_uri,
+                &[("delimiter", "/"), ("prefix", &prefix), ("marker", &marker)],
+            )?;
+            let listing = reqwest::get(uri).await?.error_for_status()?.text().await?;
+            let (mut page, next) = archived_from(&listing);
+            names.append(&mut page);
+
+            match next {
+                Some(next) => marker = next,
+                None => break,
+            }
+        }
+        Ok(names)
+    }
+
+    /// Dates the delisted symbols without a delisting date by their first and
+    /// last monthly kline archive. Symbols that cannot be dated stay undated.
+    async fn date_delisted(&self, symbols: &mut [Symbol]) {
+        if self.config.archive_list_uri.is_none() {
+            return;
+        }
+
+        let undated = symbols
             .iter()
-            .map(|item| Symbol {
-                name: item["symbol"].as_str().unwrap().to_string(),
-                initdate: self.initdate_for(item),
+            .filter(|symbol| symbol.is_delisted() && symbol.delistdate.is_none())
+            .map(|symbol| symbol.name.clone())
+            .collect::<Vec<_>>();
+        let listings = stream::iter(undated)
+            .map(|name| async move {
+                let listing = self.archived_listing_of(&name).await;
+                (name, listing)
             })
-            .collect();
+            .buffer_unordered(self.config.max_concurrent_downloads.max(1))
+            .collect::<Vec<_>>()
+            .await;
 
-        Ok(symbols)
+        for (name, listing) in listings {
+            match listing {
+                Ok(Some((initdate, delistdate))) => {
+                    let symbol = symbols.iter_mut().find(|s| s.name == name).unwrap();
+                    symbol.initdate = initdate;
+                    symbol.delistdate = Some(delistdate);
+                }
+                Ok(None) => warn!("No monthly archives found to date {}", name),
+                Err(e) => warn!("Failed to list the archives of {}: {}", name, e),
+            }
+        }
     }
 
-    fn initdate_for(&self, json: &JsonValue) -> DateTime<Utc> {
-        if let Some(initdate) = json.get(Self::KEY_INITDATE) {
-            let mut timestamp = initdate.as_i64().unwrap();
-            if timestamp.to_string().len() <= 10 {
-                timestamp *= 1000;
+    /// The listing dates implied by the monthly 1m kline archives of the symbol.
+    async fn archived_listing_of(
+        &self,
+        symbol: &str,
+    ) -> Result<Option<(DateTime<Utc>, DateTime<Utc>)>> {
+        let list_uri = self
+            .config
+            .archive_list_uri
+            .as_ref()
+            .ok_or(anyhow!("No archive list URI configured"))?;
+        let prefix = Url::parse(&self.config.hist_klines_monthly_uri)?
+            .path()
+            .trim_start_matches('/')
+            .to_string();
+        let prefix = format!("{}{}/{}/", prefix, symbol, DEFAULT_TIMEFRAME);
+
+        let mut months = Vec::new();
+        let mut marker = String::new();
+        loop {
+            let uri =
+                Url::parse_with_params(list_uri, &[("prefix", &prefix), ("marker", &marker)])?;
+            let listing = reqwest::get(uri).await?.error_for_status()?.text().await?;
+            let (mut page, next) = archived_months_from(&listing);
+            months.append(&mut page);
+
+            match next {
+                Some(next) => marker = next,
+                None => break,
             }
-            DateTime::from_utc(
-                NaiveDateTime::from_timestamp_millis(timestamp).unwrap(),
-                Utc,
-            )
-        } else {
-            datetime::create_utc(2017, 1, 1)
         }
+        Ok(listing_from(&months))
+    }
+
+    async fn fetch(&self) -> Result<Vec<Symbol>> {
+        let uri = self.exchange.info_uri_for(&self.config)?;
+        let response = reqwest::get(uri).await?.error_for_status()?;
+        let json: JsonValue = response.json().await?;
+        self.symbols_from(&json)
+    }
+
+    fn symbols_from(&self, json: &JsonValue) -> Result<Vec<Symbol>> {
+        self.exchange.symbols_from(json, &self.asset_cat)
+    }
+}
+
+#[derive(Debug)]
+pub enum FetchStatus {
+    Fetched,
+    Existing,
+    Missing,
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct FetchResult {
+    pub file: String,
+    pub timeperiod: Timeperiod,
+    pub status: FetchStatus,
+}
+
+impl FetchResult {
+    pub fn is_failed(&self) -> bool {
+        matches!(self.status, FetchStatus::Failed(_))
     }
 }
 
 pub struct DataProvider {
     config: DataConfig,
     asset_cat: AssetCategory,
-    csv_sanitizer: CsvSanitizer,
+    exchange: Arc<dyn Exchange>,
+    client: Client,
     df_sanitizer: DataFrameSanitizer,
+    checksum_verifier: ChecksumVerifier,
+    retry_policy: RetryPolicy,
+    rate_limiter: &'static RateLimiter,
+    quality_reports: Mutex<Vec<QualityReport>>,
 }
 
 impl DataProvider {
+    const MAX_CHECKSUM_ATTEMPTS: usize = 3;
+    const KLINES_LIMIT: usize = 1000;
+    const KLINE_MILLIS: i64 = 60_000;
+    const FUNDING_RATES_LIMIT: usize = 1000;
+
     pub fn new(config: DataConfig, asset_cat: AssetCategory) -> DataProvider {
+        let retry_policy = RetryPolicy::new(&config);
+        let rate_limiter = RateLimiter::global(config.max_requests_per_minute);
+        let exchange = config.exchange.exchange();
         DataProvider {
             config,
             asset_cat,
-            csv_sanitizer: CsvSanitizer::new(),
+            exchange,
+            client: Client::new(),
             df_sanitizer: DataFrameSanitizer::new(),
+            checksum_verifier: ChecksumVerifier::new(),
+            retry_policy,
+            rate_limiter,
+            quality_reports: Mutex::new(Vec::new()),
         }
     }
 
-    pub fn sync(&self, symbol: &str, init_date: &DateTime<Utc>) -> Result<()> {
-        self.sync_internal(symbol, init_date, Timeperiod::Monthly)?;
+    /// Fetches all monthly kline archives since the init date and the daily archives
+    /// of the current month, with up to `max_concurrent_downloads` files in flight.
+    pub async fn sync(&self, symbol: &str, init_date: &DateTime<Utc>) -> Result<Vec<FetchResult>> {
+        self.sync_dataset(symbol, Dataset::Klines, init_date).await
+    }
 
+    /// Same as `sync` for any dataset. Datasets only published daily
+    /// are fetched day by day from the init date on. Datasets the exchange
+    /// builds from another one, see `Exchange::source_of`, fetch that one.
+    pub async fn sync_dataset(
+        &self,
+        symbol: &str,
+        dataset: Dataset,
+        init_date: &DateTime<Utc>,
+    ) -> Result<Vec<FetchResult>> {
         let now = Utc::now();
-        let initdate = datetime::create_utc(now.year(), now.month(), 1);
-        self.sync_internal(symbol, &initdate, Timeperiod::Daily)?;
+        let dataset = self.exchange.source_of(dataset);
+        let timeperiods = self.exchange.timeperiods(dataset);
+        let initdate = if timeperiods.contains(&Timeperiod::Monthly) {
+            datetime::create_utc(now.year(), now.month(), 1)
+        } else {
+            *init_date
+        };
 
-        Ok(())
+        let mut jobs = Vec::new();
+        for timeperiod in timeperiods {
+            let initdate = match timeperiod {
+                Timeperiod::Monthly => init_date,
+                Timeperiod::Daily => &initdate,
+            };
+            jobs.append(&mut self.jobs_for(symbol, dataset, initdate, *timeperiod)?);
+        }
+
+        let results = stream::iter(jobs)
+            .map(|(timeperiod, date)| self.fetch(symbol, dataset, timeperiod, date))
+            .buffer_unordered(self.config.max_concurrent_downloads.max(1))
+            .collect::<Vec<_>>()
+            .await;
+
+        Ok(results)
     }
 
-    fn sync_internal(
+    fn jobs_for(
         &self,
         symbol: &str,
+        dataset: Dataset,
         init_date: &DateTime<Utc>,
         timeperiod: Timeperiod,
-    ) -> Result<()> {
+    ) -> Result<Vec<(Timeperiod, DateTime<Utc>)>> {
         let fromdate = self.fromdate_for(init_date, &timeperiod);
         let todate = Utc::now() - Duration::days(1);
         let dates = self.dates_for(&fromdate, &todate, &timeperiod);
 
-        let base_path = self.base_path_for(symbol, &timeperiod);
+        let base_path = self.base_path_for(symbol, dataset, &timeperiod);
         if !base_path.exists() {
             std::fs::create_dir_all(base_path)?;
         }
 
-        for date in dates {
-            if let Err(e) = self.fetch(symbol, &timeperiod, &date) {
-                error!("Error fetching data for {}", symbol);
-                debug!("Error: {}", e);
+        Ok(dates.into_iter().map(|date| (timeperiod, date)).collect())
+    }
+
+    async fn fetch(
+        &self,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: Timeperiod,
+        date: DateTime<Utc>,
+    ) -> FetchResult {
+        let zipname = self.file_name_for(symbol, dataset, &timeperiod, &date);
+
+        let status = match self
+            .fetch_internal(symbol, dataset, &timeperiod, &date, &zipname)
+            .await
+        {
+            Ok(status) => status,
+            Err(e) => {
+                match e.downcast_ref::<ChecksumMismatch>() {
+                    Some(mismatch) => error!("{}", mismatch),
+                    None => error!("Error fetching {}: {}", zipname, e),
+                }
+                FetchStatus::Failed(e.to_string())
             }
-        }
+        };
 
-        Ok(())
+        FetchResult {
+            file: zipname,
+            timeperiod,
+            status,
+        }
     }
 
-    fn fetch(&self, symbol: &str, timeperiod: &Timeperiod, date: &DateTime<Utc>) -> Result<()> {
-        let basepath = self.base_path_for(symbol, timeperiod);
-        let baseuri = self.base_uri_for(timeperiod);
-        let dateformat = self.date_format_for(timeperiod);
-        let zipname = self.file_name_for(symbol, date, dateformat);
-        let csvname = zipname.replace(".zip", ".csv");
+    async fn fetch_internal(
+        &self,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+        zipname: &str,
+    ) -> Result<FetchStatus> {
+        let dataset = self.exchange.source_of(dataset);
+        let basepath = self.base_path_for(symbol, dataset, timeperiod);
+        let csvname = Self::csv_name_for(zipname);
 
         let csvpath = basepath.join(csvname);
         if csvpath.exists() {
-            debug!("{} already exists", csvpath.to_str().unwrap());
-            return Ok(());
+            match self.checksum_verifier.check_recorded(&csvpath) {
+                Ok(_) => {
+                    debug!("{} already exists", csvpath.to_str().unwrap());
+                    return Ok(FetchStatus::Existing);
+                }
+                Err(e) => warn!("{}, fetching again", e),
+            }
         }
 
-        let fileuri = self.uri_for(baseuri, symbol, &zipname)?.to_string();
-        let response = reqwest::blocking::get(fileuri).map_err(|e| {
-            error!("Could not get content for {}", zipname);
-            e
-        })?;
+        let fileuri = self.uri_for(symbol, dataset, timeperiod, date)?;
+        let checksumuri = Url::parse(&format!("{}{}", fileuri, CHECKSUM_EXT))?;
+        let zippath = basepath.join(zipname);
 
-        let status = response.status();
-        if !status.is_success() {
-            warn!("Could not fetch {}", zipname);
-            return Ok(());
+        for attempt in 1..=Self::MAX_CHECKSUM_ATTEMPTS {
+            let download = self
+                .download(&fileuri, zipname, |response| {
+                    Self::write_to(response, &zippath)
+                })
+                .await;
+            let actual = match download {
+                Ok(Some(actual)) => actual,
+                Ok(None) => {
+                    warn!("Could not fetch {}", zipname);
+                    return Ok(FetchStatus::Missing);
+                }
+                Err(e) => {
+                    self.remove_partial(&zippath).await;
+                    return Err(e);
+                }
+            };
+            if !self.exchange.has_checksums() {
+                break;
+            }
+
+            let expected = match self.fetch_checksum(&checksumuri, zipname).await {
+                Ok(expected) => expected,
+                Err(e) => {
+                    self.remove_partial(&zippath).await;
+                    return Err(e);
+                }
+            };
+
+            match self.checksum_verifier.check(&zippath, &expected, &actual) {
+                Ok(_) => break,
+                Err(e) => {
+                    self.remove_partial(&zippath).await;
+                    if attempt == Self::MAX_CHECKSUM_ATTEMPTS {
+                        return Err(e);
+                    }
+                    warn!(
+                        "{} (attempt {}/{})",
+                        e,
+                        attempt,
+                        Self::MAX_CHECKSUM_ATTEMPTS
+                    );
+                }
+            }
         }
 
-        let content = response.bytes()?;
-        let zippath = basepath.join(&zipname);
+        let exchange = Arc::clone(&self.exchange);
+        let asset_cat = self.asset_cat.clone();
+        task::spawn_blocking(move || {
+            Self::unpack(
+                &*exchange, dataset, &asset_cat, &zippath, &basepath, &csvpath,
+            )
+        })
+        .await??;
 
-        self.create_zipfile(&zippath, &content)?;
-        self.extract_zipfile(&zippath, &basepath)?;
-        self.csv_sanitizer.run(&csvpath)?;
-        fs::remove_file(&zippath)?;
+        info!("Fetched {}", zipname);
+        Ok(FetchStatus::Fetched)
+    }
 
-        info!("Fetched {}", &zipname);
-        Ok(())
+    /// Fetches the daily archive of the given date, if not there yet, and loads it.
+    pub async fn load_daily(
+        &self,
+        symbol: &str,
+        date: &DateTime<Utc>,
+    ) -> Result<Option<DataFrame>> {
+        let basepath = self.base_path_for(symbol, Dataset::Klines, &Timeperiod::Daily);
+        if !basepath.exists() {
+            std::fs::create_dir_all(&basepath)?;
+        }
+
+        let result = self
+            .fetch(symbol, Dataset::Klines, Timeperiod::Daily, *date)
+            .await;
+        match result.status {
+            FetchStatus::Fetched | FetchStatus::Existing => {}
+            FetchStatus::Missing => return Ok(None),
+            FetchStatus::Failed(e) => return Err(anyhow!(e)),
+        }
+
+        let csvpath = basepath.join(Self::csv_name_for(&result.file));
+        Ok(self.load_paths(&[csvpath])?.pop())
     }
 
-    fn create_zipfile(&self, zippath: &PathBuf, content: &bytes::Bytes) -> Result<()> {
-        let mut zipfile = File::create(zippath)?;
-        zipfile.write_all(content)?;
-        Ok(())
+    /// Fetches the 1m klines with open times between `from` and `to` (inclusive)
+    /// from the REST endpoint, paging through `KLINES_LIMIT` klines per request.
+    /// Exchanges whose klines are built from trades have no such endpoint.
+    pub async fn fetch_klines(
+        &self,
+        symbol: &str,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<Option<DataFrame>> {
+        if self.exchange.source_of(Dataset::Klines) != Dataset::Klines {
+            debug!("No klines endpoint for {}", self.exchange.id().as_str());
+            return Ok(None);
+        }
+
+        let endtime = datetime::to_utc(to).timestamp_millis();
+        let mut starttime = datetime::to_utc(from).timestamp_millis();
+
+        let mut klines = Vec::new();
+        while starttime <= endtime {
+            let uri = self.klines_uri_for(symbol, starttime, endtime)?;
+            let json = self
+                .download(&uri, symbol, |response| async move {
+                    Ok(response.json::<JsonValue>().await?)
+                })
+                .await?;
+
+            let page = match json {
+                Some(JsonValue::Array(page)) if !page.is_empty() => page,
+                _ => break,
+            };
+
+            let last_open_time = page.last().and_then(|kline| kline[0].as_i64());
+            starttime = match last_open_time {
+                Some(open_time) => open_time + Self::KLINE_MILLIS,
+                None => break,
+            };
+            klines.extend(page);
+        }
+
+        if klines.is_empty() {
+            return Ok(None);
+        }
+
+        let mut df = self.klines_frame(&klines)?;
+        self.df_sanitizer.run(&mut df)?;
+        let source = format!("klines:{}:{}", symbol, from);
+        self.validate(&source, &mut df)?;
+        info!("Fetched {} klines of {} from {}", df.height(), symbol, from);
+        Ok(Some(df))
+    }
+
+    /// Builds a frame with the same columns as the archive CSVs from REST klines,
+    /// which are arrays with numbers for times and counts and strings for the rest.
+    fn klines_frame(&self, klines: &[JsonValue]) -> Result<DataFrame> {
+        let ints_at = |index: usize| {
+            klines
+                .iter()
+                .map(|kline| kline[index].as_i64())
+                .collect::<Vec<_>>()
+        };
+        let floats_at = |index: usize| {
+            klines
+                .iter()
+                .map(|kline| kline[index].as_str().and_then(|v| v.parse::<f64>().ok()))
+                .collect::<Vec<_>>()
+        };
+        let ignore = klines
+            .iter()
+            .map(|kline| kline[11].as_str().and_then(|v| v.parse::<i64>().ok()))
+            .collect::<Vec<_>>();
+
+        let df = DataFrame::new(vec![
+            Series::new(Column::OPEN_TIME, ints_at(0)),
+            Series::new(Column::OPEN, floats_at(1)),
+            Series::new(Column::HIGH, floats_at(2)),
+            Series::new(Column::LOW, floats_at(3)),
+            Series::new(Column::CLOSE, floats_at(4)),
+            Series::new(Column::VOLUME, floats_at(5)),
+            Series::new(Column::CLOSE_TIME, ints_at(6)),
+            Series::new(Column::QUOTE_VOLUME, floats_at(7)),
+            Series::new(Column::COUNT, ints_at(8)),
+            Series::new(Column::TAKER_BUY_VOLUME, floats_at(9)),
+            Series::new(Column::TAKER_BUY_QUOTE_VOLUME, floats_at(10)),
+            Series::new(Column::IGNORE, ignore),
+        ])?;
+        Ok(df)
     }
 
-    fn extract_zipfile(&self, zippath: &PathBuf, basepath: &PathBuf) -> Result<()> {
+    fn klines_uri_for(&self, symbol: &str, starttime: i64, endtime: i64) -> Result<Url> {
+        let mut uri = Url::parse(self.config.klines_uri.trim_end_matches('/'))?;
+        uri.query_pairs_mut()
+            .append_pair("symbol", &symbol.to_uppercase())
+            .append_pair("interval", DEFAULT_TIMEFRAME)
+            .append_pair("startTime", &starttime.to_string())
+            .append_pair("endTime", &endtime.to_string())
+            .append_pair("limit", &Self::KLINES_LIMIT.to_string());
+        Ok(uri)
+    }
+
+    /// Fetches the funding events between `from` and `to` (inclusive) from the REST
+    /// endpoint, for the current month which has no monthly archive yet.
+    /// The frame has the columns of the `fundingRate` archives.
+    pub async fn fetch_funding_rates(
+        &self,
+        symbol: &str,
+        from: &NaiveDateTime,
+        to: &NaiveDateTime,
+    ) -> Result<Option<DataFrame>> {
+        let endtime = datetime::to_utc(to).timestamp_millis();
+        let mut starttime = datetime::to_utc(from).timestamp_millis();
+
+        let mut events = Vec::new();
+        while starttime <= endtime {
+            let uri = self.funding_rates_uri_for(symbol, starttime, endtime)?;
+            let json = self
+                .download(&uri, symbol, |response| async move {
+                    Ok(response.json::<JsonValue>().await?)
+                })
+                .await?;
+
+            let page = match json {
+                Some(JsonValue::Array(page)) if !page.is_empty() => page,
+                _ => break,
+            };
+
+            let last_funding_time = page.last().and_then(|event| event["fundingTime"].as_i64());
+            starttime = match last_funding_time {
+                Some(funding_time) => funding_time + 1,
+                None => break,
+            };
+            let is_last_page = page.len() < Self::FUNDING_RATES_LIMIT;
+            events.extend(page);
+            if is_last_page {
+                break;
+            }
+        }
+
+        if events.is_empty() {
+            return Ok(None);
+        }
+
+        let mut df = self.funding_rates_frame(&events)?;
+        self.df_sanitizer
+            .run_dataset(Dataset::FundingRate, &mut df)?;
+        info!(
+            "Fetched {} funding rates of {} from {}",
+            df.height(),
+            symbol,
+            from
+        );
+        Ok(Some(df))
+    }
+
+    /// The REST events lack the funding interval of the archives,
+    /// so it is derived from the time to the previous event.
+    fn funding_rates_frame(&self, events: &[JsonValue]) -> Result<DataFrame> {
+        const HOUR_MILLIS: i64 = 3_600_000;
+
+        let funding_times = events
+            .iter()
+            .map(|event| event["fundingTime"].as_i64())
+            .collect::<Vec<_>>();
+        let funding_rates = events
+            .iter()
+            .map(|event| {
+                event["fundingRate"]
+                    .as_str()
+                    .and_then(|v| v.parse::<f64>().ok())
+            })
+            .collect::<Vec<_>>();
+
+        let mut intervals = funding_times
+            .windows(2)
+            .map(|times| match times {
+                [Some(prev), Some(next)] => Some((next - prev + HOUR_MILLIS / 2) / HOUR_MILLIS),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        intervals.insert(0, intervals.first().copied().flatten());
+
+        let df = DataFrame::new(vec![
+            Series::new("calc_time", funding_times),
+            Series::new("funding_interval_hours", intervals),
+            Series::new("last_funding_rate", funding_rates),
+        ])?;
+        Ok(df)
+    }
+
+    fn funding_rates_uri_for(&self, symbol: &str, starttime: i64, endtime: i64) -> Result<Url> {
+        let funding_rate_uri = self.config.funding_rate_uri.as_deref().ok_or(anyhow!(
+            "No funding rates for {} symbols",
+            self.asset_cat.as_str()
+        ))?;
+        let mut uri = Url::parse(funding_rate_uri.trim_end_matches('/'))?;
+        uri.query_pairs_mut()
+            .append_pair("symbol", &symbol.to_uppercase())
+            .append_pair("startTime", &starttime.to_string())
+            .append_pair("endTime", &endtime.to_string())
+            .append_pair("limit", &Self::FUNDING_RATES_LIMIT.to_string());
+        Ok(uri)
+    }
+
+    /// Fetches the `.CHECKSUM` file of an archive and parses the digest in it.
+    async fn fetch_checksum(&self, checksumuri: &Url, zipname: &str) -> Result<String> {
+        let checksum = self
+            .download(checksumuri, zipname, |response| async move {
+                Ok(response.text().await?)
+            })
+            .await?
+            .ok_or(anyhow!("Could not fetch checksum for {}", zipname))?;
+        self.checksum_verifier.parse(&checksum)
+    }
+
+    /// Downloads the content at the given uri and hands the response to `read`,
+    /// retrying transient failures according to the retry policy.
+    /// Returns `None` if the file does not exist.
+    async fn download<T, F, Fut>(&self, uri: &Url, name: &str, read: F) -> Result<Option<T>>
+    where
+        F: Fn(Response) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            self.rate_limiter.acquire().await;
+
+            let (reason, delay) = match self.client.get(uri.clone()).send().await {
+                Ok(response) if response.status().is_success() => match read(response).await {
+                    Ok(content) => return Ok(Some(content)),
+                    Err(e) => (e.to_string(), Some(self.retry_policy.backoff_for(attempt))),
+                },
+                Ok(response) => {
+                    let status = response.status();
+                    if !self.retry_policy.is_retryable(status) {
+                        return Ok(None);
+                    }
+
+                    let retry_after = self.retry_policy.retry_after(response.headers());
+                    let delay =
+                        retry_after.unwrap_or_else(|| self.retry_policy.backoff_for(attempt));
+                    if self.retry_policy.is_throttled(status) {
+                        // Throttling applies to the whole IP, so hold back every provider
+                        self.rate_limiter.pause(delay);
+                        (status.to_string(), None)
+                    } else {
+                        (status.to_string(), Some(delay))
+                    }
+                }
+                Err(e) => (e.to_string(), Some(self.retry_policy.backoff_for(attempt))),
+            };
+
+            if !self.retry_policy.can_retry(attempt) {
+                error!("Could not get content for {}", name);
+                return Err(anyhow!(
+                    "Giving up on {} after {} attempt(s): {}",
+                    name,
+                    attempt,
+                    reason
+                ));
+            }
+
+            warn!(
+                "Could not get content for {}: {} (attempt {}/{})",
+                name, reason, attempt, self.retry_policy.max_attempts
+            );
+            if let Some(delay) = delay {
+                tokio::time::sleep(delay).await;
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Streams the response body to disk and returns the SHA-256 digest of its content.
+    async fn write_to(mut response: Response, path: &Path) -> Result<String> {
+        let mut file = AsyncFile::create(path).await?;
+        let mut digest = StreamingDigest::new();
+        while let Some(chunk) = response.chunk().await? {
+            digest.update(&chunk);
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok(digest.finish())
+    }
+
+    async fn remove_partial(&self, path: &Path) {
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            debug!("Could not remove {}: {}", path.display(), e);
+        }
+    }
+
+    fn unpack(
+        exchange: &dyn Exchange,
+        dataset: Dataset,
+        asset_cat: &AssetCategory,
+        zippath: &Path,
+        basepath: &Path,
+        csvpath: &Path,
+    ) -> Result<()> {
         let zipfile = File::open(zippath)?;
-        let mut archive = ZipArchive::new(zipfile)?;
-        archive.extract(basepath)?;
+        if zippath.extension().unwrap_or_default() == "gz" {
+            let mut csvfile = File::create(csvpath)?;
+            std::io::copy(&mut GzDecoder::new(zipfile), &mut csvfile)?;
+        } else {
+            let mut archive = ZipArchive::new(zipfile)?;
+            archive.extract(basepath)?;
+        }
+
+        exchange.normalize(dataset, asset_cat, csvpath)?;
+        ChecksumVerifier::new().record(csvpath)?;
+        fs::remove_file(zippath)?;
         Ok(())
     }
 
@@ -191,66 +788,186 @@ impl DataProvider {
         Ok(dfs)
     }
 
+    /// Loads the raw CSVs covering the period from the given date onwards.
+    pub fn load_since(&self, symbol: &str, since: &DateTime<Utc>) -> Result<Vec<DataFrame>> {
+        let fromdate = datetime::create_utc(since.year(), since.month(), since.day());
+        let todate = Utc::now() - Duration::days(1);
+        let mut paths = Vec::new();
+        for timeperiod in [Timeperiod::Monthly, Timeperiod::Daily] {
+            let basepath = self.base_path_for(symbol, Dataset::Klines, &timeperiod);
+            for date in self.dates_for(&fromdate, &todate, &timeperiod) {
+                let zipname = self.file_name_for(symbol, Dataset::Klines, &timeperiod, &date);
+                let path = basepath.join(Self::csv_name_for(&zipname));
+                if path.exists() {
+                    paths.push(path);
+                }
+            }
+        }
+        self.load_paths(&paths)
+    }
+
     fn load(&self, symbol: &str, timeperiod: &Timeperiod) -> Result<Vec<DataFrame>> {
-        let path = self.base_path_for(symbol, timeperiod);
+        let paths = self.csv_paths_for(symbol, Dataset::Klines, timeperiod)?;
+        self.load_paths(&paths)
+    }
+
+    /// Loads all raw CSVs of the dataset, monthly ones first. Klines are validated
+    /// as well, the other datasets only get their time column converted.
+    pub fn load_dataset(&self, symbol: &str, dataset: Dataset) -> Result<Vec<DataFrame>> {
+        if dataset == Dataset::Klines {
+            return self.load_all(symbol);
+        }
+
+        let mut dfs = Vec::new();
+        for path in self.dataset_paths(symbol, dataset)? {
+            dfs.push(self.load_dataset_path(dataset, &path)?);
+        }
+        Ok(dfs)
+    }
+
+    /// The raw CSVs of the dataset, monthly ones first.
+    pub fn dataset_paths(&self, symbol: &str, dataset: Dataset) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        let dataset = self.exchange.source_of(dataset);
+        for timeperiod in self.exchange.timeperiods(dataset) {
+            paths.append(&mut self.csv_paths_for(symbol, dataset, timeperiod)?);
+        }
+        Ok(paths)
+    }
+
+    pub fn load_dataset_path(&self, dataset: Dataset, path: &Path) -> Result<DataFrame> {
+        let mut df = CsvReader::from_path(path)?.finish()?;
+        self.df_sanitizer.run_dataset(dataset, &mut df)?;
+        Ok(df)
+    }
+
+    fn load_paths(&self, paths: &[PathBuf]) -> Result<Vec<DataFrame>> {
+        let mut dfs = Vec::new();
+        for path in paths {
+            let mut df = self.load_klines_path(path)?;
+            let source = path.file_name().unwrap().to_string_lossy();
+            self.validate(&source, &mut df)?;
+
+            dfs.push(df);
+        }
+        Ok(dfs)
+    }
+
+    /// Loads a klines CSV, or the 1m klines of a trades CSV
+    /// for exchanges whose klines are built from trades.
+    fn load_klines_path(&self, path: &Path) -> Result<DataFrame> {
+        let source = self.exchange.source_of(Dataset::Klines);
+        if source == Dataset::Klines {
+            let mut df = CsvReader::from_path(path)?.finish()?;
+            self.df_sanitizer.run(&mut df)?;
+            return Ok(df);
+        }
+
+        let trades = self.load_dataset_path(source, path)?;
+        let klines = BarBuilder::new()
+            .time_bars(trades.lazy(), DEFAULT_TIMEFRAME)
+            .collect()?;
+        Ok(klines)
+    }
+
+    fn validate(&self, source: &str, df: &mut DataFrame) -> Result<()> {
+        let report = self.df_sanitizer.validate(source, df, Self::KLINE_MILLIS)?;
+        self.quality_reports.lock().unwrap().push(report);
+        Ok(())
+    }
+
+    /// Returns the quality reports of all files loaded since the last call.
+    pub fn take_quality_reports(&self) -> Vec<QualityReport> {
+        std::mem::take(&mut *self.quality_reports.lock().unwrap())
+    }
+
+    /// Re-hashes every raw CSV of the symbol and returns the paths that are
+    /// missing a digest or do not match it. Files missing a digest get one
+    /// recorded on the next sync, mismatching ones are fetched again.
+    pub fn verify(&self, symbol: &str) -> Result<Vec<PathBuf>> {
+        let mut invalid = Vec::new();
+        for timeperiod in [Timeperiod::Monthly, Timeperiod::Daily] {
+            for path in self.csv_paths_for(symbol, Dataset::Klines, &timeperiod)? {
+                if let Err(e) = self.checksum_verifier.verify_recorded(&path) {
+                    warn!("{}", e);
+                    invalid.push(path);
+                }
+            }
+        }
+        Ok(invalid)
+    }
+
+    fn csv_paths_for(
+        &self,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+    ) -> Result<Vec<PathBuf>> {
+        let path = self.base_path_for(symbol, dataset, timeperiod);
         let entries = match read_dir(path) {
             Ok(files) => files,
             Err(_) => return Ok(vec![]),
         };
 
-        let mut dfs = Vec::new();
+        let mut paths = Vec::new();
         for entry in entries {
-            let path = entry.unwrap().path();
-            if path.extension().unwrap() != "csv" {
+            let path = entry?.path();
+            if path.extension().unwrap_or_default() != "csv" {
                 continue;
             }
-
-            let mut df = CsvReader::from_path(&path)?.finish()?;
-            self.df_sanitizer.run(&mut df)?;
-
-            dfs.push(df);
+            paths.push(path);
         }
-        Ok(dfs)
+        paths.sort();
+        Ok(paths)
     }
 
-    fn base_path_for(&self, symbol: &str, timeperiod: &Timeperiod) -> PathBuf {
+    /// Klines stay directly below the timeperiod directory as they always did,
+    /// other datasets get a directory of their own in between.
+    fn base_path_for(&self, symbol: &str, dataset: Dataset, timeperiod: &Timeperiod) -> PathBuf {
+        let dataset = self.exchange.source_of(dataset);
         let mut base_path = PathBuf::new();
         base_path.push(&self.config.base_raw_dir);
+        base_path.push(self.exchange.id().as_str());
         base_path.push(self.asset_cat.as_str());
         base_path.push(timeperiod.as_str());
+        if dataset != Dataset::Klines {
+            base_path.push(dataset.as_str());
+        }
         base_path.push(symbol.to_uppercase());
         base_path
     }
 
-    fn base_uri_for(&self, timeperiod: &Timeperiod) -> &str {
-        match timeperiod {
-            Timeperiod::Monthly => &self.config.hist_klines_monthly_uri,
-            Timeperiod::Daily => &self.config.hist_klines_daily_uri,
-        }
-    }
-
-    fn uri_for(&self, baseuri: &str, symbol: &str, filename: &str) -> Result<url::Url> {
-        const SLASH: &str = "/";
-        let uri = Url::parse(baseuri)?
-            .join(&format!("{}{}", symbol, SLASH))?
-            .join(&format!("{}{}", DEFAULT_TIMEFRAME, SLASH))?
-            .join(filename)?;
-        Ok(uri)
+    fn uri_for(
+        &self,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> Result<Url> {
+        let dataset = self.exchange.source_of(dataset);
+        self.exchange
+            .archive_uri_for(&self.config, symbol, dataset, timeperiod, date)
     }
 
-    fn date_format_for(&self, timeperiod: &Timeperiod) -> &str {
-        match timeperiod {
-            Timeperiod::Monthly => &self.config.date_format_monthly,
-            Timeperiod::Daily => &self.config.date_format_daily,
-        }
+    fn file_name_for(
+        &self,
+        symbol: &str,
+        dataset: Dataset,
+        timeperiod: &Timeperiod,
+        date: &DateTime<Utc>,
+    ) -> String {
+        let dataset = self.exchange.source_of(dataset);
+        self.exchange
+            .archive_name_for(&self.config, symbol, dataset, timeperiod, date)
     }
 
-    fn file_name_for(&self, symbol: &str, date: &DateTime<Utc>, dateformat: &str) -> String {
-        let mut filename = self.config.download_file_format.to_string();
-        filename = filename.replace("[[Symbol]]", &symbol.to_uppercase());
-        filename = filename.replace("[[Timeframe]]", "1m");
-        filename = filename.replace("[[Date]]", &date.format(dateformat).to_string());
-        filename
+    /// Archives are `.zip` or `.csv.gz` files holding a single CSV of the same name.
+    fn csv_name_for(archive: &str) -> String {
+        let stem = archive
+            .trim_end_matches(".zip")
+            .trim_end_matches(".gz")
+            .trim_end_matches(".csv");
+        format!("{}.csv", stem)
     }
 
     fn fromdate_for(&self, init_date: &DateTime<Utc>, timeperiod: &Timeperiod) -> DateTime<Utc> {
@@ -267,6 +984,10 @@ impl DataProvider {
         timeperiod: &Timeperiod,
     ) -> Vec<DateTime<Utc>> {
         let mut dates = vec![];
+        if todate < fromdate {
+            return dates;
+        }
+
         match timeperiod {
             Timeperiod::Monthly => {
                 let fromdate = fromdate.with_day(1).unwrap();
@@ -328,23 +1049,110 @@ mod tests {
     }
 
     #[test]
-    fn test_monthly_file_name_for() {
+    fn test_klines_frame() {
+        let provider = create_provider();
+        let klines: JsonValue = serde_json::from_str(
+            r#"[[1672531200000, "16541.77", "16545.70", "16508.39", "16529.67", "4364.83",
+                 1672531259999, "72146314.34", 12400, "2179.27", "36022013.69", "0"]]"#,
+        )
+        .unwrap();
+
+        let df = provider.klines_frame(klines.as_array().unwrap()).unwrap();
+
+        assert_eq!((1, 12), df.shape());
+        let close = df.column(Column::CLOSE).unwrap().f64().unwrap();
+        assert_eq!(Some(16529.67), close.get(0));
+        let count = df.column(Column::COUNT).unwrap().i64().unwrap();
+        assert_eq!(Some(12400), count.get(0));
+    }
+
+    #[test]
+    fn test_klines_uri_for() {
         let provider = create_provider();
-        let date = datetime::create_utc(2023, 4, 1);
-        let file_name = provider.file_name_for("btcusdt", &date, "%Y-%m");
-        assert_eq!("BTCUSDT-1m-2023-04.zip", file_name);
+        let uri = provider.klines_uri_for("btcusdt", 0, 60_000).unwrap();
+        assert_eq!(
+            "https://api.binance.com/api/v3/klines?symbol=BTCUSDT&interval=1m&startTime=0&endTime=60000&limit=1000",
+            uri.as_str()
+        );
     }
 
     #[test]
-    fn test_daily_file_name_for() {
+    fn test_funding_rates_frame() {
         let provider = create_provider();
-        let date = datetime::create_utc(2023, 4, 4);
-        let file_name = provider.file_name_for("btcusdt", &date, "%Y-%m-%d");
-        assert_eq!("BTCUSDT-1m-2023-04-04.zip", file_name);
+        let events: JsonValue = serde_json::from_str(
+            r#"[{"symbol": "BTCUSDT", "fundingTime": 1698739200000, "fundingRate": "0.00010000"},
+                {"symbol": "BTCUSDT", "fundingTime": 1698768000001, "fundingRate": "-0.00002500"}]"#,
+        )
+        .unwrap();
+
+        let df = provider
+            .funding_rates_frame(events.as_array().unwrap())
+            .unwrap();
+
+        assert_eq!((2, 3), df.shape());
+        let intervals = df.column("funding_interval_hours").unwrap().i64().unwrap();
+        assert_eq!(Some(8), intervals.get(0));
+        assert_eq!(Some(8), intervals.get(1));
+        let rates = df.column("last_funding_rate").unwrap().f64().unwrap();
+        assert_eq!(Some(-0.000025), rates.get(1));
+    }
+
+    #[test]
+    fn test_klines_from_trades() {
+        use crate::data::exchange::bybit::Bybit;
+        use crate::data::exchange::ExchangeId;
+        use flate2::write::GzEncoder;
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join("qrust-bybit-test");
+        fs::create_dir_all(&dir).unwrap();
+        let gzpath = dir.join("BTCUSDT2023-04-04.csv.gz");
+        let csvpath = dir.join(DataProvider::csv_name_for("BTCUSDT2023-04-04.csv.gz"));
+        let mut encoder = GzEncoder::new(File::create(&gzpath).unwrap(), Default::default());
+        encoder
+            .write_all(
+                b"timestamp,symbol,side,size,price,tickDirection,trdMatchID\n\
+                  1680566400.100,BTCUSDT,Buy,0.5,28000.5,PlusTick,a1\n\
+                  1680566430.200,BTCUSDT,Sell,1.5,27999.0,MinusTick,b2\n",
+            )
+            .unwrap();
+        encoder.finish().unwrap();
+
+        DataProvider::unpack(
+            &Bybit,
+            Dataset::AggTrades,
+            &AssetCategory::Usdm,
+            &gzpath,
+            &dir,
+            &csvpath,
+        )
+        .unwrap();
+        let config = DataConfig::builder()
+            .exchange(ExchangeId::Bybit)
+            .asset_cat(AssetCategory::Usdm)
+            .build()
+            .unwrap();
+        let provider = DataProvider::new(config, AssetCategory::Usdm);
+        let klines = provider.load_klines_path(&csvpath).unwrap();
+
+        assert_eq!((1, 12), klines.shape());
+        let volume = klines.column(Column::VOLUME).unwrap().f64().unwrap();
+        assert_eq!(Some(2.0), volume.get(0));
+        let taker_buy = klines
+            .column(Column::TAKER_BUY_VOLUME)
+            .unwrap()
+            .f64()
+            .unwrap();
+        assert_eq!(Some(0.5), taker_buy.get(0));
+        assert!(!gzpath.exists());
+        fs::remove_file(&csvpath).unwrap();
     }
 
     fn create_provider() -> DataProvider {
-        let config = DataConfig::new(AssetCategory::Spot);
+        let config = DataConfig::builder()
+            .asset_cat(AssetCategory::Spot)
+            .build()
+            .unwrap();
         DataProvider::new(config, AssetCategory::Spot)
     }
 }
@@ -0,0 +1,180 @@
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+
+use crate::data::config::DataConfig;
+
+static RATE_LIMITER: OnceLock<RateLimiter> = OnceLock::new();
+
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub jitter: f64,
+}
+
+impl RetryPolicy {
+    /// Binance answers with 418 once an IP got banned for ignoring 429s.
+    const STATUS_IP_BANNED: u16 = 418;
+
+    pub fn new(config: &DataConfig) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: config.retry_max_attempts.max(1),
+            initial_backoff: Duration::from_millis(config.retry_initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.retry_max_backoff_ms),
+            jitter: config.retry_jitter.clamp(0.0, 1.0),
+        }
+    }
+
+    pub fn is_retryable(&self, status: StatusCode) -> bool {
+        self.is_throttled(status) || status.is_server_error()
+    }
+
+    pub fn is_throttled(&self, status: StatusCode) -> bool {
+        status == StatusCode::TOO_MANY_REQUESTS || status.as_u16() == Self::STATUS_IP_BANNED
+    }
+
+    pub fn can_retry(&self, attempt: u32) -> bool {
+        attempt < self.max_attempts
+    }
+
+    /// Exponential backoff for the given (1-based) attempt, capped at `max_backoff`
+    /// and spread by `jitter` in both directions.
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        let exp = attempt.saturating_sub(1).min(31);
+        let backoff = self
+            .initial_backoff
+            .saturating_mul(1 << exp)
+            .min(self.max_backoff);
+
+        if self.jitter == 0.0 {
+            return backoff;
+        }
+
+        let factor = rand::thread_rng().gen_range(1.0 - self.jitter..=1.0 + self.jitter);
+        backoff.mul_f64(factor).min(self.max_backoff)
+    }
+
+    /// Reads the `Retry-After` header, which Binance sends in seconds.
+    pub fn retry_after(&self, headers: &HeaderMap) -> Option<Duration> {
+        let value = headers.get(RETRY_AFTER)?.to_str().ok()?;
+        let seconds = value.trim().parse::<u64>().ok()?;
+        Some(Duration::from_secs(seconds))
+    }
+}
+
+/// Spaces requests evenly so that at most `max_requests_per_minute`
+/// are sent, no matter how many threads share the limiter.
+pub struct RateLimiter {
+    interval: Duration,
+    next_slot: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    pub fn new(max_requests_per_minute: u32) -> RateLimiter {
+        let interval = Duration::from_secs(60) / max_requests_per_minute.max(1);
+        RateLimiter {
+            interval,
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Returns the limiter shared by every provider in the process.
+    /// The first caller decides the rate.
+    pub fn global(max_requests_per_minute: u32) -> &'static RateLimiter {
+        RATE_LIMITER.get_or_init(|| RateLimiter::new(max_requests_per_minute))
+    }
+
+    /// Reserves the next free slot and returns how long to wait for it.
+    pub fn reserve(&self) -> Duration {
+        let now = Instant::now();
+        let mut next_slot = self.next_slot.lock().unwrap();
+        let slot = (*next_slot).max(now);
+        *next_slot = slot + self.interval;
+        slot - now
+    }
+
+    pub async fn acquire(&self) {
+        let wait = self.reserve();
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Holds back every request for the given duration, e.g. after a 429.
+    pub fn pause(&self, duration: Duration) {
+        let resume = Instant::now() + duration;
+        let mut next_slot = self.next_slot.lock().unwrap();
+        *next_slot = (*next_slot).max(resume);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::HeaderValue;
+
+    #[test]
+    fn test_backoff_for() {
+        let policy = create_policy(0.0);
+
+        assert_eq!(Duration::from_millis(100), policy.backoff_for(1));
+        assert_eq!(Duration::from_millis(200), policy.backoff_for(2));
+        assert_eq!(Duration::from_millis(400), policy.backoff_for(3));
+        assert_eq!(Duration::from_millis(1000), policy.backoff_for(5));
+        assert_eq!(Duration::from_millis(1000), policy.backoff_for(64));
+    }
+
+    #[test]
+    fn test_backoff_for_with_jitter() {
+        let policy = create_policy(0.5);
+
+        for _ in 0..100 {
+            let backoff = policy.backoff_for(2);
+            assert!(backoff >= Duration::from_millis(100));
+            assert!(backoff <= Duration::from_millis(300));
+        }
+    }
+
+    #[test]
+    fn test_is_retryable() {
+        let policy = create_policy(0.0);
+
+        assert!(policy.is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(policy.is_retryable(StatusCode::from_u16(418).unwrap()));
+        assert!(policy.is_retryable(StatusCode::BAD_GATEWAY));
+        assert!(!policy.is_retryable(StatusCode::NOT_FOUND));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let policy = create_policy(0.0);
+        let mut headers = HeaderMap::new();
+        assert_eq!(None, policy.retry_after(&headers));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("120"));
+        assert_eq!(Some(Duration::from_secs(120)), policy.retry_after(&headers));
+    }
+
+    #[test]
+    fn test_rate_limiter_reserve() {
+        let limiter = RateLimiter::new(60);
+
+        assert!(limiter.reserve().is_zero());
+        assert!(limiter.reserve() > Duration::from_millis(900));
+        assert!(limiter.reserve() > Duration::from_millis(1900));
+    }
+
+    fn create_policy(jitter: f64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(1000),
+            jitter,
+        }
+    }
+}
@@ -1,22 +1,32 @@
+use std::collections::BTreeMap;
 use std::fs::OpenOptions;
 use std::io::Error as IoError;
 use std::io::ErrorKind;
 use std::path::Path;
 
 use anyhow::{anyhow, Result};
+use chrono::NaiveDateTime;
 use csv::Reader as CsvReader;
 use csv::StringRecord as CsvStringRecord;
 use csv::Writer as CsvWriter;
 use polars::prelude::*;
+use serde::{Deserialize, Serialize};
 
-use crate::extensions::datetime;
+use crate::data::dataset::Dataset;
+use crate::data::{AssetCategory, Column, TradeColumn};
+use crate::extensions::datetime::EpochUnit;
 
-pub struct CsvSanitizer;
-impl CsvSanitizer {
-    const HEADER: &str = "open_time,open,high,low,close,volume,close_time,quote_volume,count,taker_buy_volume,taker_buy_quote_volume,ignore";
+pub struct CsvSanitizer {
+    header: &'static str,
+}
 
+impl CsvSanitizer {
     pub fn new() -> CsvSanitizer {
-        CsvSanitizer {}
+        CsvSanitizer::with_header(Dataset::Klines.header(&AssetCategory::Spot))
+    }
+
+    pub fn with_header(header: &'static str) -> CsvSanitizer {
+        CsvSanitizer { header }
     }
 
     pub fn run(&self, path: &Path) -> Result<()> {
@@ -55,17 +65,28 @@ impl CsvSanitizer {
         Ok(())
     }
 
+    /// Makes sure the file starts with our header. The header some archives
+    /// have is the dataset's own, only differing in case or whitespace.
     fn check_header(&self, records: &mut Vec<CsvStringRecord>) {
-        let first_record = records.first().unwrap();
         let header_record = self.get_header_record();
-
-        if first_record != &header_record {
+        if self.is_header(records.first().unwrap()) {
+            records[0] = header_record;
+        } else {
             records.insert(0, header_record);
         }
     }
 
+    fn is_header(&self, record: &CsvStringRecord) -> bool {
+        let header_parts = self.header.split(',').collect::<Vec<&str>>();
+        record.len() == header_parts.len()
+            && record
+                .iter()
+                .zip(header_parts)
+                .all(|(field, column)| field.trim().eq_ignore_ascii_case(column))
+    }
+
     fn get_header_record(&self) -> CsvStringRecord {
-        let header_parts = Self::HEADER.split(',').collect::<Vec<&str>>();
+        let header_parts = self.header.split(',').collect::<Vec<&str>>();
         CsvStringRecord::from(header_parts)
     }
 
@@ -94,25 +115,369 @@ impl CsvSanitizer {
 pub struct DataFrameSanitizer;
 
 impl DataFrameSanitizer {
+    const TEXT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
     pub fn new() -> DataFrameSanitizer {
         DataFrameSanitizer {}
     }
 
+    /// Converts `open_time` into datetimes and `close_time` into milliseconds,
+    /// whatever epoch unit the file uses, so all stores share one unit.
     pub fn run(&self, df: &mut DataFrame) -> Result<()> {
-        let open_time_ts_series = df.column("open_time")?;
-        let open_time_dt_data = open_time_ts_series
+        let open_time_ts = df.column(Column::OPEN_TIME)?.i64()?;
+        if open_time_ts.null_count() > 0 {
+            return Err(anyhow!("Invalid open_time column: contains nulls."));
+        }
+
+        let unit = self.unit_of(Column::OPEN_TIME, open_time_ts)?;
+        let open_time_dt_data = open_time_ts
+            .into_no_null_iter()
+            .map(|ts| {
+                unit.to_datetime(ts)
+                    .ok_or(anyhow!("Invalid open_time: {}", ts))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let open_time_dt_series = Series::new(Column::OPEN_TIME, open_time_dt_data);
+        df.replace(Column::OPEN_TIME, open_time_dt_series)?;
+
+        if let Ok(close_time_ts) = df.column(Column::CLOSE_TIME).and_then(|c| c.i64()) {
+            let unit = self.unit_of(Column::CLOSE_TIME, close_time_ts)?;
+            let close_time_ms = close_time_ts
+                .into_iter()
+                .map(|ts| ts.map(|ts| unit.to_millis(ts)))
+                .collect::<Vec<_>>();
+            let close_time_ms_series = Series::new(Column::CLOSE_TIME, close_time_ms);
+            df.replace(Column::CLOSE_TIME, close_time_ms_series)?;
+        }
+
+        Ok(())
+    }
+
+    /// Converts the time column of any dataset into datetimes. Kline datasets
+    /// are handled by `run`, the others have either epoch or text times.
+    pub fn run_dataset(&self, dataset: Dataset, df: &mut DataFrame) -> Result<()> {
+        if dataset.is_kline() {
+            return self.run(df);
+        }
+
+        let name = dataset.time_column();
+        let time = df.column(name)?;
+        let time_dt_data = match time.dtype() {
+            DataType::Utf8 => time
+                .utf8()?
+                .into_iter()
+                .map(|value| {
+                    value
+                        .and_then(|v| NaiveDateTime::parse_from_str(v, Self::TEXT_TIME_FORMAT).ok())
+                        .ok_or(anyhow!("Invalid {}: {:?}", name, value))
+                })
+                .collect::<Result<Vec<_>>>()?,
+            _ => {
+                let time_ts = time.cast(&DataType::Int64)?;
+                let time_ts = time_ts.i64()?;
+                if time_ts.null_count() > 0 {
+                    return Err(anyhow!("Invalid {} column: contains nulls.", name));
+                }
+                let unit = self.unit_of(name, time_ts)?;
+                time_ts
+                    .into_no_null_iter()
+                    .map(|ts| {
+                        unit.to_datetime(ts)
+                            .ok_or(anyhow!("Invalid {}: {}", name, ts))
+                    })
+                    .collect::<Result<Vec<_>>>()?
+            }
+        };
+        df.replace(name, Series::new(name, time_dt_data))?;
+
+        // Spot archives write the flags as `True`/`False`, futures ones in lowercase
+        for flag in [TradeColumn::IS_BUYER_MAKER, TradeColumn::IS_BEST_MATCH] {
+            let flags = match df.column(flag) {
+                Ok(flags) if flags.dtype() == &DataType::Utf8 => flags.utf8()?,
+                _ => continue,
+            };
+            let flags = flags
+                .into_iter()
+                .map(|flag| flag.map(|flag| flag.eq_ignore_ascii_case("true")))
+                .collect::<Vec<_>>();
+            df.replace(flag, Series::new(flag, flags))?;
+        }
+
+        Ok(())
+    }
+
+    /// Detects the epoch unit of a timestamp column. A file is expected to
+    /// use a single unit throughout, anything else is rejected.
+    fn unit_of(&self, name: &str, timestamps: &Int64Chunked) -> Result<EpochUnit> {
+        let mut units = timestamps.into_iter().flatten().map(EpochUnit::detect);
+        let unit = units.next().unwrap_or(EpochUnit::Milliseconds);
+        if let Some(other) = units.find(|other| *other != unit) {
+            return Err(anyhow!(
+                "Column {} mixes {} and {} timestamps.",
+                name,
+                unit.as_str(),
+                other.as_str()
+            ));
+        }
+        Ok(unit)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityIssue {
+    HighBelowLow,
+    OpenOutOfRange,
+    CloseOutOfRange,
+    NegativeVolume,
+    TakerBuyExceedsVolume,
+    DuplicateOpenTime,
+    NonMonotonicOpenTime,
+    CloseTimeMismatch,
+}
+
+/// Outcome of validating one source file: how many rows it had, how many
+/// were removed and how often each issue was found.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QualityReport {
+    pub source: String,
+    pub rows: usize,
+    pub removed: usize,
+    pub issues: BTreeMap<QualityIssue, usize>,
+}
+
+impl QualityReport {
+    pub fn count_of(&self, issue: QualityIssue) -> usize {
+        self.issues.get(&issue).copied().unwrap_or_default()
+    }
+}
+
+impl DataFrameSanitizer {
+    /// Removes the rows with implausible OHLCV values, repeated or out-of-order
+    /// open times and close times that do not end the bar of `bar_millis`.
+    /// Must run after `run`, which brings the times into milliseconds.
+    pub fn validate(
+        &self,
+        source: &str,
+        df: &mut DataFrame,
+        bar_millis: i64,
+    ) -> Result<QualityReport> {
+        let f64s_of = |name: &str| -> Result<Vec<Option<f64>>> {
+            let series = df.column(name)?.cast(&DataType::Float64)?;
+            Ok(series.f64()?.into_iter().collect())
+        };
+        let open = f64s_of(Column::OPEN)?;
+        let high = f64s_of(Column::HIGH)?;
+        let low = f64s_of(Column::LOW)?;
+        let close = f64s_of(Column::CLOSE)?;
+        let volume = f64s_of(Column::VOLUME)?;
+        let taker_buy_volume = f64s_of(Column::TAKER_BUY_VOLUME)?;
+        let open_time = df
+            .column(Column::OPEN_TIME)?
+            .cast(&DataType::Datetime(TimeUnit::Milliseconds, None))?
+            .datetime()?
+            .into_iter()
+            .collect::<Vec<_>>();
+        let close_time = df
+            .column(Column::CLOSE_TIME)?
+            .cast(&DataType::Int64)?
             .i64()?
             .into_iter()
-            .map(|ts| datetime::from_timestamp(&ts.unwrap()))
             .collect::<Vec<_>>();
-        let open_time_dt_series = Series::new("open_time", open_time_dt_data);
 
-        if open_time_dt_series.len() != df.height() {
-            return Err(anyhow!("Invalid open_time column."));
+        let mut issues = BTreeMap::new();
+        let mut keep = Vec::with_capacity(df.height());
+        let mut latest: Option<i64> = None;
+        for i in 0..df.height() {
+            let mut found = Vec::new();
+
+            if let (Some(high), Some(low)) = (high[i], low[i]) {
+                if high < low {
+                    found.push(QualityIssue::HighBelowLow);
+                }
+                if open[i].is_some_and(|open| open > high || open < low) {
+                    found.push(QualityIssue::OpenOutOfRange);
+                }
+                if close[i].is_some_and(|close| close > high || close < low) {
+                    found.push(QualityIssue::CloseOutOfRange);
+                }
+            }
+            if volume[i].is_some_and(|volume| volume < 0.0)
+                || taker_buy_volume[i].is_some_and(|volume| volume < 0.0)
+            {
+                found.push(QualityIssue::NegativeVolume);
+            }
+            if let (Some(taker_buy_volume), Some(volume)) = (taker_buy_volume[i], volume[i]) {
+                if taker_buy_volume > volume {
+                    found.push(QualityIssue::TakerBuyExceedsVolume);
+                }
+            }
+
+            if let Some(open_time) = open_time[i] {
+                match latest {
+                    Some(latest) if open_time == latest => {
+                        found.push(QualityIssue::DuplicateOpenTime)
+                    }
+                    Some(latest) if open_time < latest => {
+                        found.push(QualityIssue::NonMonotonicOpenTime)
+                    }
+                    _ => latest = Some(open_time),
+                }
+                if close_time[i].is_some_and(|close_time| close_time != open_time + bar_millis - 1)
+                {
+                    found.push(QualityIssue::CloseTimeMismatch);
+                }
+            }
+
+            keep.push(found.is_empty());
+            for issue in found {
+                *issues.entry(issue).or_insert(0) += 1;
+            }
         }
 
-        df.replace("open_time", open_time_dt_series)?;
+        let rows = df.height();
+        let mask = BooleanChunked::from_slice("keep", &keep);
+        *df = df.filter(&mask)?;
 
-        Ok(())
+        let report = QualityReport {
+            source: source.to_string(),
+            rows,
+            removed: rows - df.height(),
+            issues,
+        };
+        if report.removed > 0 {
+            log::warn!(
+                "Removed {} of {} rows from {}: {:?}",
+                report.removed,
+                report.rows,
+                source,
+                report.issues
+            );
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_microseconds() {
+        let mut df = df!(
+            Column::OPEN_TIME => &[1735689600000000i64, 1735689660000000],
+            Column::CLOSE_TIME => &[1735689659999999i64, 1735689719999999]
+        )
+        .unwrap();
+
+        DataFrameSanitizer::new().run(&mut df).unwrap();
+
+        let open_time = df.column(Column::OPEN_TIME).unwrap().datetime().unwrap();
+        assert_eq!(TimeUnit::Milliseconds, open_time.time_unit());
+        assert_eq!(Some(1735689600000), open_time.get(0));
+        let close_time = df.column(Column::CLOSE_TIME).unwrap().i64().unwrap();
+        assert_eq!(Some(1735689719999), close_time.get(1));
+    }
+
+    #[test]
+    fn test_run_mixed_units() {
+        let mut df = df!(
+            Column::OPEN_TIME => &[1735603200000i64, 1735689600000000]
+        )
+        .unwrap();
+
+        let err = DataFrameSanitizer::new().run(&mut df).unwrap_err();
+        assert!(err.to_string().contains("mixes ms and us"));
+    }
+
+    #[test]
+    fn test_run_nulls() {
+        let mut df = df!(Column::OPEN_TIME => &[Some(1735603200000i64), None]).unwrap();
+        assert!(DataFrameSanitizer::new().run(&mut df).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let minute = |m: i64| 1672531200000 + m * 60_000;
+        let mut df = df!(
+            Column::OPEN_TIME => &[minute(0), minute(1), minute(2), minute(2), minute(1), minute(3), minute(4)],
+            Column::OPEN => &[1.0, 1.0, 1.0, 1.0, 1.0, 5.0, 1.0],
+            Column::HIGH => &[2.0, 0.5, 2.0, 2.0, 2.0, 2.0, 2.0],
+            Column::LOW => &[0.5, 1.0, 0.5, 0.5, 0.5, 0.5, 0.5],
+            Column::CLOSE => &[1.5, 1.5, 1.5, 1.5, 1.5, 1.5, 1.5],
+            Column::VOLUME => &[10.0, 10.0, 10.0, 10.0, 10.0, 10.0, 10.0],
+            Column::CLOSE_TIME => &[minute(1) - 1, minute(2) - 1, minute(3) - 1, minute(3) - 1, minute(2) - 1, minute(4) - 1, minute(5)],
+            Column::TAKER_BUY_VOLUME => &[5.0, 5.0, 11.0, 5.0, 5.0, 5.0, 5.0]
+        )
+        .unwrap();
+        let sanitizer = DataFrameSanitizer::new();
+        sanitizer.run(&mut df).unwrap();
+
+        let report = sanitizer.validate("test.csv", &mut df, 60_000).unwrap();
+
+        assert_eq!(7, report.rows);
+        assert_eq!(6, report.removed);
+        assert_eq!(1, df.height());
+        assert_eq!(1, report.count_of(QualityIssue::HighBelowLow));
+        assert_eq!(2, report.count_of(QualityIssue::OpenOutOfRange));
+        assert_eq!(1, report.count_of(QualityIssue::CloseOutOfRange));
+        assert_eq!(1, report.count_of(QualityIssue::TakerBuyExceedsVolume));
+        assert_eq!(1, report.count_of(QualityIssue::DuplicateOpenTime));
+        assert_eq!(1, report.count_of(QualityIssue::NonMonotonicOpenTime));
+        assert_eq!(1, report.count_of(QualityIssue::CloseTimeMismatch));
+        assert_eq!(0, report.count_of(QualityIssue::NegativeVolume));
+    }
+
+    #[test]
+    fn test_run_dataset() {
+        let sanitizer = DataFrameSanitizer::new();
+        let mut df = df!(
+            "timestamp" => &["2023-01-01 00:00:07", "2023-01-01 00:00:37"],
+            "depth" => &[1.0, 2.0]
+        )
+        .unwrap();
+        sanitizer.run_dataset(Dataset::BookDepth, &mut df).unwrap();
+        let time = df.column("timestamp").unwrap().datetime().unwrap();
+        assert_eq!(Some(1672531207000), time.get(0));
+
+        let mut df = df!("calc_time" => &[1672531200000i64, 1672560000000]).unwrap();
+        sanitizer
+            .run_dataset(Dataset::FundingRate, &mut df)
+            .unwrap();
+        let time = df.column("calc_time").unwrap().datetime().unwrap();
+        assert_eq!(Some(1672560000000), time.get(1));
+    }
+
+    #[test]
+    fn test_check_header() {
+        let sanitizer =
+            CsvSanitizer::with_header("calc_time,funding_interval_hours,last_funding_rate");
+        let mut records = vec![
+            CsvStringRecord::from(vec![
+                "calc_time",
+                "funding_interval_hours",
+                "last_funding_rate",
+            ]),
+            CsvStringRecord::from(vec!["1672531200000", "8", "0.0001"]),
+        ];
+        sanitizer.check_header(&mut records);
+        assert_eq!(2, records.len());
+
+        let mut records = vec![
+            CsvStringRecord::from(vec![
+                "CALC_TIME",
+                " funding_interval_hours",
+                "last_funding_rate",
+            ]),
+            CsvStringRecord::from(vec!["1672531200000", "8", "0.0001"]),
+        ];
+        sanitizer.check_header(&mut records);
+        assert_eq!(2, records.len());
+        assert_eq!(sanitizer.get_header_record(), records[0]);
+
+        let mut records = vec![CsvStringRecord::from(vec!["1672531200000", "8", "0.0001"])];
+        sanitizer.check_header(&mut records);
+        assert_eq!(2, records.len());
+        assert_eq!(sanitizer.get_header_record(), records[0]);
     }
 }
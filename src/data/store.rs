@@ -1,16 +1,39 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
 use std::fs::File;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{anyhow, Result};
+use chrono::{DateTime, Datelike, NaiveDate, NaiveDateTime, Utc};
+use futures::stream::{self, StreamExt};
 use polars::io::parquet::ParquetReader;
 use polars::lazy::dsl::*;
 use polars::prelude::*;
-use rayon::prelude::*;
+use polars::sql::SQLContext;
+use tokio::task;
 
+use crate::data::bars::{BarBuilder, BarSource, BarType};
+use crate::data::config::DataConfig;
+use crate::data::config::StoreLayout;
+use crate::data::dataset::Dataset;
+use crate::data::derived::{BrickSize, DerivedBuilder, DerivedSeries};
+use crate::data::export::{self, ExportOptions};
+use crate::data::gaps::{Gap, GapDetector, GapReport};
+use crate::data::metadata::{StoreMetadata, TimeframeMetadata, SCHEMA_VERSION};
+use crate::data::provider::DataProvider;
+use crate::data::provider::{FetchResult, FetchStatus, DEFAULT_TIMEFRAME};
+use crate::data::sanitizer::QualityReport;
+use crate::data::symbols::{self, SymbolsCache};
+use crate::data::timeframe::{ResampleOptions, Timeframe, TimeframeUnit, WindowSide};
 use crate::data::{Column, Symbol};
-use crate::DataConfig;
-use crate::DataProvider;
+use crate::extensions::datetime;
+
+const PARTITION_FILE: &str = "part.parquet";
+const INGESTED_FILE: &str = "ingested.json";
+const BARS_TABLE: &str = "bars";
+const SYMBOLS_TABLE: &str = "symbols";
+const SYMBOL_COLUMN: &str = "symbol";
 
 pub struct DataStore {
     config: DataConfig,
@@ -21,140 +44,1665 @@ impl DataStore {
         DataStore { config }
     }
 
-    // TODO: Date range
-    pub fn load(&self, symbol: &str, timeframe: &Option<String>) -> Option<DataFrame> {
+    /// Loads the bars of the timeframe. Timeframes that are not stored, or whose
+    /// store lags behind the 1m store, are resampled from the 1m store on the fly.
+    /// Nothing is written.
+    pub fn load(&self, symbol: &str, timeframe: &Timeframe) -> Option<DataFrame> {
+        self.load_with(symbol, timeframe, &self.config.resample, false)
+    }
+
+    /// Like `load`, but writes a timeframe resampled on the fly to its store and
+    /// brings a lagging store up to date, so later loads only read the store.
+    pub fn load_and_persist(&self, symbol: &str, timeframe: &Timeframe) -> Option<DataFrame> {
+        self.load_with(symbol, timeframe, &self.config.resample, true)
+    }
+
+    /// Loads the timeframe resampled with other than the configured options,
+    /// e.g. daily bars aligned to another timezone. Such a variant is stored
+    /// under its own name, see `ResampleOptions::name_for`.
+    pub fn load_with(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        options: &ResampleOptions,
+        persist: bool,
+    ) -> Option<DataFrame> {
+        let tf = self.store_key_with(timeframe, options);
+        if self.store_path_for(symbol, &tf).exists() {
+            let is_fresh = if persist {
+                self.refresh(symbol, timeframe, options).map(|_| true)
+            } else {
+                self.stale_since(symbol, timeframe, options)
+                    .map(|since| since.is_none())
+            };
+            match is_fresh {
+                Ok(true) => return self.load_store(symbol, &tf),
+                Ok(false) => {}
+                Err(e) => {
+                    log::error!("Failed to refresh {} of {}: {}", timeframe, symbol, e);
+                    return self.load_store(symbol, &tf);
+                }
+            }
+        }
+
+        let mut df = match self.resample_on_load(symbol, timeframe, options)?.collect() {
+            Ok(df) => df,
+            Err(e) => {
+                log::error!("Failed to resample {} to {}: {}", symbol, timeframe, e);
+                return None;
+            }
+        };
+        if persist {
+            if let Err(e) = self.write(symbol, &tf, &mut df) {
+                log::error!("Failed to persist {} of {}: {}", timeframe, symbol, e);
+            }
+        }
+        Some(df)
+    }
+
+    /// Resamples the bars a persisted timeframe is missing and writes them.
+    fn refresh(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        options: &ResampleOptions,
+    ) -> Result<()> {
+        let Some(last) = self.stale_since(symbol, timeframe, options)? else {
+            return Ok(());
+        };
+
+        let store = self
+            .load_store(symbol, &None)
+            .ok_or(anyhow!("No store found for {}", symbol))?
+            .sort([Column::OPEN_TIME], false)?;
+        self.resample_tail(symbol, timeframe, options, &store, last)
+    }
+
+    /// The open time of the last bar of a persisted timeframe if the 1m store
+    /// has bars after its window, as sync only keeps the configured timeframes
+    /// up to date.
+    fn stale_since(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        options: &ResampleOptions,
+    ) -> Result<Option<NaiveDateTime>> {
+        let is_synced =
+            *options == self.config.resample && self.config.default_timeframes.contains(timeframe);
+        if *timeframe == Timeframe::MINUTE || is_synced {
+            return Ok(None);
+        }
+
+        let base_last = self
+            .metadata(symbol)
+            .and_then(|metadata| metadata.timeframes.get(DEFAULT_TIMEFRAME)?.last_open_time);
+        let tf = self.store_key_with(timeframe, options);
+        let last = self.last_time_in(symbol, &tf)?;
+        let (Some(base_last), Some(last)) = (base_last, last) else {
+            return Ok(None);
+        };
+        if last + chrono::Duration::milliseconds(timeframe.as_millis()) > base_last {
+            return Ok(None);
+        }
+        Ok(Some(last))
+    }
+
+    /// The last time stored in the bars or dataset of the timeframe.
+    fn last_time_in(
+        &self,
+        symbol: &str,
+        timeframe: &Option<String>,
+    ) -> Result<Option<NaiveDateTime>> {
+        if !self.store_path_for(symbol, timeframe).exists() {
+            return Ok(None);
+        }
+        let time_column = self.time_column_for(timeframe);
+        let last = match self.scan_store(symbol, timeframe) {
+            Some(store) => store.select([col(time_column).max()]).collect()?,
+            None => return Ok(None),
+        };
+        self.last_time_of(&[last], time_column)
+    }
+
+    /// Loads bars built by `build_bars`.
+    pub fn load_bars(
+        &self,
+        symbol: &str,
+        bar_type: &BarType,
+        source: BarSource,
+    ) -> Option<DataFrame> {
+        self.load_store(symbol, &Some(bar_type.store_name(source)))
+    }
+
+    fn load_store(&self, symbol: &str, timeframe: &Option<String>) -> Option<DataFrame> {
         let store_path = self.store_path_for(symbol, timeframe);
         if !store_path.exists() {
             log::warn!("Store file not found: {:?}", store_path);
             return None;
         }
 
-        let mut store_file = File::open(&store_path).ok()?;
-        let df = ParquetReader::new(&mut store_file).finish().ok()?;
-        Some(df)
+        match self.config.store_layout {
+            StoreLayout::File => {
+                let mut store_file = File::open(&store_path).ok()?;
+                let df = ParquetReader::new(&mut store_file).finish().ok()?;
+                Some(df)
+            }
+            StoreLayout::Partitioned => self.scan_store(symbol, timeframe)?.collect().ok(),
+        }
+    }
+
+    /// Loads the bars with `from <= open_time < to`. Only the row groups and
+    /// columns needed are read from the store file; `None` for `columns` reads all.
+    pub fn load_range(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        from: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+        columns: Option<&[&str]>,
+    ) -> Option<DataFrame> {
+        let store = self.scan(symbol, timeframe)?;
+        let open_time_dtype = store.schema().ok()?.get(Column::OPEN_TIME)?.clone();
+
+        let from = lit(from.naive_utc()).cast(open_time_dtype.clone());
+        let to = lit(to.naive_utc()).cast(open_time_dtype);
+        let mut store = store.filter(
+            col(Column::OPEN_TIME)
+                .gt_eq(from)
+                .and(col(Column::OPEN_TIME).lt(to)),
+        );
+
+        if let Some(columns) = columns {
+            store = store.select(columns.iter().map(|c| col(c)).collect::<Vec<_>>());
+        }
+
+        match store.collect() {
+            Ok(df) => Some(df),
+            Err(e) => {
+                log::error!("Failed to load range of {}: {}", symbol, e);
+                None
+            }
+        }
+    }
+
+    /// Writes the bars of the timeframe in the range of the options to a single
+    /// file, merging partitions, and returns the number of bars written.
+    pub fn export(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        options: &ExportOptions,
+        path: &Path,
+    ) -> Result<usize> {
+        let schema = self
+            .scan(symbol, timeframe)
+            .ok_or(anyhow!("No {} bars stored for {}", timeframe, symbol))?
+            .schema()?;
+        let columns = options
+            .columns
+            .as_ref()
+            .map(|columns| columns.iter().map(|c| c.as_str()).collect::<Vec<_>>());
+        if let Some(unknown) = columns
+            .iter()
+            .flatten()
+            .find(|column| schema.get(column).is_none())
+        {
+            return Err(anyhow!("Unknown column {} in {} bars", unknown, timeframe));
+        }
+
+        // Open times are neither before 1970 nor in the future
+        let from = options
+            .from
+            .unwrap_or_else(|| datetime::create_utc(1970, 1, 1));
+        let to = options.to.unwrap_or_else(Utc::now);
+        let bars = self
+            .load_range(symbol, timeframe, &from, &to, columns.as_deref())
+            .ok_or(anyhow!("Failed to load {} bars of {}", timeframe, symbol))?;
+        let rows = bars.height();
+        export::write_frame(bars, options, path)?;
+        Ok(rows)
+    }
+
+    /// Lazily scans the stored timeframe, or resamples the 1m store
+    /// if the timeframe is not stored.
+    pub fn scan(&self, symbol: &str, timeframe: &Timeframe) -> Option<LazyFrame> {
+        let tf = self.store_key_for(timeframe);
+        if self.store_path_for(symbol, &tf).exists() {
+            return self.scan_store(symbol, &tf);
+        }
+        self.resample_on_load(symbol, timeframe, &self.config.resample)
+    }
+
+    fn resample_on_load(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        options: &ResampleOptions,
+    ) -> Option<LazyFrame> {
+        if !Timeframe::MINUTE.divides(timeframe) {
+            log::warn!(
+                "{} can not be resampled from {}",
+                timeframe,
+                Timeframe::MINUTE
+            );
+            return None;
+        }
+
+        let store = self.scan_store(symbol, &None)?;
+        log::info!(
+            "Resampling on load: {} - {} - {}",
+            self.config.asset_cat.as_str(),
+            symbol,
+            timeframe
+        );
+        Some(self.resample_frame(store, timeframe, options))
+    }
+
+    /// Lazily scans the store file or all of its partitions,
+    /// so filters and projections are pushed down into the reader.
+    fn scan_store(&self, symbol: &str, timeframe: &Option<String>) -> Option<LazyFrame> {
+        let store_path = self.store_path_for(symbol, timeframe);
+        if !store_path.exists() {
+            log::warn!("Store file not found: {:?}", store_path);
+            return None;
+        }
+
+        let scan = match self.config.store_layout {
+            StoreLayout::File => LazyFrame::scan_parquet(&store_path, ScanArgsParquet::default()),
+            StoreLayout::Partitioned => self
+                .partition_paths_in(&store_path)
+                .ok()?
+                .iter()
+                .map(|path| LazyFrame::scan_parquet(path, ScanArgsParquet::default()))
+                .collect::<PolarsResult<Vec<_>>>()
+                .and_then(|scans| concat(scans, true, true)),
+        };
+
+        match scan {
+            Ok(scan) => Some(scan),
+            Err(e) => {
+                log::error!("Failed to scan store of {}: {}", symbol, e);
+                None
+            }
+        }
     }
 
+    /// Path of the store file, or of the timeframe directory holding
+    /// the `year=YYYY/month=MM` partitions in the partitioned layout.
     fn store_path_for(&self, symbol: &str, timeframe: &Option<String>) -> PathBuf {
+        let mut store_path = self.store_dir_for(symbol);
+        match self.config.store_layout {
+            StoreLayout::File => store_path.push(self.store_name_for(symbol, timeframe)),
+            StoreLayout::Partitioned => {
+                store_path.push(timeframe.as_deref().unwrap_or(DEFAULT_TIMEFRAME))
+            }
+        }
+        store_path
+    }
+
+    fn store_dir_for(&self, symbol: &str) -> PathBuf {
+        let mut store_dir = PathBuf::new();
+        store_dir.push(&self.config.base_store_dir);
+        store_dir.push(self.config.exchange.as_str());
+        store_dir.push(self.config.asset_cat.as_str());
+        store_dir.push(symbol);
+
+        if !store_dir.exists() {
+            std::fs::create_dir_all(&store_dir).unwrap();
+        }
+
+        store_dir
+    }
+
+    fn partition_path_for(&self, store_path: &Path, year: i32, month: u32) -> PathBuf {
+        store_path
+            .join(format!("year={}", year))
+            .join(format!("month={:02}", month))
+            .join(PARTITION_FILE)
+    }
+
+    fn partition_paths_in(&self, store_path: &Path) -> Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for year_dir in self.sorted_entries_of(store_path)? {
+            for month_dir in self.sorted_entries_of(&year_dir)? {
+                let path = month_dir.join(PARTITION_FILE);
+                if path.exists() {
+                    paths.push(path);
+                }
+            }
+        }
+        Ok(paths)
+    }
+
+    fn sorted_entries_of(&self, dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut entries = fs::read_dir(dir)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        entries.retain(|path| path.is_dir());
+        entries.sort();
+        Ok(entries)
+    }
+
+    /// Holds the stamp of the source files a derived series was built from.
+    fn derived_source_path_for(&self, symbol: &str, timeframe: &Option<String>) -> PathBuf {
         let store_name = self.store_name_for(symbol, timeframe);
-        let mut store_path = PathBuf::new();
-        store_path.push(&self.config.base_store_dir);
-        store_path.push(self.config.asset_cat.as_str());
-        store_path.push(symbol);
+        self.store_dir_for(symbol)
+            .join(store_name.replace(".parquet", ".source"))
+    }
+
+    fn trades_path_for(&self, symbol: &str) -> PathBuf {
+        self.store_dir_for(symbol).join(Dataset::AggTrades.as_str())
+    }
+
+    /// Lists the raw files already merged into the store of a dataset.
+    fn ingested_path_for(&self, symbol: &str, dataset: Dataset) -> PathBuf {
+        self.store_dir_for(symbol).join(format!(
+            "{}-{}-{}",
+            symbol,
+            dataset.as_str(),
+            INGESTED_FILE
+        ))
+    }
+
+    fn metadata_path_for(&self, symbol: &str) -> PathBuf {
+        self.store_dir_for(symbol)
+            .join(format!("{}-meta.json", symbol))
+    }
+
+    fn quality_report_path_for(&self, symbol: &str) -> PathBuf {
+        self.store_dir_for(symbol)
+            .join(format!("{}-quality.json", symbol))
+    }
+
+    fn gap_report_path_for(&self, symbol: &str) -> PathBuf {
+        self.store_dir_for(symbol)
+            .join(format!("{}-gaps.json", symbol))
+    }
+
+    /// Bars are ordered by open time, other datasets by their own time column.
+    fn time_column_for(&self, timeframe: &Option<String>) -> &'static str {
+        timeframe
+            .as_deref()
+            .and_then(Dataset::from_name)
+            .map_or(Column::OPEN_TIME, |dataset| dataset.time_column())
+    }
+
+    fn store_key_for(&self, timeframe: &Timeframe) -> Option<String> {
+        self.store_key_with(timeframe, &self.config.resample)
+    }
+
+    /// The 1m bars are stored without a timeframe suffix.
+    fn store_key_with(&self, timeframe: &Timeframe, options: &ResampleOptions) -> Option<String> {
+        if *timeframe == Timeframe::MINUTE {
+            None
+        } else {
+            Some(options.name_for(timeframe))
+        }
+    }
+
+    fn timeframe_name_for(&self, timeframe: &Timeframe) -> String {
+        self.store_key_for(timeframe)
+            .unwrap_or(DEFAULT_TIMEFRAME.to_string())
+    }
+
+    fn store_name_for(&self, symbol: &str, timeframe: &Option<String>) -> String {
+        let tf = match timeframe {
+            Some(tf) => format!("-{}", tf),
+            None => String::new(),
+        };
+        format!("{}{}.parquet", symbol, tf)
+    }
+}
+
+impl DataStore {
+    pub fn new_arc(config: DataConfig) -> Arc<Self> {
+        Arc::new(DataStore::new(config))
+    }
+
+    /// Syncs the bars and datasets of the symbols, `max_concurrent_symbols` at a
+    /// time, returning the names of those that failed in any part.
+    pub async fn sync(self: Arc<Self>, symbols: Vec<Symbol>) -> Vec<String> {
+        let symbols = self.symbols_to_sync(symbols);
+        let exchange = self.config.exchange.exchange();
+        let mut datasets = self
+            .config
+            .datasets
+            .iter()
+            .filter(|dataset| **dataset != Dataset::Klines)
+            .filter(|dataset| exchange.supports(**dataset, &self.config.asset_cat))
+            .copied()
+            .collect::<Vec<_>>();
+        // Perpetual bars are of little use without their funding
+        if self.config.funding_rate_uri.is_some() && !datasets.contains(&Dataset::FundingRate) {
+            datasets.push(Dataset::FundingRate);
+        }
+
+        let mut failed = stream::iter(symbols)
+            .map(|symbol| self.sync_symbol(symbol, &datasets))
+            .buffer_unordered(self.config.max_concurrent_symbols.max(1))
+            .filter_map(|failed| async move { failed })
+            .collect::<Vec<_>>()
+            .await;
+        failed.sort();
+        failed
+    }
+
+    /// Syncs the bars and datasets of a symbol, returning its name if any part failed.
+    async fn sync_symbol(self: &Arc<Self>, symbol: Symbol, datasets: &[Dataset]) -> Option<String> {
+        if self.is_complete(&symbol) {
+            log::info!("Skipped delisted: {}", symbol.name);
+            return None;
+        }
+
+        let mut is_failed = false;
+        match self.sync_internal(&symbol).await {
+            Ok(_) => log::info!("Synced: {}", symbol.name),
+            Err(e) => {
+                log::error!("Failed to sync: {}", symbol.name);
+                log::debug!("Error: {}", e);
+                is_failed = true;
+            }
+        };
+
+        for dataset in datasets {
+            match self.sync_dataset(&symbol, *dataset).await {
+                Ok(_) => log::info!("Synced {}: {}", dataset.as_str(), symbol.name),
+                Err(e) => {
+                    log::error!("Failed to sync {}: {}", dataset.as_str(), symbol.name);
+                    log::debug!("Error: {}", e);
+                    is_failed = true;
+                }
+            };
+        }
+
+        if symbol.is_delisted() {
+            if let Err(e) = self.record_delisting(&symbol) {
+                log::warn!("Failed to record delisting of {}: {}", symbol.name, e);
+            }
+        }
+        is_failed.then_some(symbol.name)
+    }
+
+    /// The symbols passing the symbol filter, delisted ones included, by name.
+    fn symbols_to_sync(&self, symbols: Vec<Symbol>) -> Vec<Symbol> {
+        let mut symbols = self.config.symbol_filter.apply(symbols);
+        symbols.sort_by(|a, b| a.name.cmp(&b.name));
+        symbols
+    }
+
+    /// Whether the store of a delisted symbol already holds its last bar.
+    fn is_complete(&self, symbol: &Symbol) -> bool {
+        let delistdate = match symbol.delistdate {
+            Some(delistdate) => delistdate,
+            None => return false,
+        };
+        self.listing_of(&symbol.name)
+            .is_some_and(|(_, end)| end >= delistdate)
+    }
+
+    /// The open time of the first stored 1m bar and the close of the last one.
+    fn listing_of(&self, symbol: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+        let metadata = self.metadata(symbol)?;
+        let tf_metadata = metadata.timeframes.get(DEFAULT_TIMEFRAME)?;
+        let first = datetime::to_utc(&tf_metadata.first_open_time?);
+        let last = datetime::to_utc(&tf_metadata.last_open_time?);
+        Some((first, last + chrono::Duration::minutes(1)))
+    }
+
+    /// Stores the listing dates of a delisted symbol in the symbols cache,
+    /// its delisting date being the end of the last archived bar.
+    fn record_delisting(&self, symbol: &Symbol) -> Result<()> {
+        let (initdate, delistdate) = self
+            .listing_of(&symbol.name)
+            .ok_or(anyhow!("No bars stored for {}", symbol.name))?;
+        let path = SymbolsCache::path_for(&self.config);
+        SymbolsCache::record_listing(&path, &symbol.name, initdate, Some(delistdate))
+    }
+
+    /// Fetches the archives of a dataset other than klines and merges the raw files
+    /// not ingested yet into its store, stored next to the bars as `{symbol}-{dataset}`.
+    async fn sync_dataset(self: &Arc<Self>, symbol: &Symbol, dataset: Dataset) -> Result<()> {
+        let provider = Arc::new(DataProvider::new(
+            self.config.clone(),
+            self.config.asset_cat.clone(),
+        ));
+        let results = provider
+            .sync_dataset(&symbol.name, dataset, &symbol.initdate)
+            .await?;
+        if let Err(e) = self.check_fetch_results(&symbol.name, &results) {
+            if dataset != Dataset::FundingRate {
+                return Err(e);
+            }
+            log::warn!("{}, falling back to the funding rate endpoint", e);
+        }
+
+        if dataset == Dataset::AggTrades {
+            let this = Arc::clone(self);
+            let name = symbol.name.clone();
+            return task::spawn_blocking(move || this.ingest_trades(&provider, &name)).await?;
+        }
+
+        let this = Arc::clone(self);
+        let loader = Arc::clone(&provider);
+        let name = symbol.name.clone();
+        let (sources, mut dfs) =
+            task::spawn_blocking(move || this.load_new_dataset_files(&loader, &name, dataset))
+                .await??;
+        let timeframe = Some(dataset.as_str().to_string());
+        if dataset == Dataset::FundingRate {
+            let last = self
+                .last_time_of(&dfs, dataset.time_column())?
+                .max(self.last_time_in(&symbol.name, &timeframe)?);
+            let from = match last {
+                Some(last) => last + chrono::Duration::milliseconds(1),
+                None => symbol.initdate.naive_utc(),
+            };
+            let to = Utc::now().naive_utc();
+            if let Some(df) = provider
+                .fetch_funding_rates(&symbol.name, &from, &to)
+                .await?
+            {
+                dfs.push(df);
+            }
+        }
+
+        let this = Arc::clone(self);
+        let name = symbol.name.clone();
+        task::spawn_blocking(move || {
+            if dfs.is_empty() {
+                if this.store_path_for(&name, &timeframe).exists() {
+                    return Ok(());
+                }
+                return Err(anyhow!(
+                    "No {} found for symbol: {}",
+                    dataset.as_str(),
+                    name
+                ));
+            }
+
+            let rows = dfs.iter().map(|df| df.height()).sum::<usize>();
+            this.extend_dataset(&name, dataset, dfs)?;
+            this.record_ingested(&name, dataset, sources)?;
+            log::info!(
+                "Updated {} store for: {} - {} ({} new rows)",
+                dataset.as_str(),
+                this.config.asset_cat.as_str(),
+                name,
+                rows
+            );
+            Ok(())
+        })
+        .await?
+    }
+
+    pub fn load_dataset(&self, symbol: &str, dataset: Dataset) -> Option<DataFrame> {
+        self.load_store(symbol, &Some(dataset.as_str().to_string()))
+    }
+
+    /// Loads the raw files of a dataset not merged into its store yet,
+    /// returning their names along with their frames.
+    fn load_new_dataset_files(
+        &self,
+        provider: &DataProvider,
+        symbol: &str,
+        dataset: Dataset,
+    ) -> Result<(Vec<String>, Vec<DataFrame>)> {
+        let ingested = read_ingested(&self.ingested_path_for(symbol, dataset));
+        let mut sources = Vec::new();
+        let mut dfs = Vec::new();
+        for path in provider.dataset_paths(symbol, dataset)? {
+            let source = path.file_name().unwrap().to_string_lossy().to_string();
+            if ingested.contains(&source) {
+                continue;
+            }
+            dfs.push(provider.load_dataset_path(dataset, &path)?);
+            sources.push(source);
+        }
+        Ok((sources, dfs))
+    }
+
+    /// Merges new rows into the store of a dataset. In the partitioned layout only
+    /// the months from the first new row onwards are read and rewritten.
+    fn extend_dataset(&self, symbol: &str, dataset: Dataset, dfs: Vec<DataFrame>) -> Result<()> {
+        let timeframe = Some(dataset.as_str().to_string());
+        let time_column = dataset.time_column();
+        let since = self.first_time_of(&dfs, time_column)?;
+
+        let mut frames = Vec::new();
+        if self.store_path_for(symbol, &timeframe).exists() {
+            let mut store = self
+                .scan_store(symbol, &timeframe)
+                .ok_or(anyhow!("Failed to scan {} store", dataset.as_str()))?;
+            if let (StoreLayout::Partitioned, Some(since)) = (&self.config.store_layout, since) {
+                let month_start = datetime::create_utc(since.year(), since.month(), 1);
+                store = store.filter(col(time_column).gt_eq(lit(month_start.naive_utc())));
+            }
+            frames.push(store.collect()?);
+        }
+        frames.extend(dfs);
+
+        let mut store = self.merge_dataset(frames, dataset)?;
+        self.write_since(symbol, &timeframe, &mut store, since)
+    }
+
+    fn record_ingested(&self, symbol: &str, dataset: Dataset, sources: Vec<String>) -> Result<()> {
+        let path = self.ingested_path_for(symbol, dataset);
+        let mut ingested = read_ingested(&path);
+        ingested.extend(sources);
+        write_ingested(&path, &ingested)
+    }
+
+    /// Writes every raw aggTrades file not ingested yet into the trade store, one
+    /// zstd compressed parquet file per day below `aggTrades/date=YYYY-MM-DD/`.
+    fn ingest_trades(&self, provider: &DataProvider, symbol: &str) -> Result<()> {
+        let trades_path = self.trades_path_for(symbol);
+        let ingested_path = trades_path.join(INGESTED_FILE);
+        let mut ingested = read_ingested(&ingested_path);
+
+        let time_column = Dataset::AggTrades.time_column();
+        for path in provider.dataset_paths(symbol, Dataset::AggTrades)? {
+            let source = path.file_name().unwrap().to_string_lossy().to_string();
+            if ingested.contains(&source) {
+                continue;
+            }
+
+            let trades = provider.load_dataset_path(Dataset::AggTrades, &path)?;
+            let trades = trades.sort([time_column], false)?;
+            for (date, mut partition) in self.partitions_by(&trades, time_column, |t| t.date())? {
+                let partition_path = trades_path
+                    .join(format!("date={}", date.format("%Y-%m-%d")))
+                    .join(PARTITION_FILE);
+                fs::create_dir_all(partition_path.parent().unwrap())?;
+                let mut partition_file = File::create(partition_path)?;
+                ParquetWriter::new(&mut partition_file)
+                    .with_compression(ParquetCompression::Zstd(None))
+                    .finish(&mut partition)?;
+            }
+
+            log::info!("Ingested {} trades from {}", trades.height(), source);
+            ingested.insert(source);
+            // Recorded after every file, so an interrupted sync resumes where it stopped
+            write_ingested(&ingested_path, &ingested)?;
+        }
+        Ok(())
+    }
+
+    /// Lazily scans the stored trades of the days touched by `from <= time < to`.
+    pub fn scan_trades(
+        &self,
+        symbol: &str,
+        from: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Option<LazyFrame> {
+        let paths = self.trade_paths_for(symbol, from.date_naive(), to.date_naive());
+        if paths.is_empty() {
+            log::warn!(
+                "No trades stored for {} between {} and {}",
+                symbol,
+                from,
+                to
+            );
+            return None;
+        }
+
+        let time_column = Dataset::AggTrades.time_column();
+        let trades = self.scan_paths(&paths)?.filter(
+            col(time_column)
+                .gt_eq(lit(from.naive_utc()))
+                .and(col(time_column).lt(lit(to.naive_utc()))),
+        );
+        Some(trades)
+    }
+
+    fn trade_paths_for(
+        &self,
+        symbol: &str,
+        fromdate: NaiveDate,
+        todate: NaiveDate,
+    ) -> Vec<PathBuf> {
+        let trades_path = self.trades_path_for(symbol);
+        let dirs = self.sorted_entries_of(&trades_path).unwrap_or_default();
+        dirs.into_iter()
+            .filter(|dir| {
+                let name = dir.file_name().unwrap().to_string_lossy();
+                let date = name
+                    .strip_prefix("date=")
+                    .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok());
+                date.is_some_and(|date| fromdate <= date && date <= todate)
+            })
+            .map(|dir| dir.join(PARTITION_FILE))
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    fn scan_paths(&self, paths: &[PathBuf]) -> Option<LazyFrame> {
+        let scans = paths
+            .iter()
+            .map(|path| LazyFrame::scan_parquet(path, ScanArgsParquet::default()))
+            .collect::<PolarsResult<Vec<_>>>()
+            .ok()?;
+        concat(scans, true, true).ok()
+    }
+
+    /// Builds information-driven bars from the whole 1m store, which only
+    /// approximates them, or exactly from all stored trades. The bars are
+    /// written as their own store and can be loaded with `load_bars`.
+    pub fn build_bars(
+        &self,
+        symbol: &str,
+        bar_type: &BarType,
+        source: BarSource,
+    ) -> Result<DataFrame> {
+        let builder = BarBuilder::new();
+        let rows = match source {
+            BarSource::Klines => self
+                .load_store(symbol, &None)
+                .ok_or(anyhow!("No store found for {}", symbol))?
+                .sort([Column::OPEN_TIME], false)?,
+            BarSource::Trades => {
+                let paths = self.trade_paths_for(symbol, NaiveDate::MIN, NaiveDate::MAX);
+                let trades = self
+                    .scan_paths(&paths)
+                    .ok_or(anyhow!("No trades stored for {}", symbol))?;
+                builder.trade_rows(trades).collect()?
+            }
+        };
+
+        let mut bars = builder.information_bars(&rows, bar_type)?;
+        bars.calc_columns()?;
+
+        let timeframe = Some(bar_type.store_name(source));
+        self.write(symbol, &timeframe, &mut bars)?;
+        log::info!(
+            "Built {} {} bars for: {} - {}",
+            bars.height(),
+            bar_type.store_name(source),
+            self.config.asset_cat.as_str(),
+            symbol
+        );
+        Ok(bars)
+    }
+
+    /// Builds time bars of any resolution, e.g. `1s` or `10s`, from the stored
+    /// trades, with the same columns as the kline stores.
+    pub fn trade_bars(
+        &self,
+        symbol: &str,
+        every: &str,
+        from: &DateTime<Utc>,
+        to: &DateTime<Utc>,
+    ) -> Result<DataFrame> {
+        let trades = self
+            .scan_trades(symbol, from, to)
+            .ok_or(anyhow!("No trades stored for {}", symbol))?;
+        let mut bars = BarBuilder::new().time_bars(trades, every).collect()?;
+        bars.calc_columns()?;
+        Ok(bars)
+    }
+
+    pub fn heikin_ashi(&self, symbol: &str, timeframe: &Timeframe) -> Result<DataFrame> {
+        self.derived(symbol, timeframe, &DerivedSeries::HeikinAshi)
+    }
+
+    pub fn renko(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        brick_size: BrickSize,
+    ) -> Result<DataFrame> {
+        self.derived(symbol, timeframe, &DerivedSeries::Renko(brick_size))
+    }
+
+    /// Loads a series derived from a timeframe. It is cached as
+    /// `{symbol}-{tf}-{series}` together with a stamp of its source files and
+    /// built again as soon as one of them changed. Timeframes that are not
+    /// stored are resampled, so their source is the 1m store.
+    pub fn derived(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        series: &DerivedSeries,
+    ) -> Result<DataFrame> {
+        let mut source_files = self.store_files_for(symbol, &self.store_key_for(timeframe))?;
+        if source_files.is_empty() {
+            source_files = self.store_files_for(symbol, &None)?;
+        }
+        if source_files.is_empty() {
+            return Err(anyhow!("No store found for {}", symbol));
+        }
+        let source_hash = StoreMetadata::stamp_of(&source_files)?;
+
+        let tf = self.timeframe_name_for(timeframe);
+        let cache = Some(format!("{}-{}", tf, series.name()));
+        let source_path = self.derived_source_path_for(symbol, &cache);
+        let cached_hash = fs::read_to_string(&source_path).unwrap_or_default();
+        if cached_hash == source_hash {
+            if let Some(df) = self.load_store(symbol, &cache) {
+                return Ok(df);
+            }
+        }
+
+        let source = self
+            .load(symbol, timeframe)
+            .ok_or(anyhow!("No store found for {}", symbol))?
+            .sort([Column::OPEN_TIME], false)?;
+        let mut df = DerivedBuilder::new().build(&source, series)?;
+        self.write(symbol, &cache, &mut df)?;
+        fs::write(&source_path, source_hash)?;
+
+        log::info!(
+            "Built {} of {} - {} - {}",
+            series.name(),
+            self.config.asset_cat.as_str(),
+            symbol,
+            tf
+        );
+        Ok(df)
+    }
+
+    fn first_time_of(&self, dfs: &[DataFrame], time_column: &str) -> Result<Option<NaiveDateTime>> {
+        let mut first: Option<NaiveDateTime> = None;
+        for df in dfs {
+            let time = df.column(time_column)?.datetime()?;
+            let unit = time.time_unit();
+            if let Some(min) = time.min().map(|ts| datetime::from_time_unit(ts, unit)) {
+                first = Some(first.map_or(min, |first| first.min(min)));
+            }
+        }
+        Ok(first)
+    }
+
+    fn last_time_of(&self, dfs: &[DataFrame], time_column: &str) -> Result<Option<NaiveDateTime>> {
+        let mut last = None;
+        for df in dfs {
+            let time = df.column(time_column)?.datetime()?;
+            let unit = time.time_unit();
+            let max = time.max().map(|ts| datetime::from_time_unit(ts, unit));
+            last = last.max(max);
+        }
+        Ok(last)
+    }
+
+    /// Adds the summed funding rate of the events within each bar of the given
+    /// timeframe as `funding_rate`, 0 for bars without a funding event.
+    pub fn join_funding(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        bars: DataFrame,
+    ) -> Result<DataFrame> {
+        let funding = self
+            .load_dataset(symbol, Dataset::FundingRate)
+            .ok_or(anyhow!("No funding rates stored for {}", symbol))?;
+        let open_time_dtype = bars.column(Column::OPEN_TIME)?.dtype().clone();
+
+        let funding = funding.lazy().select([
+            col(Dataset::FundingRate.time_column())
+                .cast(open_time_dtype)
+                .alias(Column::OPEN_TIME),
+            col("last_funding_rate").alias(Column::FUNDING_RATE),
+        ]);
+        let funding = self
+            .close_windows(funding, &self.config.resample)
+            .sort(Column::OPEN_TIME, SortOptions::default())
+            .groupby_dynamic(
+                col(Column::OPEN_TIME),
+                [],
+                self.window_options_for(timeframe, &self.config.resample),
+            )
+            .agg([col(Column::FUNDING_RATE).sum()]);
+        let funding = self.label_windows(funding, timeframe, &self.config.resample);
+
+        let joined = bars
+            .lazy()
+            .left_join(funding, col(Column::OPEN_TIME), col(Column::OPEN_TIME))
+            .with_column(col(Column::FUNDING_RATE).fill_null(lit(0.0)))
+            .collect()?;
+        Ok(joined)
+    }
+
+    async fn sync_internal(self: &Arc<Self>, symbol: &Symbol) -> Result<()> {
+        let metadata = self.metadata(&symbol.name);
+        let store = match &metadata {
+            Some(metadata) if !metadata.is_stale(&self.timeframe_names(), &Utc::now()) => {
+                log::info!(
+                    "Store is up to date: {} - {}",
+                    self.config.asset_cat.as_str(),
+                    symbol.name
+                );
+                return Ok(());
+            }
+            Some(metadata) if metadata.schema_version != SCHEMA_VERSION => {
+                log::warn!(
+                    "Store schema of {} is outdated (v{}), rebuilding",
+                    symbol.name,
+                    metadata.schema_version
+                );
+                None
+            }
+            _ => self.load_store(&symbol.name, &None),
+        };
+        let last_open_time = match &store {
+            Some(store) => Some(self.last_open_time(store)?),
+            None => None,
+        };
+        let since = match last_open_time {
+            Some(last_open_time) => datetime::to_utc(&last_open_time),
+            None => symbol.initdate,
+        };
+
+        let provider = Arc::new(DataProvider::new(
+            self.config.clone(),
+            self.config.asset_cat.clone(),
+        ));
+        let results = provider.sync(&symbol.name, &since).await?;
+        self.check_fetch_results(&symbol.name, &results)?;
+        let sources = results
+            .iter()
+            .filter(|r| matches!(r.status, FetchStatus::Fetched | FetchStatus::Existing))
+            .map(|r| r.file.clone())
+            .collect::<Vec<_>>();
+
+        let this = Arc::clone(self);
+        let loader = Arc::clone(&provider);
+        let name = symbol.name.clone();
+        let rows =
+            task::spawn_blocking(move || this.load_rows(&loader, &name, last_open_time)).await??;
+
+        let rows = match rows {
+            Some(rows) => {
+                self.fill_gaps(&provider, &symbol.name, last_open_time, rows)
+                    .await?
+            }
+            None if store.is_some() => {
+                log::info!(
+                    "Store is up to date: {} - {}",
+                    self.config.asset_cat.as_str(),
+                    symbol.name
+                );
+                let this = Arc::clone(self);
+                let name = symbol.name.clone();
+                task::spawn_blocking(move || this.record_metadata(&name, sources)).await??;
+                return Ok(());
+            }
+            None => return Err(anyhow!("No data found for symbol: {}", symbol.name)),
+        };
+
+        let quality = provider.take_quality_reports();
+        let this = Arc::clone(self);
+        let name = symbol.name.clone();
+        task::spawn_blocking(move || {
+            match store {
+                Some(store) => this.update(&name, store, rows, quality)?,
+                None => this.build(&name, rows, quality)?,
+            }
+            this.record_metadata(&name, sources)
+        })
+        .await??;
+
+        Ok(())
+    }
+
+    fn check_fetch_results(&self, symbol: &str, results: &[FetchResult]) -> Result<()> {
+        let count_of =
+            |status: fn(&FetchStatus) -> bool| results.iter().filter(|r| status(&r.status)).count();
+        log::info!(
+            "Fetched archives for {}: {} new, {} existing, {} missing",
+            symbol,
+            count_of(|s| matches!(s, FetchStatus::Fetched)),
+            count_of(|s| matches!(s, FetchStatus::Existing)),
+            count_of(|s| matches!(s, FetchStatus::Missing)),
+        );
+
+        let failed = results
+            .iter()
+            .filter(|r| r.is_failed())
+            .map(|r| r.file.as_str())
+            .collect::<Vec<_>>();
+        if !failed.is_empty() {
+            return Err(anyhow!(
+                "Failed to fetch {} archive(s) for {}: {}",
+                failed.len(),
+                symbol,
+                failed.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Loads the raw rows newer than `after` (all rows if `None`), sorted and deduplicated.
+    fn load_rows(
+        &self,
+        provider: &DataProvider,
+        symbol: &str,
+        after: Option<NaiveDateTime>,
+    ) -> Result<Option<DataFrame>> {
+        let dfs = match after {
+            Some(after) => provider.load_since(symbol, &datetime::to_utc(&after))?,
+            None => provider.load_all(symbol)?,
+        };
+        if dfs.is_empty() {
+            return Ok(None);
+        }
+
+        let rows = self.merge(dfs, after)?;
+        if rows.height() == 0 {
+            return Ok(None);
+        }
+        Ok(Some(rows))
+    }
+
+    /// Fills missing minutes from the daily archives first and from the REST klines
+    /// endpoint second. Whatever is still missing ends up in the gap report.
+    async fn fill_gaps(
+        &self,
+        provider: &DataProvider,
+        symbol: &str,
+        after: Option<NaiveDateTime>,
+        rows: DataFrame,
+    ) -> Result<DataFrame> {
+        let detector = GapDetector::new(chrono::Duration::minutes(1));
+        let mut gaps = detector.detect(&rows, after)?;
+        if gaps.is_empty() {
+            self.record_gaps(symbol, after.is_some(), gaps)?;
+            return Ok(rows);
+        }
+        log::warn!(
+            "Found {} gap(s) in {}, filling from daily archives",
+            gaps.len(),
+            symbol
+        );
+
+        let mut fills = vec![rows];
+        for date in detector.dates_for(&gaps) {
+            let date = datetime::create_utc(date.year(), date.month(), date.day());
+            match provider.load_daily(symbol, &date).await {
+                Ok(Some(df)) => fills.push(df),
+                Ok(None) => {}
+                Err(e) => log::warn!("Could not load daily archive for {}: {}", symbol, e),
+            }
+        }
+        let mut rows = self.merge(fills, after)?;
+        gaps = detector.detect(&rows, after)?;
+
+        if !gaps.is_empty() {
+            log::warn!(
+                "{} gap(s) left in {}, filling from klines",
+                gaps.len(),
+                symbol
+            );
+
+            let mut fills = vec![rows];
+            for gap in gaps.iter() {
+                match provider.fetch_klines(symbol, &gap.from, &gap.to).await {
+                    Ok(Some(df)) => fills.push(df),
+                    Ok(None) => {}
+                    Err(e) => log::warn!("Could not fetch klines for {}: {}", symbol, e),
+                }
+            }
+            rows = self.merge(fills, after)?;
+            gaps = detector.detect(&rows, after)?;
+        }
+
+        if !gaps.is_empty() {
+            log::warn!("{} gap(s) in {} could not be filled", gaps.len(), symbol);
+        }
+        self.record_gaps(symbol, after.is_some(), gaps)?;
+        Ok(rows)
+    }
+
+    /// Writes the unfillable gaps to the symbol's gap report,
+    /// merging them with the gaps of earlier syncs when updating.
+    fn record_gaps(&self, symbol: &str, update: bool, gaps: Vec<Gap>) -> Result<()> {
+        let report_path = self.gap_report_path_for(symbol);
+        let report = match GapReport::read(&report_path) {
+            Ok(mut report) if update => {
+                report.merge(gaps);
+                report
+            }
+            _ => GapReport::new(symbol, DEFAULT_TIMEFRAME, gaps),
+        };
+        report.write(&report_path)
+    }
+
+    pub fn gap_report(&self, symbol: &str) -> Option<GapReport> {
+        GapReport::read(&self.gap_report_path_for(symbol)).ok()
+    }
+
+    /// Writes the quality reports of the files the store was built from. When
+    /// updating, reports of files loaded again replace their earlier ones.
+    fn record_quality(
+        &self,
+        symbol: &str,
+        update: bool,
+        reports: Vec<QualityReport>,
+    ) -> Result<()> {
+        let mut recorded = if update {
+            self.quality_reports(symbol)
+        } else {
+            Vec::new()
+        };
+        recorded.retain(|old| !reports.iter().any(|new| new.source == old.source));
+        recorded.extend(reports);
+        recorded.sort_by(|a, b| a.source.cmp(&b.source));
+
+        let file = File::create(self.quality_report_path_for(symbol))?;
+        serde_json::to_writer_pretty(file, &recorded)?;
+        Ok(())
+    }
+
+    pub fn quality_reports(&self, symbol: &str) -> Vec<QualityReport> {
+        fs::read_to_string(self.quality_report_path_for(symbol))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn metadata(&self, symbol: &str) -> Option<StoreMetadata> {
+        StoreMetadata::read(&self.metadata_path_for(symbol)).ok()
+    }
+
+    /// Lists the metadata of every store below `base_store_dir`, across
+    /// all exchanges and asset categories, without opening any parquet file.
+    pub fn catalog(&self) -> Result<Vec<StoreMetadata>> {
+        let base_path = Path::new(&self.config.base_store_dir);
+        if !base_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut catalog = Vec::new();
+        let cat_dirs = self
+            .sorted_entries_of(base_path)?
+            .iter()
+            .map(|exchange_dir| self.sorted_entries_of(exchange_dir))
+            .collect::<Result<Vec<_>>>()?;
+        for cat_dir in cat_dirs.into_iter().flatten() {
+            for symbol_dir in self.sorted_entries_of(&cat_dir)? {
+                let symbol = symbol_dir.file_name().unwrap().to_string_lossy();
+                let metadata_path = symbol_dir.join(format!("{}-meta.json", symbol));
+                if !metadata_path.exists() {
+                    continue;
+                }
+
+                match StoreMetadata::read(&metadata_path) {
+                    Ok(metadata) => catalog.push(metadata),
+                    Err(e) => log::warn!("Invalid metadata {:?}: {}", metadata_path, e),
+                }
+            }
+        }
+        Ok(catalog)
+    }
+
+    /// A SQL context over the stores of the configured exchange and asset category.
+    /// Every stored timeframe of a symbol is a table named like `BTCUSDT_1h`, every
+    /// timeframe across all symbols one like `bars_1h` with an added `symbol` column,
+    /// and the cached symbols are the `symbols` table. Characters other than letters
+    /// and digits are replaced by `_`; names starting with a digit have to be quoted.
+    pub fn sql_context(&self) -> Result<SQLContext> {
+        let mut context = SQLContext::new();
+        let mut timeframe_tables = BTreeMap::<String, Vec<LazyFrame>>::new();
+        let catalog = self.catalog()?.into_iter().filter(|metadata| {
+            metadata.exchange == self.config.exchange
+                && metadata.asset_cat == self.config.asset_cat.as_str()
+        });
+        for metadata in catalog {
+            for tf in metadata.timeframes.keys() {
+                let key = Some(tf.clone()).filter(|tf| tf != DEFAULT_TIMEFRAME);
+                let Some(store) = self.scan_store(&metadata.symbol, &key) else {
+                    continue;
+                };
+                context.register(&table_name_for(&metadata.symbol, tf), store.clone());
+                timeframe_tables
+                    .entry(tf.clone())
+                    .or_default()
+                    .push(store.with_column(lit(metadata.symbol.as_str()).alias(SYMBOL_COLUMN)));
+            }
+        }
+        for (tf, stores) in timeframe_tables {
+            // Stores written by older versions may lack newer columns
+            let bars = diag_concat_lf(stores, false, true)?;
+            context.register(&table_name_for(BARS_TABLE, &tf), bars);
+        }
+
+        let symbols_path = SymbolsCache::path_for(&self.config);
+        if let Ok(symbols) = SymbolsCache::read(&symbols_path) {
+            context.register(SYMBOLS_TABLE, symbols::frame_of(&symbols)?.lazy());
+        }
+        Ok(context)
+    }
+
+    /// Runs a single SQL statement against the tables of `sql_context`.
+    pub fn query(&self, sql: &str) -> Result<DataFrame> {
+        Ok(self.sql_context()?.execute(sql)?.collect()?)
+    }
+
+    /// Refreshes the metadata sidecar of the symbol after a sync,
+    /// adding the archives it was built from to the known sources.
+    fn record_metadata(&self, symbol: &str, sources: Vec<String>) -> Result<()> {
+        let mut metadata = self.metadata(symbol).unwrap_or_else(|| {
+            StoreMetadata::new(self.config.exchange, self.config.asset_cat.as_str(), symbol)
+        });
+
+        metadata.schema_version = SCHEMA_VERSION;
+        metadata.last_sync = Utc::now();
+        metadata.sources.extend(sources);
+        metadata.gap_count = self
+            .gap_report(symbol)
+            .map(|report| report.gaps.len())
+            .unwrap_or_default();
+
+        let previous = std::mem::take(&mut metadata.timeframes);
+        for timeframe in self.timeframes() {
+            let tf = self.store_key_for(&timeframe);
+            let name = self.timeframe_name_for(&timeframe);
+            if let Some(mut tf_metadata) =
+                self.timeframe_metadata_for(symbol, &tf, previous.get(&name))?
+            {
+                if tf.is_some() {
+                    tf_metadata.resample = Some(self.config.resample);
+                }
+                metadata.timeframes.insert(name, tf_metadata);
+            }
+        }
+
+        metadata.write(&self.metadata_path_for(symbol))
+    }
+
+    fn timeframe_metadata_for(
+        &self,
+        symbol: &str,
+        timeframe: &Option<String>,
+        previous: Option<&TimeframeMetadata>,
+    ) -> Result<Option<TimeframeMetadata>> {
+        let store_files = self.store_files_for(symbol, timeframe)?;
+        let store = match self.scan_store(symbol, timeframe) {
+            Some(store) if !store_files.is_empty() => store,
+            _ => return Ok(None),
+        };
+
+        let summary = store
+            .select([
+                col(Column::OPEN_TIME).min().alias("first"),
+                col(Column::OPEN_TIME).max().alias("last"),
+                col(Column::OPEN_TIME).count().alias("rows"),
+            ])
+            .collect()?;
+        let open_time_of = |name: &str| -> Result<Option<NaiveDateTime>> {
+            let open_time = summary.column(name)?.datetime()?;
+            let unit = open_time.time_unit();
+            Ok(open_time
+                .get(0)
+                .map(|ts| datetime::from_time_unit(ts, unit)))
+        };
+
+        let no_digests = BTreeMap::new();
+        let previous_digests = previous.map_or(&no_digests, |previous| &previous.file_digests);
+        let (content_hash, file_digests) =
+            StoreMetadata::content_hash_of(&store_files, previous_digests)?;
+        Ok(Some(TimeframeMetadata {
+            first_open_time: open_time_of("first")?,
+            last_open_time: open_time_of("last")?,
+            rows: summary
+                .column("rows")?
+                .cast(&DataType::UInt64)?
+                .u64()?
+                .get(0)
+                .unwrap_or_default() as usize,
+            content_hash,
+            file_digests,
+            resample: None,
+        }))
+    }
+
+    fn store_files_for(&self, symbol: &str, timeframe: &Option<String>) -> Result<Vec<PathBuf>> {
+        let store_path = self.store_path_for(symbol, timeframe);
+        if !store_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        match self.config.store_layout {
+            StoreLayout::File => Ok(vec![store_path]),
+            StoreLayout::Partitioned => self.partition_paths_in(&store_path),
+        }
+    }
+
+    /// The base timeframe followed by the configured resampled ones.
+    fn timeframes(&self) -> Vec<Timeframe> {
+        let mut timeframes = vec![Timeframe::MINUTE];
+        timeframes.extend(self.config.default_timeframes.iter().copied());
+        timeframes
+    }
+
+    fn timeframe_names(&self) -> Vec<String> {
+        self.timeframes()
+            .iter()
+            .map(|tf| self.timeframe_name_for(tf))
+            .collect()
+    }
+
+    fn build(
+        self: &Arc<Self>,
+        symbol: &str,
+        rows: DataFrame,
+        quality: Vec<QualityReport>,
+    ) -> Result<()> {
+        let store = self.create(symbol, rows, quality)?.lazy();
+
+        for tf in self.config.default_timeframes.iter() {
+            self.resample(symbol, tf, &self.config.resample, store.clone())?;
+        }
+
+        Ok(())
+    }
+
+    fn create(
+        self: &Arc<Self>,
+        symbol: &str,
+        mut store: DataFrame,
+        quality: Vec<QualityReport>,
+    ) -> Result<DataFrame> {
+        store.calc_columns()?;
+
+        self.write(symbol, &None, &mut store)?;
+        self.record_quality(symbol, false, quality)?;
+
+        log::info!(
+            "Created store for: {} - {}",
+            self.config.asset_cat.as_str(),
+            symbol
+        );
+        Ok(store)
+    }
+
+    fn update(
+        self: &Arc<Self>,
+        symbol: &str,
+        store: DataFrame,
+        rows: DataFrame,
+        quality: Vec<QualityReport>,
+    ) -> Result<()> {
+        let store = store.sort([Column::OPEN_TIME], false)?;
+        let appended = self.extend(&store, rows)?;
+
+        let first_open_time = self.first_open_time(&appended)?;
+        let mut store = store.vstack(&appended)?;
+        self.write_since(symbol, &None, &mut store, Some(first_open_time))?;
+        self.record_quality(symbol, true, quality)?;
+
+        log::info!(
+            "Updated store for: {} - {} (+{} rows)",
+            self.config.asset_cat.as_str(),
+            symbol,
+            appended.height()
+        );
+
+        for tf in self.config.default_timeframes.iter() {
+            self.resample_tail(symbol, tf, &self.config.resample, &store, first_open_time)?;
+        }
+
+        Ok(())
+    }
+
+    /// Adds the `StoreCalcs` columns to rows newer than the sorted store,
+    /// continuing the returns from the last stored bar.
+    fn extend(&self, store: &DataFrame, mut rows: DataFrame) -> Result<DataFrame> {
+        let last = store.height() - 1;
+        let prev_close = store.column(Column::CLOSE)?.f64()?.get(last);
+        let prev_cum_returns = store.column(Column::CUM_RETURNS)?.f64()?.get(last);
+
+        rows.calc_columns_from(prev_close, prev_cum_returns.unwrap_or_default())?;
+        Ok(rows)
+    }
+
+    /// Concatenates the frames in the column layout of the first one and returns
+    /// the rows newer than `after`, sorted by open time. Where frames overlap,
+    /// the row of the earlier frame is kept.
+    fn merge(&self, dfs: Vec<DataFrame>, after: Option<NaiveDateTime>) -> Result<DataFrame> {
+        let schema = dfs.first().ok_or(anyhow!("Nothing to merge"))?.schema();
+        let columns = schema
+            .iter()
+            .map(|(name, dtype)| col(name).cast(dtype.clone()))
+            .collect::<Vec<_>>();
+
+        let frames = dfs
+            .into_iter()
+            .map(|df| df.lazy().select(columns.clone()))
+            .collect::<Vec<_>>();
+
+        let mut merged = concat(frames, true, true)?.drop_nulls(None);
+        if let Some(after) = after {
+            merged = merged.filter(col(Column::OPEN_TIME).gt(lit(after)));
+        }
+
+        let merged = merged
+            .unique_stable(
+                Some(vec![Column::OPEN_TIME.into()]),
+                UniqueKeepStrategy::First,
+            )
+            .sort(Column::OPEN_TIME, SortOptions::default())
+            .collect()?;
+        Ok(merged)
+    }
+
+    /// Concatenates the frames of a dataset in the column layout of the first one,
+    /// keeping the first of the rows sharing a key and sorting by the time column.
+    fn merge_dataset(&self, dfs: Vec<DataFrame>, dataset: Dataset) -> Result<DataFrame> {
+        let schema = dfs.first().ok_or(anyhow!("Nothing to merge"))?.schema();
+        let columns = schema
+            .iter()
+            .map(|(name, dtype)| col(name).cast(dtype.clone()))
+            .collect::<Vec<_>>();
 
-        if !store_path.exists() {
-            std::fs::create_dir_all(&store_path).unwrap();
-        }
+        let frames = dfs
+            .into_iter()
+            .map(|df| df.lazy().select(columns.clone()))
+            .collect::<Vec<_>>();
 
-        store_path.push(store_name);
-        store_path
+        let merged = concat(frames, true, true)?
+            .unique_stable(
+                Some(
+                    dataset
+                        .key_columns()
+                        .iter()
+                        .map(|c| c.to_string())
+                        .collect(),
+                ),
+                UniqueKeepStrategy::First,
+            )
+            .sort(dataset.time_column(), SortOptions::default())
+            .collect()?;
+        Ok(merged)
     }
 
-    fn store_name_for(&self, symbol: &str, timeframe: &Option<String>) -> String {
-        let tf = match timeframe {
-            Some(tf) => format!("-{}", tf),
-            None => String::new(),
-        };
-        format!("{}{}.parquet", symbol, tf)
+    fn first_open_time(&self, store: &DataFrame) -> Result<NaiveDateTime> {
+        let open_time = store.column(Column::OPEN_TIME)?.datetime()?;
+        let first = open_time.min().ok_or(anyhow!("Store is empty"))?;
+        Ok(datetime::from_time_unit(first, open_time.time_unit()))
     }
-}
 
-impl DataStore {
-    pub fn new_arc(config: DataConfig) -> Arc<Self> {
-        Arc::new(DataStore::new(config))
+    fn last_open_time(&self, store: &DataFrame) -> Result<NaiveDateTime> {
+        let open_time = store.column(Column::OPEN_TIME)?.datetime()?;
+        let last = open_time.max().ok_or(anyhow!("Store is empty"))?;
+        Ok(datetime::from_time_unit(last, open_time.time_unit()))
     }
 
-    pub fn sync(self: Arc<Self>, mut symbols: Vec<Symbol>) {
-        symbols.sort_by(|a, b| a.name.cmp(&b.name));
-
-        symbols.into_par_iter().for_each(|symbol| {
-            let this = Arc::clone(&self);
-
-            match this.sync_internal(&symbol) {
-                Ok(_) => log::info!("Synced: {}", symbol.name),
-                Err(e) => {
-                    log::error!("Failed to sync: {}", symbol.name);
-                    log::debug!("Error: {}", e);
-                }
-            };
-        });
+    fn write(&self, symbol: &str, timeframe: &Option<String>, df: &mut DataFrame) -> Result<()> {
+        self.write_since(symbol, timeframe, df, None)
     }
 
-    fn sync_internal(self: &Arc<Self>, symbol: &Symbol) -> Result<()> {
-        let store = self.load(&symbol.name, &None);
-        match store {
-            Some(store) => {
-                // TODO: Check if df is up to date
-                // println!("{}", store.sample_n(50, false, false, None).unwrap());
+    /// Writes the store. In the partitioned layout only the partitions from the month
+    /// of `since` onwards are rewritten; without `since` all partitions are replaced.
+    fn write_since(
+        &self,
+        symbol: &str,
+        timeframe: &Option<String>,
+        df: &mut DataFrame,
+        since: Option<NaiveDateTime>,
+    ) -> Result<()> {
+        let store_path = self.store_path_for(symbol, timeframe);
+        if self.config.store_layout == StoreLayout::File {
+            let mut store_file = File::create(store_path)?;
+            ParquetWriter::new(&mut store_file).finish(df)?;
+            return Ok(());
+        }
+
+        if since.is_none() && store_path.exists() {
+            fs::remove_dir_all(&store_path)?;
+        }
 
-                println!("{}", store.sort([Column::OPEN_TIME], false)?);
-                // println!("{}", store);
+        let time_column = self.time_column_for(timeframe);
+        let since = since.map(|since| (since.year(), since.month()));
+        let df = df.sort([time_column], false)?;
+        for (year, month, mut partition) in self.partitions_of(&df, time_column)? {
+            if since.is_some_and(|since| (year, month) < since) {
+                continue;
             }
-            None => {
-                let provider =
-                    DataProvider::new(self.config.clone(), self.config.asset_cat.clone());
-                provider.sync(&symbol.name, &symbol.initdate)?;
 
-                let store = self.create(&provider, &symbol.name)?.lazy();
+            let partition_path = self.partition_path_for(&store_path, year, month);
+            fs::create_dir_all(partition_path.parent().unwrap())?;
+            let mut partition_file = File::create(partition_path)?;
+            ParquetWriter::new(&mut partition_file).finish(&mut partition)?;
+        }
+        Ok(())
+    }
+
+    /// Splits a frame sorted by the given time column into its monthly partitions.
+    fn partitions_of(
+        &self,
+        df: &DataFrame,
+        time_column: &str,
+    ) -> Result<Vec<(i32, u32, DataFrame)>> {
+        let partitions = self.partitions_by(df, time_column, |time| (time.year(), time.month()))?;
+        Ok(partitions
+            .into_iter()
+            .map(|((year, month), partition)| (year, month, partition))
+            .collect())
+    }
+
+    /// Splits a frame sorted by the given time column into contiguous slices
+    /// sharing the same key, e.g. the month or the day of their time.
+    fn partitions_by<K: PartialEq + Copy>(
+        &self,
+        df: &DataFrame,
+        time_column: &str,
+        key_of: impl Fn(NaiveDateTime) -> K,
+    ) -> Result<Vec<(K, DataFrame)>> {
+        let time = df.column(time_column)?.datetime()?;
+        let unit = time.time_unit();
 
-                for tf in self.config.default_timeframes.iter() {
-                    self.resample(&symbol.name, tf, store.clone())?;
+        let mut partitions = Vec::new();
+        let mut current = None;
+        let mut start = 0;
+        for (i, ts) in time.into_iter().enumerate() {
+            let key = ts.map(|ts| key_of(datetime::from_time_unit(ts, unit)));
+            if key != current {
+                if let Some(key) = current {
+                    partitions.push((key, df.slice(start as i64, i - start)));
                 }
+                current = key;
+                start = i;
             }
         }
+        if let Some(key) = current {
+            partitions.push((key, df.slice(start as i64, df.height() - start)));
+        }
 
-        Ok(())
+        Ok(partitions)
     }
 
-    fn create(self: &Arc<Self>, provider: &DataProvider, symbol: &str) -> Result<DataFrame> {
-        let mut dfs = provider.load_all(symbol)?;
-        if dfs.is_empty() {
-            return Err(anyhow!("No data found for symbol: {}", symbol));
-        }
-
-        let mut store = dfs.pop().unwrap();
-        for df in dfs {
-            store = store.vstack(&df)?;
-        }
+    fn resample(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        options: &ResampleOptions,
+        store: LazyFrame,
+    ) -> Result<()> {
+        let mut resampled_store = self.resample_frame(store, timeframe, options).collect()?;
+        self.write(
+            symbol,
+            &self.store_key_with(timeframe, options),
+            &mut resampled_store,
+        )?;
 
-        // TODO: Check for gaps in data
-        // Sometimes monthly data has daily gaps, so we need to fill them from daily data
+        log::info!(
+            "Resampled: {} - {} - {}",
+            self.config.asset_cat.as_str(),
+            symbol,
+            options.name_for(timeframe)
+        );
+        Ok(())
+    }
 
-        store = store.drop_nulls::<String>(None)?;
+    /// Re-resamples the bars from the one containing `since` onwards and keeps
+    /// the older bars of the existing timeframe file, only computing their
+    /// returns again. The 1m bars are taken from two bars earlier on, so the
+    /// first window resampled again is complete whatever its alignment and label.
+    fn resample_tail(
+        &self,
+        symbol: &str,
+        timeframe: &Timeframe,
+        options: &ResampleOptions,
+        store: &DataFrame,
+        since: NaiveDateTime,
+    ) -> Result<()> {
+        let tf = self.store_key_with(timeframe, options);
+        let existing = match self.load_store(symbol, &tf) {
+            Some(existing) => existing,
+            None => return self.resample(symbol, timeframe, options, store.clone().lazy()),
+        };
 
-        store.calc_log_returns()?;
-        store.calc_cum_returns()?;
+        let cut = existing
+            .clone()
+            .lazy()
+            .filter(col(Column::OPEN_TIME).lt_eq(lit(since)))
+            .select([col(Column::OPEN_TIME).max()])
+            .collect()?;
+        let cut_open_time = cut.column(Column::OPEN_TIME)?.datetime()?;
+        let cut = match cut_open_time.get(0) {
+            Some(cut) => datetime::from_time_unit(cut, cut_open_time.time_unit()),
+            None => return self.resample(symbol, timeframe, options, store.clone().lazy()),
+        };
 
-        let store_path = self.store_path_for(symbol, &None);
-        let mut store_file = File::create(store_path)?;
-        ParquetWriter::new(&mut store_file).finish(&mut store)?;
+        let margin = chrono::Duration::milliseconds(2 * timeframe.as_millis());
+        let head = existing.lazy().filter(col(Column::OPEN_TIME).lt(lit(cut)));
+        let tail = self
+            .resample_frame(
+                store
+                    .clone()
+                    .lazy()
+                    .filter(col(Column::OPEN_TIME).gt_eq(lit(cut - margin))),
+                timeframe,
+                options,
+            )
+            .filter(col(Column::OPEN_TIME).gt_eq(lit(cut)));
+        let resampled_store = concat([head, tail], true, true)?;
+        let mut resampled_store = with_calcs(resampled_store, None, 0.0).collect()?;
+        self.write_since(symbol, &tf, &mut resampled_store, Some(cut))?;
 
         log::info!(
-            "Created store for: {} - {}",
+            "Resampled tail since {}: {} - {} - {}",
+            cut,
             self.config.asset_cat.as_str(),
-            symbol
+            symbol,
+            options.name_for(timeframe)
         );
-        Ok(store)
+        Ok(())
     }
 
-    fn resample(&self, symbol: &str, timeframe: &str, store: LazyFrame) -> Result<()> {
-        let duration = Duration::parse(timeframe);
-        let offset = Duration::parse("0s");
-        let mut resampled_store = store
+    fn resample_frame(
+        &self,
+        store: LazyFrame,
+        timeframe: &Timeframe,
+        options: &ResampleOptions,
+    ) -> LazyFrame {
+        let windows = self
+            .close_windows(store, options)
             .sort(Column::OPEN_TIME, SortOptions::default())
             .groupby_dynamic(
                 col(Column::OPEN_TIME),
                 [],
-                DynamicGroupOptions {
-                    index_column: Column::OPEN_TIME.into(),
-                    every: duration,
-                    period: duration,
-                    offset,
-                    truncate: false,
-                    include_boundaries: false,
-                    closed_window: ClosedWindow::Left,
-                    start_by: Default::default(),
-                    check_sorted: false,
-                },
+                self.window_options_for(timeframe, options),
             )
             .agg([
                 col(Column::OPEN).first(),
@@ -166,58 +1714,202 @@ impl DataStore {
                 col(Column::COUNT).sum(),
                 col(Column::TAKER_BUY_VOLUME).sum(),
                 col(Column::TAKER_BUY_QUOTE_VOLUME).sum(),
-                col(Column::LOG_RETURNS).sum(),
-                col(Column::CUM_RETURNS).last(),
-            ])
-            .collect()?;
+            ]);
+        with_calcs(self.label_windows(windows, timeframe, options), None, 0.0)
+    }
 
-        let store_path = self.store_path_for(symbol, &Some(timeframe.to_string()));
-        let mut store_file = File::create(store_path)?;
-        ParquetWriter::new(&mut store_file).finish(&mut resampled_store)?;
+    /// Windows of the timeframe, shifted by the offset and timezone of the options.
+    /// Weekly windows start on the configured weekday.
+    fn window_options_for(
+        &self,
+        timeframe: &Timeframe,
+        options: &ResampleOptions,
+    ) -> DynamicGroupOptions {
+        let duration = timeframe.duration();
+        let (start_by, offset_millis) = match timeframe.unit() {
+            TimeframeUnit::Week => (StartBy::Monday, self.week_offset_millis(options)),
+            _ => (StartBy::WindowBound, options.offset_millis()),
+        };
+        DynamicGroupOptions {
+            index_column: Column::OPEN_TIME.into(),
+            every: duration,
+            period: duration,
+            offset: Duration::parse(&format!("{}ms", offset_millis)),
+            truncate: true,
+            include_boundaries: false,
+            closed_window: ClosedWindow::Left,
+            start_by,
+            check_sorted: false,
+        }
+    }
 
-        log::info!(
-            "Resampled: {} - {} - {}",
-            self.config.asset_cat.as_str(),
-            symbol,
-            timeframe
-        );
-        Ok(())
+    /// Polars starts the first weekly window on the Monday of the first bar moved
+    /// by the offset, and leaves out the bars before that start. The shift to the
+    /// configured weekday and timezone is therefore taken back into the week before.
+    fn week_offset_millis(&self, options: &ResampleOptions) -> i64 {
+        let week_millis = chrono::Duration::weeks(1).num_milliseconds();
+        let days = options.week_start.num_days_from_monday() as i64;
+        let shift = (options.offset_millis() + days * chrono::Duration::days(1).num_milliseconds())
+            .rem_euclid(week_millis);
+        if shift == 0 {
+            0
+        } else {
+            shift - week_millis
+        }
+    }
+
+    /// Windows closed on the right are grouped as left closed windows of the
+    /// open times moved back by 1ms. Polars starts the first right closed window
+    /// at the first open time and would leave a bar on that boundary out.
+    fn close_windows(&self, frame: LazyFrame, options: &ResampleOptions) -> LazyFrame {
+        match options.closed {
+            WindowSide::Left => frame,
+            WindowSide::Right => frame.with_column(
+                col(Column::OPEN_TIME)
+                    .dt()
+                    .offset_by(Duration::parse("-1ms"))
+                    .alias(Column::OPEN_TIME),
+            ),
+        }
+    }
+
+    /// Labels the windows by their end instead of their start if configured.
+    fn label_windows(
+        &self,
+        windows: LazyFrame,
+        timeframe: &Timeframe,
+        options: &ResampleOptions,
+    ) -> LazyFrame {
+        match options.label {
+            WindowSide::Left => windows,
+            WindowSide::Right => windows.with_column(
+                col(Column::OPEN_TIME)
+                    .dt()
+                    .offset_by(timeframe.duration())
+                    .alias(Column::OPEN_TIME),
+            ),
+        }
     }
 }
 
+/// Columns derived from the OHLCV columns of bars sorted by open time.
+/// Returns of the first bar are null, unless the close before it is given.
 trait StoreCalcs {
+    fn calc_columns(&mut self) -> Result<()>;
+    fn calc_columns_from(&mut self, prev_close: Option<f64>, prev_cum_returns: f64) -> Result<()>;
     fn calc_log_returns(&mut self) -> Result<()>;
     fn calc_cum_returns(&mut self) -> Result<()>;
 }
 
 impl StoreCalcs for DataFrame {
-    fn calc_log_returns(&mut self) -> Result<()> {
-        let close = self.column(Column::CLOSE)?.f64()?.clone();
-        let shifted_close = close.shift_and_fill(1, Some(1.0));
-
-        let log_returns = Series::new(
-            Column::LOG_RETURNS,
-            (close / shifted_close).apply(|diff| diff.ln()),
-        );
+    fn calc_columns(&mut self) -> Result<()> {
+        self.calc_columns_from(None, 0.0)
+    }
 
-        self.hstack_mut(&[log_returns])?;
+    fn calc_columns_from(&mut self, prev_close: Option<f64>, prev_cum_returns: f64) -> Result<()> {
+        *self = with_calcs(self.clone().lazy(), prev_close, prev_cum_returns).collect()?;
         Ok(())
     }
 
+    fn calc_log_returns(&mut self) -> Result<()> {
+        with_columns(self, [log_returns(None)])
+    }
+
     fn calc_cum_returns(&mut self) -> Result<()> {
-        let cum_returns = Series::new(
-            Column::CUM_RETURNS,
-            self.column(Column::LOG_RETURNS)?.cumsum(false),
-        );
+        with_columns(self, [cum_returns(0.0)])
+    }
+}
 
-        self.hstack_mut(&[cum_returns])?;
-        Ok(())
+fn with_columns<E: AsRef<[Expr]>>(df: &mut DataFrame, exprs: E) -> Result<()> {
+    *df = df.clone().lazy().with_columns(exprs).collect()?;
+    Ok(())
+}
+
+/// Adds all columns of `StoreCalcs`, so resampled frames get the
+/// returns of their own bars rather than sums of the 1m returns.
+fn with_calcs(frame: LazyFrame, prev_close: Option<f64>, prev_cum_returns: f64) -> LazyFrame {
+    frame
+        .with_columns([
+            log_returns(prev_close),
+            simple_returns(prev_close),
+            log_volume(),
+            typical_price(),
+            vwap(),
+        ])
+        .with_column(cum_returns(prev_cum_returns))
+}
+
+fn prev_close_of(prev_close: Option<f64>) -> Expr {
+    match prev_close {
+        Some(prev_close) => col(Column::CLOSE).shift_and_fill(1, lit(prev_close)),
+        None => col(Column::CLOSE).shift(1),
     }
 }
 
+fn log_returns(prev_close: Option<f64>) -> Expr {
+    (col(Column::CLOSE) / prev_close_of(prev_close))
+        .log(std::f64::consts::E)
+        .alias(Column::LOG_RETURNS)
+}
+
+fn simple_returns(prev_close: Option<f64>) -> Expr {
+    (col(Column::CLOSE) / prev_close_of(prev_close) - lit(1.0)).alias(Column::SIMPLE_RETURNS)
+}
+
+fn cum_returns(prev_cum_returns: f64) -> Expr {
+    (col(Column::LOG_RETURNS).fill_null(lit(0.0)).cumsum(false) + lit(prev_cum_returns))
+        .alias(Column::CUM_RETURNS)
+}
+
+/// `ln(1 + volume)`, which stays finite for bars without volume.
+fn log_volume() -> Expr {
+    col(Column::VOLUME)
+        .cast(DataType::Float64)
+        .log1p()
+        .alias(Column::LOG_VOLUME)
+}
+
+fn typical_price() -> Expr {
+    ((col(Column::HIGH) + col(Column::LOW) + col(Column::CLOSE)) / lit(3.0))
+        .alias(Column::TYPICAL_PRICE)
+}
+
+/// Quote over base volume, null for bars without volume.
+fn vwap() -> Expr {
+    when(col(Column::VOLUME).gt(lit(0.0)))
+        .then(col(Column::QUOTE_VOLUME) / col(Column::VOLUME))
+        .otherwise(lit(Null {}))
+        .alias(Column::VWAP)
+}
+
+/// The raw files already merged into a dataset store, empty if none were recorded.
+fn read_ingested(path: &Path) -> BTreeSet<String> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn write_ingested(path: &Path, ingested: &BTreeSet<String>) -> Result<()> {
+    fs::write(path, serde_json::to_string_pretty(ingested)?)?;
+    Ok(())
+}
+
+/// The SQL table of a stored timeframe, e.g. `BTCUSDT_1h` or `BTC_USDT_SWAP_1d`.
+fn table_name_for(name: &str, timeframe: &str) -> String {
+    format!("{}_{}", name, timeframe)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::data::export::ExportFormat;
+    use crate::data::symbols::{SymbolFilter, SymbolInfo};
+    use crate::data::AssetCategory;
+    use chrono::Weekday;
 
     #[test]
     fn test_calc_log_returns() {
@@ -229,7 +1921,46 @@ mod tests {
         df.calc_log_returns().unwrap();
         df.calc_cum_returns().unwrap();
 
-        println!("{}", df);
+        let log_returns = df.column(Column::LOG_RETURNS).unwrap().f64().unwrap();
+        assert_eq!(None, log_returns.get(0));
+        assert_eq!(Some(2.0_f64.ln()), log_returns.get(1));
+        let cum_returns = df.column(Column::CUM_RETURNS).unwrap().f64().unwrap();
+        assert_eq!(Some(0.0), cum_returns.get(0));
+        assert!((cum_returns.get(4).unwrap() - 1.5_f64.ln()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_calc_columns() {
+        let mut df = df!(
+            Column::HIGH => &[3.0, 6.0],
+            Column::LOW => &[1.0, 3.0],
+            Column::CLOSE => &[2.0, 3.0],
+            Column::VOLUME => &[0.0, 2.0],
+            Column::QUOTE_VOLUME => &[0.0, 9.0]
+        )
+        .unwrap();
+
+        df.calc_columns().unwrap();
+
+        let values_of = |name: &str| {
+            df.column(name)
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec![None, Some(0.5)], values_of(Column::SIMPLE_RETURNS));
+        assert_eq!(vec![Some(2.0), Some(4.0)], values_of(Column::TYPICAL_PRICE));
+        assert_eq!(vec![None, Some(4.5)], values_of(Column::VWAP));
+        assert_eq!(
+            vec![Some(0.0), Some(2.0_f64.ln_1p())],
+            values_of(Column::LOG_VOLUME)
+        );
+        assert_eq!(
+            vec![Some(0.0), Some(1.5_f64.ln())],
+            values_of(Column::CUM_RETURNS)
+        );
     }
 
     #[test]
@@ -253,4 +1984,523 @@ mod tests {
             assert_eq!(a, b);
         }
     }
+
+    #[test]
+    fn test_extend() {
+        let store = DataStore::new(
+            DataConfig::builder()
+                .asset_cat(AssetCategory::Spot)
+                .build()
+                .unwrap(),
+        );
+
+        let bars = |minutes: &[u32], closes: &[f64]| {
+            df!(
+                Column::OPEN_TIME => minutes.iter().map(|m| minute(*m)).collect::<Vec<_>>(),
+                Column::HIGH => closes,
+                Column::LOW => closes,
+                Column::CLOSE => closes,
+                Column::VOLUME => vec![1.0; closes.len()],
+                Column::QUOTE_VOLUME => closes
+            )
+            .unwrap()
+        };
+        let mut existing = bars(&[0, 1], &[1.0, 2.0]);
+        existing.calc_columns().unwrap();
+
+        let fetched = bars(&[1, 2, 3, 2], &[2.0, 4.0, 2.0, 4.0]);
+
+        let last_open_time = store.last_open_time(&existing).unwrap();
+        let rows = store.merge(vec![fetched], Some(last_open_time)).unwrap();
+        let appended = store.extend(&existing, rows).unwrap();
+        assert_eq!(2, appended.height());
+
+        let log_returns = appended.column(Column::LOG_RETURNS).unwrap().f64().unwrap();
+        assert_eq!(Some(2.0_f64.ln()), log_returns.get(0));
+        assert_eq!(Some(0.5_f64.ln()), log_returns.get(1));
+
+        let cum_returns = appended.column(Column::CUM_RETURNS).unwrap().f64().unwrap();
+        let prev_cum_returns = existing.column(Column::CUM_RETURNS).unwrap().f64().unwrap();
+        let expected = prev_cum_returns.get(1).unwrap() + 2.0_f64.ln();
+        assert!((expected - cum_returns.get(0).unwrap()).abs() < 1e-12);
+
+        let vwap = appended.column(Column::VWAP).unwrap().f64().unwrap();
+        assert_eq!(Some(4.0), vwap.get(0));
+    }
+
+    fn minute(minute: u32) -> NaiveDateTime {
+        datetime::create_utc(2023, 1, 1).naive_utc() + chrono::Duration::minutes(minute as i64)
+    }
+
+    #[test]
+    fn test_load_range() {
+        let store = create_store("qrust-load-range-test", StoreLayout::File);
+
+        let mut df = df!(
+            Column::OPEN_TIME => &[minute(0), minute(1), minute(2), minute(3)],
+            Column::CLOSE => &[1.0, 2.0, 3.0, 4.0],
+            Column::VOLUME => &[10.0, 20.0, 30.0, 40.0]
+        )
+        .unwrap();
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+
+        let from = datetime::to_utc(&minute(1));
+        let to = datetime::to_utc(&minute(3));
+        let range = store
+            .load_range(
+                "TESTUSDT",
+                &Timeframe::MINUTE,
+                &from,
+                &to,
+                Some(&[Column::CLOSE]),
+            )
+            .unwrap();
+
+        assert_eq!((2, 1), range.shape());
+        let close = range.column(Column::CLOSE).unwrap().f64().unwrap();
+        assert_eq!(Some(2.0), close.get(0));
+        assert_eq!(Some(3.0), close.get(1));
+    }
+
+    #[test]
+    fn test_export() {
+        let store = create_store("qrust-export-test", StoreLayout::Partitioned);
+        let feb = datetime::create_utc(2023, 2, 1).naive_utc();
+        let mut df = df!(
+            Column::OPEN_TIME => &[minute(0), minute(1), feb],
+            Column::CLOSE => &[1.0, 2.0, 3.0]
+        )
+        .unwrap();
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+
+        let path = std::env::temp_dir().join("qrust-export-test.parquet");
+        let options = ExportOptions::new(ExportFormat::Parquet)
+            .with_columns(&[Column::OPEN_TIME, Column::CLOSE])
+            .with_range(Some(datetime::to_utc(&minute(1))), None);
+        let rows = store
+            .export("TESTUSDT", &Timeframe::MINUTE, &options, &path)
+            .unwrap();
+        assert_eq!(2, rows);
+        let exported = ParquetReader::new(File::open(&path).unwrap())
+            .finish()
+            .unwrap();
+        assert_eq!((2, 2), exported.shape());
+
+        let options = options.with_columns(&["spread"]);
+        assert!(store
+            .export("TESTUSDT", &Timeframe::MINUTE, &options, &path)
+            .is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_partitioned_write_since() {
+        let store = create_store("qrust-partitioned-test", StoreLayout::Partitioned);
+        let feb = datetime::create_utc(2023, 2, 1).naive_utc();
+
+        let mut df = df!(
+            Column::OPEN_TIME => &[minute(0), minute(1), feb],
+            Column::CLOSE => &[1.0, 2.0, 3.0]
+        )
+        .unwrap();
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+
+        let store_path = store.store_path_for("TESTUSDT", &None);
+        assert_eq!(2, store.partition_paths_in(&store_path).unwrap().len());
+
+        let mut df = df!(
+            Column::OPEN_TIME => &[minute(0), minute(1), feb],
+            Column::CLOSE => &[10.0, 20.0, 30.0]
+        )
+        .unwrap();
+        store
+            .write_since("TESTUSDT", &None, &mut df, Some(feb))
+            .unwrap();
+
+        let loaded = store.load("TESTUSDT", &Timeframe::MINUTE).unwrap();
+        let close = loaded.column(Column::CLOSE).unwrap().f64().unwrap();
+        assert_eq!(
+            vec![Some(1.0), Some(2.0), Some(30.0)],
+            close.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_catalog() {
+        let mut store = create_store("qrust-catalog-test", StoreLayout::File);
+        store.config.default_timeframes = vec![];
+        let mut df = df!(
+            Column::OPEN_TIME => &[minute(0), minute(1), minute(2)],
+            Column::CLOSE => &[1.0, 2.0, 3.0]
+        )
+        .unwrap();
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+        store
+            .record_metadata("TESTUSDT", vec!["TESTUSDT-1m-2023-01.zip".to_string()])
+            .unwrap();
+
+        let catalog = store.catalog().unwrap();
+        let metadata = catalog.iter().find(|m| m.symbol == "TESTUSDT").unwrap();
+        let tf_metadata = &metadata.timeframes[DEFAULT_TIMEFRAME];
+        assert_eq!("spot", metadata.asset_cat);
+        assert_eq!(1, metadata.sources.len());
+        assert_eq!(3, tf_metadata.rows);
+        assert_eq!(Some(minute(0)), tf_metadata.first_open_time);
+        assert_eq!(Some(minute(2)), tf_metadata.last_open_time);
+        assert!(!metadata.is_stale(&store.timeframe_names(), &Utc::now()));
+    }
+
+    #[test]
+    fn test_query() {
+        let mut store = create_store("qrust-query-test", StoreLayout::File);
+        store.config.default_timeframes = vec![];
+        for symbol in ["TESTUSDT", "OTHERUSDT"] {
+            let mut df = create_minute_bars(3);
+            store.write(symbol, &None, &mut df).unwrap();
+            store.record_metadata(symbol, vec![]).unwrap();
+        }
+
+        let df = store
+            .query("SELECT open_time, close FROM TESTUSDT_1m LIMIT 2")
+            .unwrap();
+        assert_eq!((2, 2), df.shape());
+
+        let df = store
+            .query("SELECT symbol, close FROM bars_1m WHERE symbol = 'OTHERUSDT'")
+            .unwrap();
+        assert_eq!(3, df.height());
+        let symbols = df.column(SYMBOL_COLUMN).unwrap().utf8().unwrap();
+        assert_eq!(Some("OTHERUSDT"), symbols.get(0));
+
+        assert!(store.query("SELECT * FROM MISSINGUSDT_1m").is_err());
+        assert_eq!(
+            "BTC_USDT_SWAP_1d_utc_0800",
+            table_name_for("BTC-USDT-SWAP", "1d-utc+0800")
+        );
+    }
+
+    #[test]
+    fn test_symbols_to_sync() {
+        let mut store = create_store("qrust-symbols-to-sync-test", StoreLayout::File);
+        store.config.symbol_filter = SymbolFilter::new()
+            .with_status("TRADING")
+            .with_quote_asset("USDT");
+        let listed = |name: &str, status: &str| {
+            let mut symbol = Symbol::new(name, datetime::create_utc(2020, 1, 1));
+            symbol.info = Some(SymbolInfo {
+                status: status.to_string(),
+                quote_asset: "USDT".to_string(),
+                ..Default::default()
+            });
+            symbol
+        };
+        let cached = vec![listed("SRMUSDT", "TRADING")];
+        let live = vec![
+            listed("BTCUSDT", "TRADING"),
+            listed("ETHUSDT", "BREAK"),
+            listed("NEWUSDT", "PENDING_TRADING"),
+        ];
+        let symbols = SymbolsCache::merge(cached, live, vec!["FTTUSDT".to_string()]);
+
+        let names = store
+            .symbols_to_sync(symbols)
+            .into_iter()
+            .map(|s| s.name)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["BTCUSDT", "ETHUSDT", "FTTUSDT", "SRMUSDT"], names);
+    }
+
+    #[test]
+    fn test_is_complete() {
+        let mut store = create_store("qrust-delisted-test", StoreLayout::File);
+        store.config.default_timeframes = vec![];
+        let mut df = create_minute_bars(3);
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+        store.record_metadata("TESTUSDT", vec![]).unwrap();
+
+        let mut symbol = Symbol::new("TESTUSDT", datetime::to_utc(&minute(0)));
+        assert!(!store.is_complete(&symbol));
+        symbol.delistdate = Some(datetime::to_utc(&minute(3)));
+        assert!(store.is_complete(&symbol));
+        symbol.delistdate = Some(datetime::to_utc(&minute(4)));
+        assert!(!store.is_complete(&symbol));
+    }
+
+    #[test]
+    fn test_join_funding() {
+        let store = create_store("qrust-funding-test", StoreLayout::File);
+        let hour = |h: u32| minute(h * 60);
+        let mut funding = df!(
+            "calc_time" => &[hour(0) + chrono::Duration::milliseconds(4), hour(8), hour(16)],
+            "funding_interval_hours" => &[8i64, 8, 8],
+            "last_funding_rate" => &[0.0001, -0.0002, 0.0003]
+        )
+        .unwrap();
+        store
+            .write("TESTUSDT", &Some("fundingRate".to_string()), &mut funding)
+            .unwrap();
+
+        let bars = df!(
+            Column::OPEN_TIME => &[hour(0), hour(4), hour(8), hour(12)],
+            Column::CLOSE => &[1.0, 2.0, 3.0, 4.0]
+        )
+        .unwrap();
+        let joined = store
+            .join_funding("TESTUSDT", &"4h".parse().unwrap(), bars)
+            .unwrap();
+
+        let funding_rate = joined.column(Column::FUNDING_RATE).unwrap().f64().unwrap();
+        assert_eq!(
+            vec![Some(0.0001), Some(0.0), Some(-0.0002), Some(0.0)],
+            funding_rate.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_extend_dataset() {
+        let _ = fs::remove_dir_all(std::env::temp_dir().join("qrust-extend-dataset-test"));
+        let store = create_store("qrust-extend-dataset-test", StoreLayout::Partitioned);
+        let time = |month: u32, hour: i64| {
+            datetime::create_utc(2023, month, 1).naive_utc() + chrono::Duration::hours(hour)
+        };
+        let funding = |times: &[NaiveDateTime], rates: &[f64]| {
+            df!(
+                "calc_time" => times,
+                "funding_interval_hours" => &vec![8i64; times.len()],
+                "last_funding_rate" => rates
+            )
+            .unwrap()
+        };
+
+        let first = funding(&[time(1, 0), time(2, 0)], &[0.0001, 0.0002]);
+        store
+            .extend_dataset("TESTUSDT", Dataset::FundingRate, vec![first])
+            .unwrap();
+        let second = funding(&[time(2, 0), time(2, 8)], &[0.0009, 0.0003]);
+        store
+            .extend_dataset("TESTUSDT", Dataset::FundingRate, vec![second])
+            .unwrap();
+
+        let stored = store
+            .load_dataset("TESTUSDT", Dataset::FundingRate)
+            .unwrap();
+        let rates = stored.column("last_funding_rate").unwrap().f64().unwrap();
+        assert_eq!(
+            vec![Some(0.0001), Some(0.0002), Some(0.0003)],
+            rates.into_iter().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_derived_cache() {
+        let store = create_store("qrust-derived-test", StoreLayout::Partitioned);
+        let mut bars = df!(
+            Column::OPEN_TIME => &[minute(0), minute(1)],
+            Column::OPEN => &[10.0, 11.0],
+            Column::HIGH => &[12.0, 14.0],
+            Column::LOW => &[9.0, 10.0],
+            Column::CLOSE => &[11.0, 13.0],
+            Column::VOLUME => &[1.0, 1.0]
+        )
+        .unwrap();
+        store.write("TESTUSDT", &None, &mut bars).unwrap();
+
+        let ha = store.heikin_ashi("TESTUSDT", &Timeframe::MINUTE).unwrap();
+        assert_eq!(2, ha.height());
+        assert_eq!(
+            ha,
+            store.heikin_ashi("TESTUSDT", &Timeframe::MINUTE).unwrap()
+        );
+
+        let mut bars = bars.head(Some(1));
+        store.write("TESTUSDT", &None, &mut bars).unwrap();
+        assert_eq!(
+            1,
+            store
+                .heikin_ashi("TESTUSDT", &Timeframe::MINUTE)
+                .unwrap()
+                .height()
+        );
+    }
+
+    #[test]
+    fn test_load_resampled() {
+        let store = create_store("qrust-load-resampled-test", StoreLayout::File);
+        let mut df = create_minute_bars(6);
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+
+        let timeframe = Timeframe::parse("3m").unwrap();
+        let tf = store.store_key_for(&timeframe);
+        fs::remove_file(store.store_path_for("TESTUSDT", &tf)).ok();
+
+        let resampled = store.load("TESTUSDT", &timeframe).unwrap();
+        assert_eq!(2, resampled.height());
+        let high = resampled.column(Column::HIGH).unwrap().f64().unwrap();
+        assert_eq!(Some(4.0), high.get(0));
+        let volume = resampled.column(Column::VOLUME).unwrap().f64().unwrap();
+        assert_eq!(Some(3.0), volume.get(1));
+        assert!(!store.store_path_for("TESTUSDT", &tf).exists());
+
+        store.load_and_persist("TESTUSDT", &timeframe).unwrap();
+        assert!(store.store_path_for("TESTUSDT", &tf).exists());
+        assert_eq!(resampled, store.load("TESTUSDT", &timeframe).unwrap());
+        assert!(store
+            .load("TESTUSDT", &Timeframe::parse("30s").unwrap())
+            .is_none());
+
+        // A lagging store is only brought up to date by load_and_persist
+        let mut df = create_minute_bars(9);
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+        store.record_metadata("TESTUSDT", vec![]).unwrap();
+        assert_eq!(3, store.load("TESTUSDT", &timeframe).unwrap().height());
+        assert_eq!(2, store.load_store("TESTUSDT", &tf).unwrap().height());
+        store.load_and_persist("TESTUSDT", &timeframe).unwrap();
+        assert_eq!(3, store.load_store("TESTUSDT", &tf).unwrap().height());
+    }
+
+    #[test]
+    fn test_load_with_options() {
+        let store = create_store("qrust-load-with-test", StoreLayout::File);
+        let mut df = create_minute_bars(48 * 60);
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+
+        let daily = Timeframe::parse("1d").unwrap();
+        let options = ResampleOptions {
+            timezone: "+08:00".parse().unwrap(),
+            ..Default::default()
+        };
+        let resampled = store
+            .load_with("TESTUSDT", &daily, &options, false)
+            .unwrap();
+        let open_time = resampled
+            .column(Column::OPEN_TIME)
+            .unwrap()
+            .datetime()
+            .unwrap();
+        let first = datetime::create_utc(2022, 12, 31).naive_utc() + chrono::Duration::hours(16);
+        assert_eq!(3, resampled.height());
+        assert_eq!(
+            Some(first),
+            open_time
+                .get(0)
+                .map(|ts| datetime::from_time_unit(ts, open_time.time_unit()))
+        );
+        let volume = resampled.column(Column::VOLUME).unwrap().f64().unwrap();
+        assert_eq!(Some(960.0), volume.get(0));
+
+        let options = ResampleOptions {
+            closed: WindowSide::Right,
+            label: WindowSide::Right,
+            ..Default::default()
+        };
+        let three = Timeframe::parse("3m").unwrap();
+        let tf = store.store_key_with(&three, &options);
+        fs::remove_file(store.store_path_for("TESTUSDT", &tf)).ok();
+        let mut head = df.head(Some(6));
+        store.write("TESTUSDT", &None, &mut head).unwrap();
+        let resampled = store.load_with("TESTUSDT", &three, &options, true).unwrap();
+        let open_time = resampled
+            .column(Column::OPEN_TIME)
+            .unwrap()
+            .datetime()
+            .unwrap();
+        let volume = resampled.column(Column::VOLUME).unwrap().f64().unwrap();
+        assert_eq!(
+            vec![Some(minute(0)), Some(minute(3)), Some(minute(6))],
+            open_time
+                .into_iter()
+                .map(|ts| ts.map(|ts| datetime::from_time_unit(ts, open_time.time_unit())))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![Some(1.0), Some(3.0), Some(2.0)],
+            volume.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(Some("3m-cr-lr".to_string()), tf);
+        assert!(store.store_path_for("TESTUSDT", &tf).exists());
+    }
+
+    #[test]
+    fn test_load_weekly() {
+        let store = create_store("qrust-load-weekly-test", StoreLayout::File);
+        let weekly = Timeframe::parse("1w").unwrap();
+        let open_times_of = |df: &DataFrame| {
+            let open_time = df.column(Column::OPEN_TIME).unwrap().datetime().unwrap();
+            open_time
+                .into_iter()
+                .map(|ts| ts.map(|ts| datetime::from_time_unit(ts, open_time.time_unit())))
+                .collect::<Vec<_>>()
+        };
+        let shifted_bars = |days: i64| {
+            create_minute_bars(10 * 24 * 60)
+                .lazy()
+                .with_column(
+                    col(Column::OPEN_TIME)
+                        .dt()
+                        .offset_by(Duration::parse(&format!("{}d", days))),
+                )
+                .collect()
+                .unwrap()
+        };
+
+        // From Wednesday 2023-01-04 in weeks starting on Sunday
+        let mut df = shifted_bars(3);
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+        let options = ResampleOptions {
+            week_start: Weekday::Sun,
+            ..Default::default()
+        };
+        let resampled = store
+            .load_with("TESTUSDT", &weekly, &options, false)
+            .unwrap();
+        assert_eq!(
+            vec![Some(minute(0)), Some(minute(7 * 24 * 60))],
+            open_times_of(&resampled)
+        );
+        let volume = resampled.column(Column::VOLUME).unwrap().f64().unwrap();
+        assert_eq!(Some(4.0 * 24.0 * 60.0), volume.get(0));
+
+        // From Monday 2023-01-02 UTC, still Sunday in weeks aligned to UTC-5
+        let mut df = shifted_bars(1);
+        store.write("TESTUSDT", &None, &mut df).unwrap();
+        let options = ResampleOptions {
+            timezone: "-05:00".parse().unwrap(),
+            ..Default::default()
+        };
+        let resampled = store
+            .load_with("TESTUSDT", &weekly, &options, false)
+            .unwrap();
+        let volume = resampled.column(Column::VOLUME).unwrap().f64().unwrap();
+        assert_eq!(Some(5.0 * 60.0), volume.get(0));
+        assert_eq!(10.0 * 24.0 * 60.0, volume.sum().unwrap());
+    }
+
+    fn create_minute_bars(rows: usize) -> DataFrame {
+        let values = |v: f64| vec![v; rows];
+        df!(
+            Column::OPEN_TIME => (0..rows as u32).map(minute).collect::<Vec<_>>(),
+            Column::OPEN => (0..rows).map(|i| i as f64 + 1.0).collect::<Vec<_>>(),
+            Column::HIGH => (0..rows).map(|i| i as f64 + 2.0).collect::<Vec<_>>(),
+            Column::LOW => (0..rows).map(|i| i as f64 + 0.5).collect::<Vec<_>>(),
+            Column::CLOSE => (0..rows).map(|i| i as f64 + 2.0).collect::<Vec<_>>(),
+            Column::VOLUME => values(1.0),
+            Column::QUOTE_VOLUME => values(1.0),
+            Column::COUNT => vec![1i64; rows],
+            Column::TAKER_BUY_VOLUME => values(1.0),
+            Column::TAKER_BUY_QUOTE_VOLUME => values(1.0),
+            Column::LOG_RETURNS => values(0.0),
+            Column::CUM_RETURNS => values(0.0)
+        )
+        .unwrap()
+    }
+
+    fn create_store(dir: &str, layout: StoreLayout) -> DataStore {
+        let mut config = DataConfig::builder()
+            .asset_cat(AssetCategory::Spot)
+            .build()
+            .unwrap();
+        config.base_store_dir = std::env::temp_dir().join(dir).to_string_lossy().to_string();
+        config.store_layout = layout;
+        DataStore::new(config)
+    }
 }
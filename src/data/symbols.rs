@@ -0,0 +1,444 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use polars::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::data::config::DataConfig;
+use crate::data::Symbol;
+use crate::extensions::datetime;
+
+/// Status given to symbols that are no longer listed in exchangeInfo.
+pub const DELISTED: &str = "DELISTED";
+
+/// Trading rules and contract details of a symbol as listed in exchangeInfo.
+/// Fields the exchange does not report for the asset category are `None`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SymbolInfo {
+    pub status: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    pub contract_type: Option<String>,
+    pub margin_asset: Option<String>,
+    pub tick_size: Option<f64>,
+    pub step_size: Option<f64>,
+    pub min_notional: Option<f64>,
+    pub delivery_date: Option<DateTime<Utc>>,
+}
+
+impl SymbolInfo {
+    /// Spot symbols stay in exchangeInfo with status BREAK once delisted,
+    /// futures with one of the settlement states.
+    pub fn is_delisted(&self) -> bool {
+        matches!(
+            self.status.as_str(),
+            DELISTED | "BREAK" | "DELIVERED" | "SETTLING" | "CLOSE"
+        )
+    }
+}
+
+/// Listing date of symbols exchangeInfo reports none for, before any archive.
+pub fn default_initdate() -> DateTime<Utc> {
+    datetime::create_utc(2017, 1, 1)
+}
+
+/// Picks the symbols to sync. Every field that is set has to match;
+/// symbols without metadata, e.g. created by `Symbol::new`, always pass.
+/// Delisted symbols are not filtered by status, and those only found in
+/// the archives are matched on the quote asset their name ends with.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SymbolFilter {
+    pub status: Option<String>,
+    pub quote_asset: Option<String>,
+    pub contract_type: Option<String>,
+}
+
+impl SymbolFilter {
+    pub fn new() -> SymbolFilter {
+        SymbolFilter::default()
+    }
+
+    pub fn with_status(mut self, status: &str) -> SymbolFilter {
+        self.status = Some(status.to_string());
+        self
+    }
+
+    pub fn with_quote_asset(mut self, quote_asset: &str) -> SymbolFilter {
+        self.quote_asset = Some(quote_asset.to_string());
+        self
+    }
+
+    pub fn with_contract_type(mut self, contract_type: &str) -> SymbolFilter {
+        self.contract_type = Some(contract_type.to_string());
+        self
+    }
+
+    pub fn matches(&self, symbol: &Symbol) -> bool {
+        let info = match &symbol.info {
+            Some(info) => info,
+            None => return true,
+        };
+        let is_match = |expected: &Option<String>, actual: Option<&str>| match expected {
+            Some(expected) => actual.is_some_and(|actual| actual.eq_ignore_ascii_case(expected)),
+            None => true,
+        };
+
+        if !symbol.is_delisted() {
+            return is_match(&self.status, Some(&info.status))
+                && is_match(&self.quote_asset, Some(&info.quote_asset))
+                && is_match(&self.contract_type, info.contract_type.as_deref());
+        }
+
+        let pair = symbol.name.split('_').next().unwrap_or_default();
+        let is_quoted_in = match &self.quote_asset {
+            Some(expected) if info.quote_asset.is_empty() => pair.ends_with(expected.as_str()),
+            _ => is_match(&self.quote_asset, Some(&info.quote_asset)),
+        };
+        let is_contract = info.contract_type.is_none()
+            || is_match(&self.contract_type, info.contract_type.as_deref());
+        is_quoted_in && is_contract
+    }
+
+    pub fn apply(&self, symbols: Vec<Symbol>) -> Vec<Symbol> {
+        symbols.into_iter().filter(|s| self.matches(s)).collect()
+    }
+}
+
+/// Local record of all symbols ever seen, listed or not.
+pub struct SymbolsCache;
+
+impl SymbolsCache {
+    const CACHE_FILE: &'static str = "symbols.json";
+
+    pub fn path_for(config: &DataConfig) -> PathBuf {
+        let mut cache_path = PathBuf::new();
+        cache_path.push(&config.base_store_dir);
+        cache_path.push(config.exchange.as_str());
+        cache_path.push(config.asset_cat.as_str());
+        cache_path.push(Self::CACHE_FILE);
+        cache_path
+    }
+
+    pub fn read(path: &Path) -> Result<Vec<Symbol>> {
+        let content = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn write(path: &Path, symbols: &[Symbol]) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = File::create(path)?;
+        serde_json::to_writer_pretty(file, symbols)?;
+        Ok(())
+    }
+
+    /// Merges the live symbols into the cached ones, sorted by name. Cached
+    /// symbols missing from exchangeInfo and symbols only found in the archives
+    /// are kept as delisted, the latter undated until `listing_from` dates them.
+    pub fn merge(cached: Vec<Symbol>, live: Vec<Symbol>, archived: Vec<String>) -> Vec<Symbol> {
+        let mut symbols = cached
+            .into_iter()
+            .map(|mut symbol| {
+                let info = symbol.info.get_or_insert_with(SymbolInfo::default);
+                info.status = DELISTED.to_string();
+                (symbol.name.clone(), symbol)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        for mut symbol in live {
+            if let Some(cached) = symbols.get(&symbol.name) {
+                if symbol.is_delisted() {
+                    symbol.delistdate = cached.delistdate;
+                }
+            }
+            symbols.insert(symbol.name.clone(), symbol);
+        }
+
+        for name in archived {
+            symbols.entry(name).or_insert_with_key(|name| {
+                let mut symbol = Symbol::new(name, default_initdate());
+                symbol.info = Some(SymbolInfo {
+                    status: DELISTED.to_string(),
+                    ..Default::default()
+                });
+                symbol
+            });
+        }
+
+        symbols.into_values().collect()
+    }
+
+    /// Updates the listing dates of a cached symbol.
+    pub fn record_listing(
+        path: &Path,
+        name: &str,
+        initdate: DateTime<Utc>,
+        delistdate: Option<DateTime<Utc>>,
+    ) -> Result<()> {
+        let mut symbols = Self::read(path)?;
+        if let Some(symbol) = symbols.iter_mut().find(|s| s.name == name) {
+            symbol.initdate = initdate;
+            symbol.delistdate = delistdate;
+        }
+        Self::write(path, &symbols)
+    }
+
+    /// The symbols that were tradable at the date. Delisted symbols
+    /// without a known delisting date are left out.
+    pub fn universe_at(symbols: &[Symbol], date: &DateTime<Utc>) -> Vec<Symbol> {
+        symbols
+            .iter()
+            .filter(|symbol| symbol.is_listed_at(date))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Symbols listed in an S3 style listing of the archive directories,
+/// and the marker to continue from if the listing is truncated.
+pub fn archived_from(listing: &str) -> (Vec<String>, Option<String>) {
+    let names = listing
+        .split("<CommonPrefixes>")
+        .skip(1)
+        .filter_map(|prefixes| value_of(prefixes, "Prefix"))
+        .filter_map(|prefix| prefix.trim_end_matches('/').rsplit('/').next())
+        .map(|name| name.to_string())
+        .collect();
+    let marker = match value_of(listing, "IsTruncated") {
+        Some("true") => value_of(listing, "NextMarker").map(|marker| marker.to_string()),
+        _ => None,
+    };
+    (names, marker)
+}
+
+/// Months of the monthly archives in an S3 style listing of the files of a
+/// symbol, and the marker to continue from if the listing is truncated.
+pub fn archived_months_from(listing: &str) -> (Vec<NaiveDate>, Option<String>) {
+    let keys = listing
+        .split("<Contents>")
+        .skip(1)
+        .filter_map(|contents| value_of(contents, "Key"))
+        .collect::<Vec<_>>();
+    let months = keys
+        .iter()
+        .filter_map(|key| key.strip_suffix(".zip"))
+        .filter_map(|stem| stem.get(stem.len().checked_sub(7)?..))
+        .filter_map(|month| NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").ok())
+        .collect();
+    let marker = match value_of(listing, "IsTruncated") {
+        Some("true") => value_of(listing, "NextMarker")
+            .or(keys.last().copied())
+            .map(|marker| marker.to_string()),
+        _ => None,
+    };
+    (months, marker)
+}
+
+/// The listing dates implied by the monthly archives of a symbol: listed from
+/// the start of the first archived month until the end of the last one.
+pub fn listing_from(months: &[NaiveDate]) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let first = months.iter().min()?;
+    let end = months.iter().max()?.checked_add_months(Months::new(1))?;
+    Some((
+        datetime::create_utc(first.year(), first.month(), 1),
+        datetime::create_utc(end.year(), end.month(), 1),
+    ))
+}
+
+fn value_of<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{}>", tag))? + tag.len() + 2;
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(&xml[start..end])
+}
+
+/// The symbols as a frame, one row per symbol with its listing dates and main
+/// metadata, e.g. to join bars with in SQL.
+pub fn frame_of(symbols: &[Symbol]) -> Result<DataFrame> {
+    let info_of = |map: fn(&SymbolInfo) -> Option<String>| {
+        symbols
+            .iter()
+            .map(|symbol| symbol.info.as_ref().and_then(map))
+            .collect::<Vec<_>>()
+    };
+    let names = symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+    let initdates = symbols
+        .iter()
+        .map(|s| s.initdate.naive_utc())
+        .collect::<Vec<_>>();
+    let delistdates = symbols
+        .iter()
+        .map(|s| s.delistdate.map(|delistdate| delistdate.naive_utc()))
+        .collect::<Vec<_>>();
+
+    Ok(df!(
+        "name" => names,
+        "status" => info_of(|info| Some(info.status.clone())),
+        "base_asset" => info_of(|info| Some(info.base_asset.clone())),
+        "quote_asset" => info_of(|info| Some(info.quote_asset.clone())),
+        "contract_type" => info_of(|info| info.contract_type.clone()),
+        "initdate" => initdates,
+        "delistdate" => delistdates
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_symbol(name: &str, status: &str, quote_asset: &str, contract_type: &str) -> Symbol {
+        let mut symbol = Symbol::new(name, datetime::create_utc(2020, 1, 1));
+        symbol.info = Some(SymbolInfo {
+            status: status.to_string(),
+            quote_asset: quote_asset.to_string(),
+            contract_type: Some(contract_type.to_string()),
+            ..Default::default()
+        });
+        symbol
+    }
+
+    #[test]
+    fn test_filter() {
+        let symbols = vec![
+            create_symbol("BTCUSDT", "TRADING", "USDT", "PERPETUAL"),
+            create_symbol("BTCUSDT_231229", "TRADING", "USDT", "CURRENT_QUARTER"),
+            create_symbol("ETHBUSD", "SETTLING", "BUSD", "PERPETUAL"),
+            Symbol::new("XRPUSDT", datetime::create_utc(2020, 1, 1)),
+        ];
+
+        let filter = SymbolFilter::new()
+            .with_status("TRADING")
+            .with_quote_asset("USDT")
+            .with_contract_type("perpetual");
+        let names = filter
+            .apply(symbols.clone())
+            .into_iter()
+            .map(|s| s.name)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["BTCUSDT", "XRPUSDT"], names);
+        assert_eq!(4, SymbolFilter::new().apply(symbols).len());
+
+        let cached = vec![create_symbol("SRMUSDT", "TRADING", "USDT", "PERPETUAL")];
+        let archived = vec!["FTTUSDT".to_string(), "FTTBUSD".to_string()];
+        let delisted = SymbolsCache::merge(cached, Vec::new(), archived);
+        let names = filter
+            .apply(delisted)
+            .into_iter()
+            .map(|s| s.name)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["FTTUSDT", "SRMUSDT"], names);
+    }
+
+    #[test]
+    fn test_merge() {
+        let cached = vec![
+            create_symbol("BTCUSDT", "TRADING", "USDT", "PERPETUAL"),
+            create_symbol("SRMUSDT", "TRADING", "USDT", "PERPETUAL"),
+        ];
+        let live = vec![create_symbol("BTCUSDT", "TRADING", "USDT", "PERPETUAL")];
+        let archived = vec!["BTCUSDT".to_string(), "FTTUSDT".to_string()];
+
+        let symbols = SymbolsCache::merge(cached, live, archived);
+        let names = symbols.iter().map(|s| s.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["BTCUSDT", "FTTUSDT", "SRMUSDT"], names);
+        assert!(!symbols[0].is_delisted());
+        assert!(symbols[1].is_delisted());
+        assert_eq!("USDT", symbols[2].info.as_ref().unwrap().quote_asset);
+        assert!(symbols[2].is_delisted());
+    }
+
+    #[test]
+    fn test_universe_at() {
+        let mut delisted = create_symbol("SRMUSDT", DELISTED, "USDT", "PERPETUAL");
+        delisted.initdate = datetime::create_utc(2021, 1, 1);
+        delisted.delistdate = Some(datetime::create_utc(2022, 11, 15));
+        let symbols = vec![
+            create_symbol("BTCUSDT", "TRADING", "USDT", "PERPETUAL"),
+            delisted,
+        ];
+
+        let universe = SymbolsCache::universe_at(&symbols, &datetime::create_utc(2022, 6, 1));
+        assert_eq!(2, universe.len());
+        let universe = SymbolsCache::universe_at(&symbols, &datetime::create_utc(2022, 11, 15));
+        assert_eq!(1, universe.len());
+        let universe = SymbolsCache::universe_at(&symbols, &datetime::create_utc(2019, 1, 1));
+        assert!(universe.is_empty());
+
+        // Archive-only symbols stay out of the universe until they are dated
+        let symbols = SymbolsCache::merge(Vec::new(), Vec::new(), vec!["FTTUSDT".to_string()]);
+        let universe = SymbolsCache::universe_at(&symbols, &datetime::create_utc(2022, 6, 1));
+        assert!(universe.is_empty());
+    }
+
+    #[test]
+    fn test_listing_from() {
+        let listing = "<ListBucketResult><IsTruncated>false</IsTruncated>\
+            <Contents><Key>data/futures/um/monthly/klines/SRMUSDT/1m/SRMUSDT-1m-2021-01.zip</Key></Contents>\
+            <Contents><Key>data/futures/um/monthly/klines/SRMUSDT/1m/SRMUSDT-1m-2021-01.zip.CHECKSUM</Key></Contents>\
+            <Contents><Key>data/futures/um/monthly/klines/SRMUSDT/1m/SRMUSDT-1m-2022-10.zip</Key></Contents>\
+            </ListBucketResult>";
+
+        let (months, marker) = archived_months_from(listing);
+        assert_eq!(2, months.len());
+        assert_eq!(None, marker);
+        let (initdate, delistdate) = listing_from(&months).unwrap();
+        assert_eq!(datetime::create_utc(2021, 1, 1), initdate);
+        assert_eq!(datetime::create_utc(2022, 11, 1), delistdate);
+        assert_eq!(None, listing_from(&[]));
+    }
+
+    #[test]
+    fn test_archived_from() {
+        let listing = "<ListBucketResult><Prefix>data/futures/um/monthly/klines/</Prefix>\
+            <NextMarker>data/futures/um/monthly/klines/ETHUSDT/</NextMarker>\
+            <IsTruncated>true</IsTruncated>\
+            <CommonPrefixes><Prefix>data/futures/um/monthly/klines/BTCUSDT/</Prefix></CommonPrefixes>\
+            <CommonPrefixes><Prefix>data/futures/um/monthly/klines/ETHUSDT/</Prefix></CommonPrefixes>\
+            </ListBucketResult>";
+
+        let (names, marker) = archived_from(listing);
+        assert_eq!(vec!["BTCUSDT", "ETHUSDT"], names);
+        assert_eq!(
+            Some("data/futures/um/monthly/klines/ETHUSDT/".to_string()),
+            marker
+        );
+    }
+
+    #[test]
+    fn test_cache() {
+        let path = std::env::temp_dir().join("qrust-symbols-test/symbols.json");
+        let symbols = vec![create_symbol("BTCUSDT", "TRADING", "USDT", "PERPETUAL")];
+        SymbolsCache::write(&path, &symbols).unwrap();
+
+        let read = SymbolsCache::read(&path).unwrap();
+        assert_eq!(symbols[0].name, read[0].name);
+        assert_eq!(symbols[0].info, read[0].info);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_frame_of() {
+        let mut delisted = create_symbol("ETHBUSD", "DELISTED", "BUSD", "PERPETUAL");
+        delisted.delistdate = Some(datetime::create_utc(2023, 3, 1));
+        let symbols = vec![
+            create_symbol("BTCUSDT", "TRADING", "USDT", "PERPETUAL"),
+            delisted,
+            Symbol::new("XRPUSDT", datetime::create_utc(2020, 1, 1)),
+        ];
+
+        let df = frame_of(&symbols).unwrap();
+        assert_eq!((3, 7), df.shape());
+        let quote_assets = df.column("quote_asset").unwrap().utf8().unwrap();
+        assert_eq!(
+            vec![Some("USDT"), Some("BUSD"), None],
+            quote_assets.into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(2, df.column("delistdate").unwrap().null_count());
+    }
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use chrono::{FixedOffset, Weekday};
+use polars::prelude::Duration;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TimeframeUnit {
+    Second,
+    Minute,
+    Hour,
+    Day,
+    Week,
+    Month,
+}
+
+impl TimeframeUnit {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TimeframeUnit::Second => "s",
+            TimeframeUnit::Minute => "m",
+            TimeframeUnit::Hour => "h",
+            TimeframeUnit::Day => "d",
+            TimeframeUnit::Week => "w",
+            TimeframeUnit::Month => "M",
+        }
+    }
+
+    /// Months are approximated by 30 days.
+    pub fn as_millis(&self) -> i64 {
+        match self {
+            TimeframeUnit::Second => 1_000,
+            TimeframeUnit::Minute => 60_000,
+            TimeframeUnit::Hour => 3_600_000,
+            TimeframeUnit::Day => 86_400_000,
+            TimeframeUnit::Week => 604_800_000,
+            TimeframeUnit::Month => 2_592_000_000,
+        }
+    }
+
+    fn from_suffix(suffix: &str) -> Option<TimeframeUnit> {
+        match suffix {
+            "s" => Some(TimeframeUnit::Second),
+            "m" => Some(TimeframeUnit::Minute),
+            "h" => Some(TimeframeUnit::Hour),
+            "d" => Some(TimeframeUnit::Day),
+            "w" => Some(TimeframeUnit::Week),
+            "M" => Some(TimeframeUnit::Month),
+            _ => None,
+        }
+    }
+}
+
+/// A bar length like `1m`, `2h`, `3d` or `1M`, validated when parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Timeframe {
+    count: u32,
+    unit: TimeframeUnit,
+}
+
+impl Timeframe {
+    /// The timeframe of the klines every store is built from.
+    pub const MINUTE: Timeframe = Timeframe {
+        count: 1,
+        unit: TimeframeUnit::Minute,
+    };
+
+    pub fn new(count: u32, unit: TimeframeUnit) -> Result<Timeframe> {
+        if count == 0 {
+            return Err(anyhow!("Timeframe must be longer than 0{}", unit.as_str()));
+        }
+        Ok(Timeframe { count, unit })
+    }
+
+    pub fn parse(value: &str) -> Result<Timeframe> {
+        let value = value.trim();
+        let split = value
+            .find(|c: char| !c.is_ascii_digit())
+            .ok_or(anyhow!("Timeframe '{}' has no unit", value))?;
+        let (count, suffix) = value.split_at(split);
+
+        let count = count
+            .parse::<u32>()
+            .map_err(|_| anyhow!("Invalid timeframe: '{}'", value))?;
+        let unit = TimeframeUnit::from_suffix(suffix).ok_or(anyhow!(
+            "Invalid timeframe unit '{}' in '{}'",
+            suffix,
+            value
+        ))?;
+        Timeframe::new(count, unit)
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn unit(&self) -> TimeframeUnit {
+        self.unit
+    }
+
+    pub fn as_millis(&self) -> i64 {
+        self.count as i64 * self.unit.as_millis()
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self.unit {
+            TimeframeUnit::Month => Duration::parse(&format!("{}mo", self.count)),
+            _ => Duration::parse(&self.to_string()),
+        }
+    }
+
+    /// Whether bars of this timeframe can be aggregated into bars of `other`.
+    pub fn divides(&self, other: &Timeframe) -> bool {
+        other.as_millis() % self.as_millis() == 0
+    }
+}
+
+impl fmt::Display for Timeframe {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.count, self.unit.as_str())
+    }
+}
+
+impl FromStr for Timeframe {
+    type Err = Error;
+
+    fn from_str(value: &str) -> Result<Timeframe> {
+        Timeframe::parse(value)
+    }
+}
+
+impl TryFrom<String> for Timeframe {
+    type Error = Error;
+
+    fn try_from(value: String) -> Result<Timeframe> {
+        Timeframe::parse(&value)
+    }
+}
+
+impl From<Timeframe> for String {
+    fn from(timeframe: Timeframe) -> String {
+        timeframe.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WindowSide {
+    #[default]
+    Left,
+    Right,
+}
+
+/// How the 1m bars are grouped into the windows of a larger timeframe.
+/// The defaults give UTC aligned windows `[open, open + tf)` labeled by their start.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ResampleOptions {
+    /// Shifts the window boundaries, e.g. `30m` for 1h bars from half past.
+    pub offset: Option<Timeframe>,
+    /// The boundary that belongs to a window.
+    pub closed: WindowSide,
+    /// The boundary a window is labeled with.
+    pub label: WindowSide,
+    /// The UTC offset day, week and month boundaries are aligned to, e.g. `+08:00`.
+    #[serde(with = "utc_offset")]
+    pub timezone: FixedOffset,
+    pub week_start: Weekday,
+}
+
+impl Default for ResampleOptions {
+    fn default() -> Self {
+        ResampleOptions {
+            offset: None,
+            closed: WindowSide::Left,
+            label: WindowSide::Left,
+            timezone: FixedOffset::east_opt(0).unwrap(),
+            week_start: Weekday::Mon,
+        }
+    }
+}
+
+impl ResampleOptions {
+    /// Name of the timeframe resampled with these options, e.g. `1d-utc+0800`.
+    /// Only the options that differ from the defaults are appended, so stores
+    /// of different variants coexist and the default ones keep their names.
+    pub fn name_for(&self, timeframe: &Timeframe) -> String {
+        let mut parts = vec![timeframe.to_string()];
+        if let Some(offset) = self.offset {
+            parts.push(format!("o{}", offset));
+        }
+        if self.closed == WindowSide::Right {
+            parts.push("cr".to_string());
+        }
+        if self.label == WindowSide::Right {
+            parts.push("lr".to_string());
+        }
+        if self.timezone.local_minus_utc() != 0 {
+            parts.push(format!("utc{}", self.timezone.to_string().replace(':', "")));
+        }
+        if timeframe.unit() == TimeframeUnit::Week && self.week_start != Weekday::Mon {
+            parts.push(self.week_start.to_string().to_lowercase());
+        }
+        parts.join("-")
+    }
+
+    /// The shift of the window boundaries from UTC midnight in milliseconds.
+    pub fn offset_millis(&self) -> i64 {
+        let offset = self.offset.map(|o| o.as_millis()).unwrap_or_default();
+        offset - self.timezone.local_minus_utc() as i64 * 1000
+    }
+}
+
+mod utc_offset {
+    use chrono::FixedOffset;
+    use serde::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(offset: &FixedOffset, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&offset.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<FixedOffset, D::Error> {
+        let value = String::deserialize(d)?;
+        value.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let timeframe = Timeframe::parse("2h").unwrap();
+        assert_eq!(2, timeframe.count());
+        assert_eq!(TimeframeUnit::Hour, timeframe.unit());
+        assert_eq!(7_200_000, timeframe.as_millis());
+        assert_eq!("2h", timeframe.to_string());
+        assert_eq!(Timeframe::MINUTE, "1m".parse().unwrap());
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(Timeframe::parse("").is_err());
+        assert!(Timeframe::parse("15").is_err());
+        assert!(Timeframe::parse("0m").is_err());
+        assert!(Timeframe::parse("3y").is_err());
+        assert!(Timeframe::parse("h1").is_err());
+    }
+
+    #[test]
+    fn test_divides() {
+        let minute = Timeframe::MINUTE;
+        assert!(minute.divides(&Timeframe::parse("3d").unwrap()));
+        assert!(!Timeframe::parse("1h")
+            .unwrap()
+            .divides(&Timeframe::parse("90m").unwrap()));
+        assert!(!minute.divides(&Timeframe::parse("10s").unwrap()));
+        assert!(!Timeframe::parse("1w")
+            .unwrap()
+            .divides(&Timeframe::parse("1M").unwrap()));
+    }
+
+    #[test]
+    fn test_resample_name_for() {
+        let daily = Timeframe::parse("1d").unwrap();
+        let weekly = Timeframe::parse("1w").unwrap();
+        let mut options = ResampleOptions::default();
+        assert_eq!("1d", options.name_for(&daily));
+
+        options.timezone = "+08:00".parse().unwrap();
+        options.week_start = Weekday::Sun;
+        assert_eq!("1d-utc+0800", options.name_for(&daily));
+        assert_eq!("1w-utc+0800-sun", options.name_for(&weekly));
+        assert_eq!(-8 * 3_600_000, options.offset_millis());
+
+        let options: ResampleOptions =
+            toml::from_str("offset = \"30m\"\nclosed = \"right\"\nlabel = \"right\"").unwrap();
+        assert_eq!(
+            "1h-o30m-cr-lr",
+            options.name_for(&Timeframe::parse("1h").unwrap())
+        );
+    }
+}
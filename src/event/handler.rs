@@ -1,31 +1,33 @@
 use tokio::sync::mpsc;
 
 use crate::event::DataEvent;
-use crate::signals::SignalProcessor;
+use crate::signals::{Signal, SignalProcessor};
 
 pub struct EventHandler {
     signal_procs: Vec<Box<dyn SignalProcessor>>,
     receiver: mpsc::Receiver<Option<DataEvent>>,
 }
 
-// impl<S: EventStrat> EventHandler<S> {
-//     pub fn new(strat: S, receiver: mpsc::Receiver<Option<DataEvent>>) -> Self {
-//         Self { strat, receiver }
-//     }
+impl EventHandler {
+    pub fn new(
+        signal_procs: Vec<Box<dyn SignalProcessor>>,
+        receiver: mpsc::Receiver<Option<DataEvent>>,
+    ) -> Self {
+        Self {
+            signal_procs,
+            receiver,
+        }
+    }
 
-//     pub async fn listen(&mut self) {
-//         while let Some(event_opt) = self.receiver.recv().await {
-//             if let Some(event) = event_opt {
-//                 self.on_data(event);
-//             } else {
-//                 println!("All items have been received");
-//                 break;
-//             }
-//         }
-//     }
-
-//     fn on_data(&self, event: DataEvent) {
-//         let signal = self.strat.run(event.data);
-//         // println!("Received signal: {:?}", signal);
-//     }
-// }
+    /// Runs every signal processor on each event until the source is done,
+    /// returning the signals of each processor in the order of the events.
+    pub async fn listen(&mut self) -> Vec<Vec<Signal>> {
+        let mut signals = vec![Vec::new(); self.signal_procs.len()];
+        while let Some(Some(event)) = self.receiver.recv().await {
+            for (signal_proc, signals) in self.signal_procs.iter().zip(signals.iter_mut()) {
+                signals.push(signal_proc.proc(&event.data));
+            }
+        }
+        signals
+    }
+}
@@ -4,6 +4,7 @@ use tokio::sync::mpsc;
 
 use crate::data::config::DataConfig;
 use crate::data::store::DataStore;
+use crate::data::timeframe::Timeframe;
 use crate::event::DataEvent;
 
 #[async_trait]
@@ -13,7 +14,7 @@ pub trait EventSource {
 
 pub struct EventSourceOptions {
     pub symbol: String,
-    pub timeframe: Option<String>,
+    pub timeframe: Timeframe,
 }
 
 pub struct StoreEventSource {
@@ -40,7 +41,7 @@ impl StoreEventSource {
 impl EventSource for StoreEventSource {
     async fn start(&self, lookback: usize) -> Result<()> {
         let symbol = self.options.symbol.clone();
-        let timeframe = self.options.timeframe.clone();
+        let timeframe = self.options.timeframe;
 
         let data = self
             .store
@@ -1,4 +1,6 @@
-use chrono::{DateTime, NaiveDateTime, Utc};
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use polars::prelude::TimeUnit;
 
 pub fn create_utc(year: i32, month: u32, day: u32) -> DateTime<Utc> {
     DateTime::from_utc(
@@ -10,10 +12,76 @@ pub fn create_utc(year: i32, month: u32, day: u32) -> DateTime<Utc> {
     )
 }
 
-pub fn from_timestamp(timestamp: &i64) -> NaiveDateTime {
-    let mut ts = *timestamp;
-    if ts.to_string().len() <= 10 {
-        ts *= 1000;
+/// Parses a UTC date given as `2021-01`, `2021-01-15` or `2021-01-15T08:00:00`.
+pub fn parse_utc(value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S") {
+        return Ok(to_utc(&datetime));
     }
-    NaiveDateTime::from_timestamp_millis(ts).unwrap()
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .or_else(|_| NaiveDate::parse_from_str(&format!("{}-01", value), "%Y-%m-%d"))
+        .map_err(|_| {
+            anyhow!(
+                "Invalid date '{}', expected e.g. 2021-01 or 2021-01-15",
+                value
+            )
+        })?;
+    Ok(to_utc(&date.and_hms_opt(0, 0, 0).unwrap()))
+}
+
+/// Resolution of an epoch timestamp. Binance archives use milliseconds,
+/// spot archives switched to microseconds from 2025 on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EpochUnit {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+}
+
+impl EpochUnit {
+    /// Guesses the unit by magnitude, which is unambiguous for any
+    /// date between 1973 and 5138.
+    pub fn detect(timestamp: i64) -> EpochUnit {
+        match timestamp.unsigned_abs() {
+            ts if ts < 100_000_000_000 => EpochUnit::Seconds,
+            ts if ts < 100_000_000_000_000 => EpochUnit::Milliseconds,
+            ts if ts < 100_000_000_000_000_000 => EpochUnit::Microseconds,
+            _ => EpochUnit::Nanoseconds,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EpochUnit::Seconds => "s",
+            EpochUnit::Milliseconds => "ms",
+            EpochUnit::Microseconds => "us",
+            EpochUnit::Nanoseconds => "ns",
+        }
+    }
+
+    pub fn to_millis(self, timestamp: i64) -> i64 {
+        match self {
+            EpochUnit::Seconds => timestamp * 1_000,
+            EpochUnit::Milliseconds => timestamp,
+            EpochUnit::Microseconds => timestamp / 1_000,
+            EpochUnit::Nanoseconds => timestamp / 1_000_000,
+        }
+    }
+
+    pub fn to_datetime(self, timestamp: i64) -> Option<NaiveDateTime> {
+        DateTime::from_timestamp_millis(self.to_millis(timestamp)).map(|dt| dt.naive_utc())
+    }
+}
+
+pub fn to_utc(datetime: &NaiveDateTime) -> DateTime<Utc> {
+    Utc.from_utc_datetime(datetime)
+}
+
+pub fn from_time_unit(value: i64, unit: TimeUnit) -> NaiveDateTime {
+    let nanos = match unit {
+        TimeUnit::Nanoseconds => value,
+        TimeUnit::Microseconds => value * 1_000,
+        TimeUnit::Milliseconds => value * 1_000_000,
+    };
+    Utc.timestamp_nanos(nanos).naive_utc()
 }
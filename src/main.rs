@@ -2,91 +2,42 @@
 
 use log::LevelFilter as LogLevel;
 use std::io::{Result, Write};
+use std::process::ExitCode;
 
 use chrono::Local as LocalDateTime;
+use clap::Parser;
+use env_logger::Builder as LogBuilder;
 use env_logger::Target as LogTarget;
-use env_logger::{fmt, Builder as LogBuilder};
-use tokio::sync::mpsc;
 
-use data::config::DataConfig;
-use data::provider::{DataProvider, SymbolsProvider};
-use data::store::DataStore;
-use data::{AssetCategory, Symbol};
-use event::handler::EventHandler;
-use event::sources::{EventSource, EventSourceOptions, StoreEventSource};
-use extensions::datetime;
+use cli::Cli;
 
+mod cli;
 mod data;
 mod event;
 mod extensions;
 mod signals;
 mod ta;
 
-const DEFAULT_CHANNEL_SIZE: usize = 100;
-
 #[tokio::main]
-async fn main() {
-    // let (sender, receiver): (Sender<String>, Receiver<String>) = channel();
-
-    let log_handler = LogHandler::new();
-    let log_target = fmt::Target::Pipe(Box::new(log_handler));
-    setup_logger(log_target, LogLevel::Info);
-
-    // all_symbols().await;
-    // sync_test().await;
-    // event_test().await;
-}
-
-async fn all_symbols() {
-    let config = DataConfig::new(AssetCategory::Usdm);
-    let provider = SymbolsProvider::new(config, AssetCategory::Usdm);
-    provider.get().await.unwrap();
-}
-
-async fn sync_test() {
-    let config = DataConfig::new(AssetCategory::Usdm);
-
-    // let symbols_provider = SymbolsProvider::new(config.clone(), AssetCategory::Usdm);
-    // let symbols = symbols_provider.get().await.unwrap();
-
-    let symbols = vec![Symbol {
-        name: "BTCUSDT".to_string(),
-        initdate: datetime::create_utc(2020, 1, 1),
-    }];
-
-    let sync_task = tokio::task::spawn_blocking(move || {
-        let data_store = DataStore::new_arc(config);
-        data_store.sync(symbols);
-    });
-
-    sync_task.await.unwrap();
+async fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let level = if cli.verbose {
+        LogLevel::Debug
+    } else {
+        LogLevel::Info
+    };
+    // Output goes to stdout, so it can be piped
+    setup_logger(LogTarget::Stderr, level);
+
+    match cli::run(cli).await {
+        Ok(code) => code,
+        Err(e) => {
+            log::error!("{:#}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
 
-// async fn event_test() {
-//     let symbol = "BTCUSDT".to_string();
-//     let timeframe = Some("5m".to_string());
-
-//     let (sender, receiver) = mpsc::channel(DEFAULT_CHANNEL_SIZE);
-
-//     let config = DataConfig::new(AssetCategory::Usdm);
-//     let options = EventSourceOptions { symbol, timeframe };
-
-//     let source = StoreEventSource::new(config, options, sender.clone());
-
-//     let strat = EmaCrossStrat::new(10, 20);
-//     let lookback = strat.get_threshold() * 5;
-//     let mut handler = EventHandler::new(strat, receiver);
-
-//     tokio::spawn(async move {
-//         match source.start(lookback).await {
-//             Ok(_) => println!("Done"),
-//             Err(e) => log::error!("Error: {}", e),
-//         }
-//     });
-
-//     handler.listen().await;
-// }
-
 fn setup_logger(target: LogTarget, level: LogLevel) {
     LogBuilder::new()
         .target(target)
@@ -2,7 +2,7 @@ pub mod ema_signals;
 
 use polars::prelude::DataFrame;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Signal {
     Buy,
     Sell,